@@ -0,0 +1,567 @@
+// AsciiMath (http://asciimath.org/) to presentation MathML, so that math doesn't need JavaScript to render.
+use super::html::Escape;
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+	Identifier,
+	Operator,
+	// Operators like sum and lim that take their scripts above and below instead of to the side.
+	UnderOver,
+	Left,
+	Right,
+	// | and || open a group if there's another one to close it, otherwise they're operators.
+	LeftRight,
+	// sqrt, text, abs, floor, ceil and norm
+	Unary,
+	// Accents that go over (hat, vec) or under (ul) their argument.
+	Over,
+	Under,
+	// bb, cc, etc. whose output is the mathvariant.
+	Font,
+	// frac, root, stackrel, overset and underset
+	Binary,
+}
+use Kind::*;
+
+struct Symbol {
+	input: &'static str,
+	output: &'static str,
+	kind: Kind,
+}
+const fn symbol(input: &'static str, output: &'static str, kind: Kind) -> Symbol {
+	Symbol {
+		input,
+		output,
+		kind,
+	}
+}
+
+#[rustfmt::skip]
+const SYMBOLS: &[Symbol] = &[
+	// Greek letters
+	symbol("alpha", "α", Identifier), symbol("beta", "β", Identifier), symbol("gamma", "γ", Identifier),
+	symbol("Gamma", "Γ", Identifier), symbol("delta", "δ", Identifier), symbol("Delta", "Δ", Identifier),
+	symbol("epsilon", "ε", Identifier), symbol("varepsilon", "ɛ", Identifier), symbol("zeta", "ζ", Identifier),
+	symbol("eta", "η", Identifier), symbol("theta", "θ", Identifier), symbol("Theta", "Θ", Identifier),
+	symbol("vartheta", "ϑ", Identifier), symbol("iota", "ι", Identifier), symbol("kappa", "κ", Identifier),
+	symbol("lambda", "λ", Identifier), symbol("Lambda", "Λ", Identifier), symbol("mu", "μ", Identifier),
+	symbol("nu", "ν", Identifier), symbol("xi", "ξ", Identifier), symbol("Xi", "Ξ", Identifier),
+	symbol("pi", "π", Identifier), symbol("Pi", "Π", Identifier), symbol("rho", "ρ", Identifier),
+	symbol("sigma", "σ", Identifier), symbol("Sigma", "Σ", Identifier), symbol("tau", "τ", Identifier),
+	symbol("upsilon", "υ", Identifier), symbol("phi", "ϕ", Identifier), symbol("varphi", "φ", Identifier),
+	symbol("Phi", "Φ", Identifier), symbol("chi", "χ", Identifier), symbol("psi", "ψ", Identifier),
+	symbol("Psi", "Ψ", Identifier), symbol("omega", "ω", Identifier), symbol("Omega", "Ω", Identifier),
+	// Functions
+	symbol("sin", "sin", Identifier), symbol("cos", "cos", Identifier), symbol("tan", "tan", Identifier),
+	symbol("sec", "sec", Identifier), symbol("csc", "csc", Identifier), symbol("cot", "cot", Identifier),
+	symbol("sinh", "sinh", Identifier), symbol("cosh", "cosh", Identifier), symbol("tanh", "tanh", Identifier),
+	symbol("log", "log", Identifier), symbol("ln", "ln", Identifier), symbol("exp", "exp", Identifier),
+	symbol("det", "det", Identifier), symbol("dim", "dim", Identifier), symbol("gcd", "gcd", Identifier),
+	symbol("lcm", "lcm", Identifier), symbol("mod", "mod", Operator),
+	symbol("lim", "lim", UnderOver), symbol("min", "min", UnderOver), symbol("max", "max", UnderOver),
+	// Operators
+	symbol("+", "+", Operator), symbol("-", "−", Operator), symbol("*", "⋅", Operator),
+	symbol("**", "∗", Operator), symbol("***", "⋆", Operator), symbol("/", "/", Operator),
+	symbol("//", "/", Operator), symbol("\\\\", "\\", Operator), symbol("setminus", "\\", Operator),
+	symbol("xx", "×", Operator), symbol("-:", "÷", Operator), symbol("@", "∘", Operator),
+	symbol("o+", "⊕", Operator), symbol("ox", "⊗", Operator), symbol("o.", "⊙", Operator),
+	symbol("^^", "∧", Operator), symbol("vv", "∨", Operator), symbol("nn", "∩", Operator),
+	symbol("uu", "∪", Operator), symbol("sum", "∑", UnderOver), symbol("prod", "∏", UnderOver),
+	symbol("^^^", "⋀", UnderOver), symbol("vvv", "⋁", UnderOver), symbol("nnn", "⋂", UnderOver),
+	symbol("uuu", "⋃", UnderOver), symbol("_", "_", Operator), symbol("^", "^", Operator),
+	symbol(",", ",", Operator), symbol(".", ".", Operator), symbol("!", "!", Operator),
+	// Relations
+	symbol("=", "=", Operator), symbol("!=", "≠", Operator), symbol(":=", ":=", Operator),
+	symbol("<", "&lt;", Operator), symbol("lt", "&lt;", Operator), symbol(">", "&gt;", Operator),
+	symbol("gt", "&gt;", Operator), symbol("<=", "≤", Operator), symbol("le", "≤", Operator),
+	symbol(">=", "≥", Operator), symbol("ge", "≥", Operator), symbol("-<", "≺", Operator),
+	symbol(">-", "≻", Operator), symbol("in", "∈", Operator), symbol("!in", "∉", Operator),
+	symbol("sub", "⊂", Operator), symbol("sup", "⊃", Operator), symbol("sube", "⊆", Operator),
+	symbol("supe", "⊇", Operator), symbol("-=", "≡", Operator), symbol("~=", "≅", Operator),
+	symbol("~~", "≈", Operator), symbol("prop", "∝", Operator),
+	// Logic
+	symbol("not", "¬", Operator), symbol("=>", "⇒", Operator), symbol("<=>", "⇔", Operator),
+	symbol("iff", "⇔", Operator), symbol("AA", "∀", Operator), symbol("EE", "∃", Operator),
+	symbol("_|_", "⊥", Operator), symbol("TT", "⊤", Operator), symbol("|--", "⊢", Operator),
+	symbol("|==", "⊨", Operator),
+	// Miscellaneous
+	symbol("int", "∫", Operator), symbol("oint", "∮", Operator), symbol("del", "∂", Operator),
+	symbol("grad", "∇", Operator), symbol("+-", "±", Operator), symbol("-+", "∓", Operator),
+	symbol("O/", "∅", Operator), symbol("oo", "∞", Operator), symbol("aleph", "ℵ", Operator),
+	symbol("/_", "∠", Operator), symbol(":.", "∴", Operator), symbol("...", "...", Operator),
+	symbol("cdots", "⋯", Operator), symbol("vdots", "⋮", Operator), symbol("ddots", "⋱", Operator),
+	symbol("CC", "ℂ", Identifier), symbol("NN", "ℕ", Identifier), symbol("QQ", "ℚ", Identifier),
+	symbol("RR", "ℝ", Identifier), symbol("ZZ", "ℤ", Identifier),
+	// Arrows
+	symbol("uarr", "↑", Operator), symbol("darr", "↓", Operator), symbol("rarr", "→", Operator),
+	symbol("->", "→", Operator), symbol("to", "→", Operator), symbol("|->", "↦", Operator),
+	symbol("larr", "←", Operator), symbol("harr", "↔", Operator), symbol("rArr", "⇒", Operator),
+	symbol("lArr", "⇐", Operator), symbol("hArr", "⇔", Operator),
+	// Brackets, where an empty output is invisible.
+	symbol("(", "(", Left), symbol(")", ")", Right), symbol("[", "[", Left),
+	symbol("]", "]", Right), symbol("{", "{", Left), symbol("}", "}", Right),
+	symbol("(:", "⟨", Left), symbol(":)", "⟩", Right), symbol("<<", "⟨", Left),
+	symbol(">>", "⟩", Right), symbol("{:", "", Left), symbol(":}", "", Right),
+	symbol("|__", "⌊", Left), symbol("__|", "⌋", Right), symbol("|~", "⌈", Left),
+	symbol("~|", "⌉", Right), symbol("|", "|", LeftRight), symbol("||", "∥", LeftRight),
+	// Functions of their arguments
+	symbol("sqrt", "", Unary), symbol("text", "", Unary), symbol("abs", "", Unary),
+	symbol("floor", "", Unary), symbol("ceil", "", Unary), symbol("norm", "", Unary),
+	symbol("hat", "^", Over), symbol("bar", "¯", Over), symbol("vec", "→", Over),
+	symbol("dot", ".", Over), symbol("ddot", "..", Over), symbol("tilde", "~", Over),
+	symbol("ul", "̲", Under),
+	symbol("bb", "bold", Font), symbol("bbb", "double-struck", Font), symbol("cc", "script", Font),
+	symbol("tt", "monospace", Font), symbol("fr", "fraktur", Font), symbol("sf", "sans-serif", Font),
+	symbol("frac", "", Binary), symbol("root", "", Binary), symbol("stackrel", "", Binary),
+	symbol("overset", "", Binary), symbol("underset", "", Binary),
+];
+
+#[derive(Debug)]
+enum Node {
+	// <mi>, <mo>, <mn> or <mtext> and its (escaped) text
+	Token(&'static str, String),
+	Row(Vec<Node>),
+	// Brackets are kept separate so that scripts and fractions can take them off: (a+b)/2 is a fraction of a+b
+	Bracketed(&'static str, Vec<Node>, &'static str),
+	// An element, its attributes and its children
+	Element(&'static str, &'static str, Vec<Node>),
+	// Rows of cells
+	Table(&'static str, Vec<Vec<Vec<Node>>>),
+}
+fn strip(node: Node) -> Node {
+	match node {
+		Node::Bracketed(_, children, _) => Node::Row(children),
+		node => node,
+	}
+}
+fn is_comma(node: &Node) -> bool {
+	matches!(node, Node::Token("mo", s) if s == ",")
+}
+fn columns(cells: &[Node]) -> usize {
+	cells.iter().filter(|n| is_comma(n)).count() + 1
+}
+
+// [(a, b), (c, d)] is a matrix: two or more rows in the same kind of brackets, separated by commas and with the same number of columns.
+fn matrix(left: &'static str, children: Vec<Node>, right: &'static str) -> Node {
+	let is_matrix = children.len() >= 3
+		&& children.len() % 2 == 1
+		&& match &children[0] {
+			Node::Bracketed(l, first, r) if *l == "(" || *l == "[" => {
+				children.iter().enumerate().all(|(i, node)| match node {
+					Node::Bracketed(l2, cells, r2) if i % 2 == 0 => {
+						l2 == l && r2 == r && columns(cells) == columns(first)
+					}
+					node => i % 2 == 1 && is_comma(node),
+				})
+			}
+			_ => false,
+		};
+	if !is_matrix {
+		return Node::Bracketed(left, children, right);
+	}
+	let rows = children
+		.into_iter()
+		.step_by(2)
+		.map(|row| {
+			let mut cells = vec![Vec::new()];
+			if let Node::Bracketed(_, nodes, _) = row {
+				for node in nodes {
+					if is_comma(&node) {
+						cells.push(Vec::new());
+					} else {
+						cells.last_mut().unwrap().push(node);
+					}
+				}
+			}
+			cells
+		})
+		.collect();
+	// A brace without a closing bracket is a piecewise definition, which reads better aligned left.
+	let attributes = if left == "{" && right.is_empty() {
+		r#" columnalign="left""#
+	} else {
+		""
+	};
+	Node::Bracketed(left, vec![Node::Table(attributes, rows)], right)
+}
+
+enum Token<'a> {
+	Symbol(&'static Symbol),
+	Number(&'a str),
+	Text(&'a str),
+	Other(&'a str),
+}
+
+struct Parser<'a> {
+	// Always starts at a token, never whitespace.
+	rest: &'a str,
+}
+impl<'a> Parser<'a> {
+	// The next token and how long it is.
+	fn peek(&self) -> Option<(Token<'a>, usize)> {
+		let rest = self.rest;
+		let c = rest.chars().next()?;
+		if let Some(symbol) = SYMBOLS
+			.iter()
+			.filter(|s| rest.starts_with(s.input))
+			.max_by_key(|s| s.input.len())
+		{
+			return Some((Token::Symbol(symbol), symbol.input.len()));
+		}
+		let digits = |s: &str| s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+		if c.is_ascii_digit() {
+			let mut len = digits(rest);
+			if rest[len..].starts_with('.') && digits(&rest[len + 1..]) > 0 {
+				len += 1 + digits(&rest[len + 1..]);
+			}
+			return Some((Token::Number(&rest[..len]), len));
+		}
+		if c == '"' {
+			if let Some(end) = rest[1..].find('"') {
+				return Some((Token::Text(&rest[1..end + 1]), end + 2));
+			}
+		}
+		Some((Token::Other(&rest[..c.len_utf8()]), c.len_utf8()))
+	}
+	fn bump(&mut self, len: usize) {
+		self.rest = self.rest[len..].trim_start();
+	}
+	fn next(&mut self) -> Option<Token<'a>> {
+		let (token, len) = self.peek()?;
+		self.bump(len);
+		Some(token)
+	}
+	// Take the next token if it's the symbol s.
+	fn eat(&mut self, s: &str) -> bool {
+		match self.peek() {
+			Some((Token::Symbol(symbol), len)) if symbol.input == s => {
+				self.bump(len);
+				true
+			}
+			_ => false,
+		}
+	}
+	// E: intermediates, with / making a fraction of the ones on either side. Stops after a right bracket (or the closing | or ||) and returns it, or None at the end of the input.
+	fn parse_expression(&mut self, closing: Option<&str>) -> (Vec<Node>, Option<&'static str>) {
+		let mut nodes = Vec::new();
+		loop {
+			match self.peek() {
+				None => return (nodes, None),
+				Some((Token::Symbol(s), len))
+					if s.kind == Right || (s.kind == LeftRight && closing == Some(s.input)) =>
+				{
+					self.bump(len);
+					return (nodes, Some(s.output));
+				}
+				_ => {}
+			}
+			let mut node = self.parse_intermediate();
+			if self.eat("/") {
+				let denominator = strip(self.parse_intermediate());
+				node = Node::Element("mfrac", "", vec![strip(node), denominator]);
+			}
+			nodes.push(node);
+		}
+	}
+	// I: a simple expression with an optional subscript and superscript.
+	fn parse_intermediate(&mut self) -> Node {
+		let under_over = matches!(self.peek(), Some((Token::Symbol(s), _)) if s.kind == UnderOver);
+		let (sub, sup, subsup) = if under_over {
+			("munder", "mover", "munderover")
+		} else {
+			("msub", "msup", "msubsup")
+		};
+		let base = self.parse_simple();
+		if self.eat("_") {
+			let script = strip(self.parse_simple());
+			if self.eat("^") {
+				let sup = strip(self.parse_simple());
+				Node::Element(subsup, "", vec![base, script, sup])
+			} else {
+				Node::Element(sub, "", vec![base, script])
+			}
+		} else if self.eat("^") {
+			Node::Element(sup, "", vec![base, strip(self.parse_simple())])
+		} else {
+			base
+		}
+	}
+	// S: a token, a bracketed expression, or a function and its arguments.
+	fn parse_simple(&mut self) -> Node {
+		let symbol = match self.next() {
+			None => return Node::Row(Vec::new()),
+			Some(Token::Number(n)) => return Node::Token("mn", n.to_string()),
+			Some(Token::Text(t)) => return Node::Token("mtext", Escape(t).to_string()),
+			Some(Token::Other(c)) if c.chars().all(char::is_alphabetic) => {
+				return Node::Token("mi", c.to_string())
+			}
+			Some(Token::Other(c)) => return Node::Token("mo", Escape(c).to_string()),
+			Some(Token::Symbol(symbol)) => symbol,
+		};
+		match symbol.kind {
+			Left => self.parse_brackets(symbol.output, None),
+			LeftRight if self.rest.contains(symbol.input) => {
+				self.parse_brackets(symbol.output, Some(symbol.input))
+			}
+			Unary => self.parse_unary(symbol.input),
+			Over => Node::Element(
+				"mover",
+				r#" accent="true""#,
+				vec![
+					strip(self.parse_simple()),
+					Node::Token("mo", symbol.output.to_string()),
+				],
+			),
+			Under => Node::Element(
+				"munder",
+				r#" accentunder="true""#,
+				vec![
+					strip(self.parse_simple()),
+					Node::Token("mo", symbol.output.to_string()),
+				],
+			),
+			Font => {
+				let arg = strip(self.parse_simple());
+				let attributes = match symbol.output {
+					"bold" => r#" mathvariant="bold""#,
+					"double-struck" => r#" mathvariant="double-struck""#,
+					"script" => r#" mathvariant="script""#,
+					"monospace" => r#" mathvariant="monospace""#,
+					"fraktur" => r#" mathvariant="fraktur""#,
+					_ => r#" mathvariant="sans-serif""#,
+				};
+				Node::Element("mstyle", attributes, vec![arg])
+			}
+			Binary => {
+				let a = strip(self.parse_simple());
+				let b = strip(self.parse_simple());
+				match symbol.input {
+					"frac" => Node::Element("mfrac", "", vec![a, b]),
+					"root" => Node::Element("mroot", "", vec![b, a]),
+					"underset" => Node::Element("munder", "", vec![b, a]),
+					_ => Node::Element("mover", "", vec![b, a]),
+				}
+			}
+			Identifier => Node::Token("mi", symbol.output.to_string()),
+			Operator | UnderOver | Right | LeftRight => {
+				Node::Token("mo", symbol.output.to_string())
+			}
+		}
+	}
+	fn parse_brackets(&mut self, left: &'static str, closing: Option<&str>) -> Node {
+		let (children, right) = self.parse_expression(closing);
+		matrix(left, children, right.unwrap_or(""))
+	}
+	fn parse_unary(&mut self, name: &str) -> Node {
+		// text(...) is taken as is, without parsing what's inside.
+		if name == "text" {
+			for (open, close) in [('(', ')'), ('[', ']'), ('{', '}')] {
+				if let Some(end) = self.rest.strip_prefix(open).and_then(|r| r.find(close)) {
+					let text = Escape(&self.rest[1..end + 1]).to_string();
+					self.bump(end + 2);
+					return Node::Token("mtext", text);
+				}
+			}
+		}
+		let arg = strip(self.parse_simple());
+		match name {
+			"sqrt" => Node::Element("msqrt", "", vec![arg]),
+			"abs" => Node::Bracketed("|", vec![arg], "|"),
+			"floor" => Node::Bracketed("⌊", vec![arg], "⌋"),
+			"ceil" => Node::Bracketed("⌈", vec![arg], "⌉"),
+			"norm" => Node::Bracketed("∥", vec![arg], "∥"),
+			// text without brackets
+			_ => arg,
+		}
+	}
+}
+
+fn render(o: &mut String, node: &Node) {
+	match node {
+		Node::Token(tag, text) => write!(o, "<{0}>{1}</{0}>", tag, text).unwrap(),
+		Node::Row(children) if children.len() == 1 => render(o, &children[0]),
+		Node::Row(children) => {
+			o.push_str("<mrow>");
+			children.iter().for_each(|c| render(o, c));
+			o.push_str("</mrow>");
+		}
+		Node::Bracketed(left, children, right) => {
+			o.push_str("<mrow>");
+			if !left.is_empty() {
+				write!(o, "<mo>{}</mo>", left).unwrap();
+			}
+			children.iter().for_each(|c| render(o, c));
+			if !right.is_empty() {
+				write!(o, "<mo>{}</mo>", right).unwrap();
+			}
+			o.push_str("</mrow>");
+		}
+		Node::Element(tag, attributes, children) => {
+			write!(o, "<{}{}>", tag, attributes).unwrap();
+			children.iter().for_each(|c| render(o, c));
+			write!(o, "</{}>", tag).unwrap();
+		}
+		Node::Table(attributes, rows) => {
+			write!(o, "<mtable{}>", attributes).unwrap();
+			for row in rows {
+				o.push_str("<mtr>");
+				for cell in row {
+					o.push_str("<mtd>");
+					cell.iter().for_each(|c| render(o, c));
+					o.push_str("</mtd>");
+				}
+				o.push_str("</mtr>");
+			}
+			o.push_str("</mtable>");
+		}
+	}
+}
+
+/// Convert AsciiMath to a <math> element. AsciiMath can't be invalid, anything that isn't understood is shown as is.
+pub fn to_mathml(input: &str) -> String {
+	let mut parser = Parser {
+		rest: input.trim_start(),
+	};
+	let mut o = String::from(r#"<math xmlns="http://www.w3.org/1998/Math/MathML">"#);
+	loop {
+		let (nodes, right) = parser.parse_expression(None);
+		nodes.iter().for_each(|n| render(&mut o, n));
+		match right {
+			// A right bracket that wasn't opened.
+			Some(right) => write!(o, "<mo>{}</mo>", right).unwrap(),
+			None => break,
+		}
+	}
+	o.push_str("</math>");
+	o
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[derive(Debug, PartialEq)]
+	enum Xml {
+		Element(String, Vec<Xml>),
+		Text(String),
+	}
+	// Just enough of an XML parser for MathML, without attributes or the whitespace between elements.
+	fn parse_xml(s: &mut &str) -> Vec<Xml> {
+		let mut nodes = Vec::new();
+		loop {
+			let text_end = s.find('<').unwrap_or(s.len());
+			let text = s[..text_end].trim();
+			if !text.is_empty() {
+				nodes.push(Xml::Text(text.replace("&lt;", "<").replace("&gt;", ">")));
+			}
+			*s = &s[text_end..];
+			if s.is_empty() || s.starts_with("</") {
+				return nodes;
+			}
+			let tag_end = s.find('>').unwrap();
+			let tag = &s[1..tag_end];
+			let name = tag.split_whitespace().next().unwrap().to_string();
+			*s = &s[tag_end + 1..];
+			if tag.ends_with('/') {
+				nodes.push(Xml::Element(name, Vec::new()));
+				continue;
+			}
+			let children = parse_xml(s);
+			let close = format!("</{}>", name);
+			*s = s.strip_prefix(close.as_str()).expect(&close);
+			nodes.push(Xml::Element(name, children));
+		}
+	}
+	// Different ways of writing the same MathML: rows are flattened into the rows that they're in, a row of one thing is just that thing, and annotations are left out.
+	fn normalize(node: Xml) -> Xml {
+		let is_row = |name: &str| ["math", "mrow", "semantics", "mtd"].contains(&name);
+		match node {
+			Xml::Text(t) => Xml::Text(t),
+			Xml::Element(name, children) => {
+				let mut flat = Vec::new();
+				for child in children.into_iter().map(normalize) {
+					match child {
+						Xml::Element(child, _) if child == "annotation" => {}
+						Xml::Element(child, grandchildren) if is_row(&name) && is_row(&child) => {
+							flat.extend(grandchildren)
+						}
+						child => flat.push(child),
+					}
+				}
+				if flat.len() == 1 && (name == "mrow" || name == "semantics") {
+					return flat.pop().unwrap();
+				}
+				Xml::Element(name, flat)
+			}
+		}
+	}
+	fn mathml(s: &str) -> Xml {
+		let mut s = s;
+		let mut nodes = parse_xml(&mut s);
+		assert!(s.is_empty(), "{}", s);
+		assert_eq!(nodes.len(), 1);
+		normalize(nodes.pop().unwrap())
+	}
+
+	#[test]
+	fn test_eq1() {
+		assert_eq!(
+			mathml(&to_mathml("Z_n = (Z_(n - 1))^2 + C")),
+			mathml(include_str!("../content/projects/Mandelbrot/eq1.mml"))
+		);
+		assert_ne!(
+			mathml(&to_mathml("Z_n = (Z_(n + 1))^2 + C")),
+			mathml(include_str!("../content/projects/Mandelbrot/eq1.mml"))
+		);
+	}
+
+	#[test]
+	fn test_asciimath() {
+		let math = |s: &str| {
+			let mathml = to_mathml(s);
+			mathml
+				.strip_prefix(r#"<math xmlns="http://www.w3.org/1998/Math/MathML">"#)
+				.and_then(|m| m.strip_suffix("</math>"))
+				.unwrap()
+				.to_string()
+		};
+		// Fractions take the brackets off of their numerator and denominator.
+		assert_eq!(
+			math("(a+b)/2 = frac 1 x"),
+			"<mfrac><mrow><mi>a</mi><mo>+</mo><mi>b</mi></mrow><mn>2</mn></mfrac><mo>=</mo><mfrac><mn>1</mn><mi>x</mi></mfrac>"
+		);
+		assert_eq!(
+			math("sum_(i=1)^n i^2"),
+			"<munderover><mo>∑</mo><mrow><mi>i</mi><mo>=</mo><mn>1</mn></mrow><mi>n</mi></munderover><msup><mi>i</mi><mn>2</mn></msup>"
+		);
+		assert_eq!(
+			math("alpha_0 <= sqrt(2pi) xx 3.14"),
+			"<msub><mi>α</mi><mn>0</mn></msub><mo>≤</mo><msqrt><mrow><mn>2</mn><mi>π</mi></mrow></msqrt><mo>×</mo><mn>3.14</mn>"
+		);
+		assert_eq!(
+			math("[(1, 0), (0, 1)]"),
+			"<mrow><mo>[</mo><mtable><mtr><mtd><mn>1</mn></mtd><mtd><mn>0</mn></mtd></mtr><mtr><mtd><mn>0</mn></mtd><mtd><mn>1</mn></mtd></mtr></mtable><mo>]</mo></mrow>"
+		);
+		// Rows with different numbers of columns aren't a matrix.
+		assert_eq!(
+			math("((1), (0, 1))"),
+			"<mrow><mo>(</mo><mrow><mo>(</mo><mn>1</mn><mo>)</mo></mrow><mo>,</mo><mrow><mo>(</mo><mn>0</mn><mo>,</mo><mn>1</mn><mo>)</mo></mrow><mo>)</mo></mrow>"
+		);
+		// The sequence in content/projects/Mandelbrot
+		assert_eq!(
+			math("{[Z_n = {Z_{n - 1}}^2 + C], [Z_0 = C] :}"),
+			"<mrow><mo>{</mo><mtable columnalign=\"left\"><mtr><mtd><msub><mi>Z</mi><mi>n</mi></msub><mo>=</mo><msup><mrow><mo>{</mo><msub><mi>Z</mi><mrow><mi>n</mi><mo>−</mo><mn>1</mn></mrow></msub><mo>}</mo></mrow><mn>2</mn></msup><mo>+</mo><mi>C</mi></mtd></mtr><mtr><mtd><msub><mi>Z</mi><mn>0</mn></msub><mo>=</mo><mi>C</mi></mtd></mtr></mtable></mrow>"
+		);
+		assert_eq!(
+			math("|x| < 1 text(if <b>) \"and\" hat x"),
+			"<mrow><mo>|</mo><mi>x</mi><mo>|</mo></mrow><mo>&lt;</mo><mn>1</mn><mtext>if &lt;b&gt;</mtext><mtext>and</mtext><mover accent=\"true\"><mi>x</mi><mo>^</mo></mover>"
+		);
+		// Unmatched brackets and unknown characters are still shown.
+		assert_eq!(
+			math("a) (b ?"),
+			"<mi>a</mi><mo>)</mo><mrow><mo>(</mo><mi>b</mi><mo>?</mo></mrow>"
+		);
+		assert_eq!(math(""), "");
+	}
+}
@@ -0,0 +1,324 @@
+use super::date::Date;
+use super::Entry;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Change this when the format, or what goes into the hashes, changes so that old caches are ignored.
+const VERSION: &str = "gen-cache 2";
+
+/// FNV-1a, which unlike std's DefaultHasher doesn't change from one version of Rust to the next.
+pub struct Fnv(u64);
+impl Default for Fnv {
+	fn default() -> Self {
+		Self(0xcbf29ce484222325)
+	}
+}
+impl Hasher for Fnv {
+	fn finish(&self) -> u64 {
+		self.0
+	}
+	fn write(&mut self, bytes: &[u8]) {
+		for b in bytes {
+			self.0 ^= *b as u64;
+			self.0 = self.0.wrapping_mul(0x100000001b3);
+		}
+	}
+}
+
+pub fn hash(value: &impl Hash) -> u64 {
+	let mut h = Fnv::default();
+	value.hash(&mut h);
+	h.finish()
+}
+
+/// Hash the names and contents of every file in dir and its subdirectories, so that adding, removing or changing any of them changes the hash.
+pub fn hash_dir(h: &mut Fnv, dir: &Path) -> io::Result<()> {
+	if !dir.is_dir() {
+		return Ok(());
+	}
+	let mut paths = fs::read_dir(dir)?
+		.map(|e| e.map(|e| e.path()))
+		.collect::<io::Result<Vec<_>>>()?;
+	paths.sort();
+	for path in paths {
+		if path.is_dir() {
+			hash_dir(h, &path)?;
+		} else {
+			path.hash(h);
+			fs::read(&path)?.hash(h);
+		}
+	}
+	Ok(())
+}
+
+fn escape(s: &str) -> String {
+	s.replace('\\', "\\\\")
+		.replace('\n', "\\n")
+		.replace('\r', "\\r")
+}
+fn unescape(s: &str) -> String {
+	let mut out = String::new();
+	let mut chars = s.chars();
+	while let Some(c) = chars.next() {
+		match (c, chars.clone().next()) {
+			('\\', Some('n')) => out.push('\n'),
+			('\\', Some('r')) => out.push('\r'),
+			('\\', Some('\\')) => out.push('\\'),
+			_ => {
+				out.push(c);
+				continue;
+			}
+		}
+		chars.next();
+	}
+	out
+}
+
+/// What the last build read and wrote. Outputs are keyed by the hash of everything they were made from, so a build can skip an output whose key hasn't changed.
+#[derive(Debug, Default, PartialEq)]
+pub struct Cache {
+	// The hash of what every output depends on: the config, the templates, etc. If it's changed then nothing in the cache can be used.
+	pub site: u64,
+	// Source -> hash of its contents, and the page it was rendered to
+	pages: BTreeMap<PathBuf, (u64, Entry)>,
+	// Output -> hash of what it was made from
+	outputs: BTreeMap<PathBuf, u64>,
+}
+impl Cache {
+	pub fn new(site: u64) -> Self {
+		Self {
+			site,
+			..Self::default()
+		}
+	}
+	/// The last build's cache, or an empty one if there isn't one, it can't be read, or it was for a different site hash.
+	pub fn load(path: &Path, site: u64) -> Self {
+		fs::read_to_string(path)
+			.ok()
+			.and_then(|s| Self::parse(&s))
+			.filter(|c| c.site == site)
+			.unwrap_or_else(|| Self::new(site))
+	}
+	pub fn save(&self, path: &Path) -> io::Result<()> {
+		fs::write(path, self.serialize())
+	}
+
+	/// Whether the last build made output from something with this key, and it's still there.
+	pub fn is_fresh(&self, output: &Path, key: u64) -> bool {
+		self.outputs.get(output) == Some(&key) && output.is_file()
+	}
+	/// The page that the last build rendered to output from a source with this key.
+	pub fn page(&self, source: &Path, key: u64, output: &Path) -> Option<&Entry> {
+		match self.pages.get(source) {
+			Some((k, entry)) if *k == key && self.is_fresh(output, key) => Some(entry),
+			_ => None,
+		}
+	}
+	pub fn output_count(&self) -> usize {
+		self.outputs.len()
+	}
+	pub fn add_output(&mut self, output: &Path, key: u64) {
+		self.outputs.insert(output.to_path_buf(), key);
+	}
+	pub fn add_page(&mut self, source: &Path, key: u64, output: &Path, entry: Entry) {
+		self.pages.insert(source.to_path_buf(), (key, entry));
+		self.add_output(output, key);
+	}
+	/// Keep what last wrote to output, for when it couldn't be made this time. The old key doesn't match the new inputs, so the next build tries again.
+	pub fn keep_output(&mut self, last: &Cache, output: &Path) {
+		if let Some(key) = last.outputs.get(output) {
+			self.add_output(output, *key);
+		}
+	}
+	/// Keep the page that last rendered from source, if its output is still there.
+	pub fn keep_page(&mut self, last: &Cache, source: &Path, output: &Path) -> Option<Entry> {
+		let (key, entry) = last.pages.get(source)?;
+		if !output.is_file() {
+			return None;
+		}
+		self.pages
+			.insert(source.to_path_buf(), (*key, entry.clone()));
+		self.keep_output(last, output);
+		Some(entry.clone())
+	}
+
+	/// Delete what the last build wrote that next didn't, and any directories in output_dir that leaves empty. Returns the number of files deleted.
+	pub fn remove_stale(&self, next: &Cache, output_dir: &Path) -> io::Result<usize> {
+		let mut removed = 0;
+		for path in self.outputs.keys() {
+			if next.outputs.contains_key(path) {
+				continue;
+			}
+			match fs::remove_file(path) {
+				Ok(()) => removed += 1,
+				Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+				Err(e) => return Err(e),
+			}
+			// remove_dir fails on directories that aren't empty, which is what stops this.
+			let mut dir = path.parent();
+			while let Some(d) = dir.filter(|d| d.starts_with(output_dir) && *d != output_dir) {
+				if fs::remove_dir(d).is_err() {
+					break;
+				}
+				dir = d.parent();
+			}
+		}
+		Ok(removed)
+	}
+
+	// One record per line, and a page's fields on indented lines after it.
+	fn serialize(&self) -> String {
+		let mut s = format!("{}\nsite {:016x}\n", VERSION, self.site);
+		for (path, key) in &self.outputs {
+			writeln!(s, "output {:016x} {}", key, escape(&path.to_string_lossy())).unwrap();
+		}
+		for (path, (key, e)) in &self.pages {
+			writeln!(s, "page {:016x} {}", key, escape(&path.to_string_lossy())).unwrap();
+			let mut field = |name: &str, value: &str| {
+				writeln!(s, "\t{} {}", name, escape(value)).unwrap();
+			};
+			field("title", &e.title);
+			field("url", &e.url);
+			if let Some(date) = e.date {
+				field("date", &date.to_string());
+			}
+			if let Some(updated) = e.updated {
+				field("updated", &updated.to_string());
+			}
+			if let Some(weight) = e.weight {
+				field("weight", &weight.to_string());
+			}
+			field("description", &e.description);
+			field("reading_time", &e.reading_time.to_string());
+			for tag in &e.tags {
+				field("tag", tag);
+			}
+			for (name, email) in &e.authors {
+				field("author", name);
+				if let Some(email) = email {
+					field("email", email);
+				}
+			}
+			field("draft", &e.draft.to_string());
+			field("content", &e.content);
+			for image in &e.images {
+				field("image", image);
+			}
+		}
+		s
+	}
+	fn parse(s: &str) -> Option<Self> {
+		let mut lines = s.lines();
+		if lines.next()? != VERSION {
+			return None;
+		}
+		let site = u64::from_str_radix(lines.next()?.strip_prefix("site ")?, 16).ok()?;
+		let mut cache = Self::new(site);
+		let mut page: Option<(PathBuf, u64, Entry)> = None;
+		for line in lines {
+			if let Some(field) = line.strip_prefix('\t') {
+				let e = &mut page.as_mut()?.2;
+				let (name, value) = field.split_once(' ')?;
+				let value = unescape(value);
+				match name {
+					"title" => e.title = value,
+					"url" => e.url = value,
+					"date" => e.date = Some(Date::parse(&value).ok()?),
+					"updated" => e.updated = Some(Date::parse(&value).ok()?),
+					"weight" => e.weight = Some(value.parse().ok()?),
+					"description" => e.description = value,
+					"reading_time" => e.reading_time = value.parse().ok()?,
+					"tag" => e.tags.push(value),
+					"author" => e.authors.push((value, None)),
+					"email" => e.authors.last_mut()?.1 = Some(value),
+					"draft" => e.draft = value.parse().ok()?,
+					"content" => e.content = value,
+					"image" => e.images.push(value),
+					_ => return None,
+				}
+				continue;
+			}
+			if let Some((path, key, entry)) = page.take() {
+				cache.pages.insert(path, (key, entry));
+			}
+			let (kind, rest) = line.split_once(' ')?;
+			let (key, path) = rest.split_once(' ')?;
+			let key = u64::from_str_radix(key, 16).ok()?;
+			let path = PathBuf::from(unescape(path));
+			match kind {
+				"output" => {
+					cache.outputs.insert(path, key);
+				}
+				"page" => page = Some((path, key, Entry::default())),
+				_ => return None,
+			}
+		}
+		if let Some((path, key, entry)) = page {
+			cache.pages.insert(path, (key, entry));
+		}
+		Some(cache)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_cache() {
+		let mut cache = Cache::new(hash(&"site"));
+		let entry = Entry {
+			title: "A \\ B".to_string(),
+			url: "/blog/a/".to_string(),
+			date: Some(Date::parse("2020-03-27T20:18:42Z").unwrap()),
+			weight: Some(-1),
+			description: "Two\r\nlines\r".to_string(),
+			reading_time: 3,
+			tags: vec!["rust".to_string(), "web".to_string()],
+			authors: vec![
+				("Me".to_string(), Some("me@example.com".to_string())),
+				("You".to_string(), None),
+			],
+			content: "<p>Hi</p>\n".to_string(),
+			images: vec!["blog/a/diagram.webp".to_string()],
+			..Entry::default()
+		};
+		cache.add_page(
+			Path::new("content/blog/a.md"),
+			hash(&"contents"),
+			Path::new("public/blog/a/index.html"),
+			entry,
+		);
+		cache.add_output(Path::new("public/blog/a b.png"), 7);
+		assert_eq!(Cache::parse(&cache.serialize()), Some(cache));
+
+		assert_eq!(Cache::parse("gen-cache 0\nsite 0\n"), None);
+		assert_eq!(Cache::parse("gen-cache 2\nsite 0\n\ttitle orphan\n"), None);
+		assert_ne!(hash(&("a", "bc")), hash(&("ab", "c")));
+	}
+
+	#[test]
+	fn test_remove_stale() {
+		let dir = std::env::temp_dir().join(format!("gen-cache-test-{}", std::process::id()));
+		let (kept, stale) = (dir.join("kept.html"), dir.join("old/post/index.html"));
+		fs::create_dir_all(stale.parent().unwrap()).unwrap();
+		fs::write(&kept, "").unwrap();
+		fs::write(&stale, "").unwrap();
+
+		let mut last = Cache::new(1);
+		last.add_output(&kept, 1);
+		last.add_output(&stale, 2);
+		assert!(last.is_fresh(&kept, 1));
+		assert!(!last.is_fresh(&kept, 2));
+		let mut next = Cache::new(1);
+		next.add_output(&kept, 1);
+		assert_eq!(last.remove_stale(&next, &dir).unwrap(), 1);
+		assert!(kept.exists());
+		assert!(!dir.join("old").exists());
+		fs::remove_dir_all(&dir).unwrap();
+	}
+}
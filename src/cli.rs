@@ -0,0 +1,248 @@
+use super::date::Date;
+use std::path::PathBuf;
+
+pub const USAGE: &str = "Usage: gen [command] [options]
+
+Commands:
+  build                  Build the site (the default)
+  serve                  Build the site, rebuild it on changes, and serve it with live reload
+  check                  Parse and render every document without writing anything
+  new <section> <title>  Start a draft in the content directory's section
+  fmt                    Tidy the whitespace in every document
+  clean                  Delete the output directory and the build cache
+  help                   Show this
+
+Options:
+  -s, --src <dir>        Content directory, instead of the config's content_dir
+  -d, --dest <dir>       Output directory, instead of the config's output_dir
+      --drafts           Build drafts too
+  -u, --base-url <url>   Use this instead of the config's base_url
+  -a, --address <addr>   Where serve listens (default 127.0.0.1:1111)
+      --check            With fmt: list the documents that aren't tidy, and change nothing
+  -v, --verbose          List every file that's written
+  -q, --quiet            Only print errors
+  -h, --help             Show this
+";
+
+/// Exit codes, from sysexits.h. 1 is for fmt --check finding documents that aren't tidy.
+pub const EXIT_USAGE: i32 = 64;
+/// Documents, templates or the config that don't parse or render
+pub const EXIT_DATA: i32 = 65;
+pub const EXIT_IO: i32 = 74;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+	Quiet,
+	Normal,
+	Verbose,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+	Build,
+	Serve,
+	Check,
+	New { section: String, title: String },
+	Fmt,
+	Clean,
+	Help,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Args {
+	pub command: Command,
+	pub src: Option<PathBuf>,
+	pub dest: Option<PathBuf>,
+	pub drafts: bool,
+	pub base_url: Option<String>,
+	pub address: String,
+	pub check: bool,
+	pub verbosity: Verbosity,
+}
+
+/// Parse the arguments after the binary's name.
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+	let mut parsed = Args {
+		command: Command::Build,
+		src: None,
+		dest: None,
+		drafts: false,
+		base_url: None,
+		address: "127.0.0.1:1111".to_string(),
+		check: false,
+		verbosity: Verbosity::Normal,
+	};
+	let mut positional = Vec::new();
+	let mut args = args.into_iter();
+	while let Some(arg) = args.next() {
+		// --src=content is the same as --src content
+		let (flag, mut value) = match arg.split_once('=') {
+			Some((flag, value)) if flag.starts_with("--") => {
+				(flag.to_string(), Some(value.to_string()))
+			}
+			_ => (arg.clone(), None),
+		};
+		let mut value = |name: &str| {
+			value
+				.take()
+				.or_else(|| args.next())
+				.ok_or_else(|| format!("{} needs a value", name))
+		};
+		match flag.as_str() {
+			"-s" | "--src" => parsed.src = Some(value(&flag)?.into()),
+			"-d" | "--dest" => parsed.dest = Some(value(&flag)?.into()),
+			"-u" | "--base-url" => parsed.base_url = Some(value(&flag)?),
+			"-a" | "--address" => parsed.address = value(&flag)?,
+			"--drafts" => parsed.drafts = true,
+			"--check" => parsed.check = true,
+			"-v" | "--verbose" => parsed.verbosity = Verbosity::Verbose,
+			"-q" | "--quiet" => parsed.verbosity = Verbosity::Quiet,
+			"-h" | "--help" => parsed.command = Command::Help,
+			f if f.starts_with('-') && f.len() > 1 => return Err(format!("Unknown option {}", f)),
+			_ => positional.push(arg),
+		}
+	}
+	if parsed.command == Command::Help {
+		return Ok(parsed);
+	}
+	let mut positional = positional.into_iter();
+	let command = positional.next();
+	parsed.command = match command.as_deref() {
+		None | Some("build") => Command::Build,
+		Some("serve") => Command::Serve,
+		Some("check") => Command::Check,
+		Some("fmt") => Command::Fmt,
+		Some("clean") => Command::Clean,
+		Some("help") => Command::Help,
+		Some("new") => {
+			let section = positional.next().ok_or("new needs a section and a title")?;
+			// The title doesn't have to be quoted.
+			let title = positional.collect::<Vec<_>>().join(" ");
+			if title.trim().is_empty() {
+				return Err("new needs a title after the section".to_string());
+			}
+			return Ok(Args {
+				command: Command::New { section, title },
+				..parsed
+			});
+		}
+		Some(c) => return Err(format!("Unknown command {}", c)),
+	};
+	match positional.next() {
+		Some(extra) => Err(format!(
+			"Unexpected argument {} for {}",
+			extra,
+			command.unwrap_or_default()
+		)),
+		None => Ok(parsed),
+	}
+}
+
+/// A new draft's source.
+pub fn new_document(title: &str, date: Date) -> String {
+	format!("= {}\n:date: {}\n:draft:\n\n", title.trim(), date)
+}
+
+/// Normalize a document's whitespace: \n line endings, no trailing whitespace, no more than one blank line in a row, and a newline at the end. Code blocks and block shortcodes are left alone, and line breaks from two trailing spaces become a backslash.
+pub fn format_document(s: &str) -> String {
+	let mut out = String::new();
+	let mut verbatim = false;
+	let mut blank = false;
+	let s = s.replace("\r\n", "\n");
+	let mut lines = s.lines().peekable();
+	while let Some(line) = lines.next() {
+		let trimmed = line.trim_start();
+		if verbatim {
+			out.push_str(line);
+			out.push('\n');
+			verbatim = !(trimmed.starts_with("```") || trimmed.starts_with("{% end %}"));
+			continue;
+		}
+		if trimmed.starts_with("```")
+			|| (trimmed.starts_with("{%") && !trimmed.starts_with("{% end %}"))
+		{
+			// A fence that closes on the same line, like ```code```, doesn't start a block.
+			verbatim = !(trimmed.len() > 3 && trimmed[3..].contains("```"));
+		}
+		let content = line.trim_end();
+		if content.is_empty() {
+			blank = true;
+			continue;
+		}
+		if blank && !out.is_empty() {
+			out.push('\n');
+		}
+		blank = false;
+		out.push_str(content);
+		let next_continues = lines.peek().map_or(false, |l| !l.trim().is_empty());
+		if line.ends_with("  ") && !content.ends_with('\\') && next_continues && !verbatim {
+			out.push('\\');
+		}
+		out.push('\n');
+	}
+	out
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn args(s: &str) -> Result<Args, String> {
+		parse(s.split_whitespace().map(str::to_string))
+	}
+
+	#[test]
+	fn test_parse() {
+		let a = args("").unwrap();
+		assert_eq!(a.command, Command::Build);
+		assert_eq!(a.verbosity, Verbosity::Normal);
+
+		let a =
+			args("serve --src=site/content -d out --drafts -u http://localhost:8000 -v").unwrap();
+		assert_eq!(a.command, Command::Serve);
+		assert_eq!(a.src, Some(PathBuf::from("site/content")));
+		assert_eq!(a.dest, Some(PathBuf::from("out")));
+		assert!(a.drafts);
+		assert_eq!(a.base_url.as_deref(), Some("http://localhost:8000"));
+		assert_eq!(a.verbosity, Verbosity::Verbose);
+
+		assert_eq!(
+			args("new blog Finite State Machines").unwrap().command,
+			Command::New {
+				section: "blog".to_string(),
+				title: "Finite State Machines".to_string()
+			}
+		);
+		let a = args("fmt --check -q").unwrap();
+		assert_eq!(a.command, Command::Fmt);
+		assert!(a.check);
+		assert_eq!(a.verbosity, Verbosity::Quiet);
+		assert_eq!(args("build --help").unwrap().command, Command::Help);
+
+		assert_eq!(args("deploy").unwrap_err(), "Unknown command deploy");
+		assert_eq!(args("build --fast").unwrap_err(), "Unknown option --fast");
+		assert_eq!(args("build --src").unwrap_err(), "--src needs a value");
+		assert_eq!(
+			args("clean public").unwrap_err(),
+			"Unexpected argument public for clean"
+		);
+		assert!(args("new blog").is_err());
+	}
+
+	#[test]
+	fn test_format_document() {
+		assert_eq!(
+			new_document(" Hello ", Date::parse("2020-08-03").unwrap()),
+			"= Hello\n:date: 2020-08-03\n:draft:\n\n"
+		);
+		assert_eq!(
+			format_document("= T\r\n\r\n\r\nOne  \r\ntwo \t\n\n\n\n```js\nlet a;  \n\n\n```\n{% math() %}\nx  \n\n\n{% end %}\nEnd  \n\n"),
+			"= T\n\nOne\\\ntwo\n\n```js\nlet a;  \n\n\n```\n{% math() %}\nx  \n\n\n{% end %}\nEnd\n"
+		);
+		let tidy = "= T\n\nSome `code` and ```inline```.\n\nMore  \n";
+		assert_eq!(
+			format_document(tidy),
+			"= T\n\nSome `code` and ```inline```.\n\nMore\n"
+		);
+	}
+}
@@ -0,0 +1,309 @@
+use super::parser2::{Input, ParseError};
+use super::toml::{self, Table, Value};
+use std::error::Error;
+use std::fmt::Display;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Link {
+	pub name: String,
+	pub url: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SiteConfig {
+	pub base_url: String,
+	pub title: String,
+	pub description: String,
+	pub author: String,
+	pub content_dir: PathBuf,
+	pub output_dir: PathBuf,
+	pub template_dir: PathBuf,
+	// Copied into the output as is.
+	pub static_dir: PathBuf,
+	// What the last build read and wrote, so that the next one can skip what hasn't changed.
+	pub cache_path: PathBuf,
+	// Atom feeds are always written, RSS only if asked for.
+	pub rss: bool,
+	pub nav: Vec<Link>,
+	pub social: Vec<Link>,
+	// Anything else, for templates.
+	pub extra: Table,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+	Io(io::Error),
+	Parse(ParseError),
+	Invalid { key: String, message: String },
+}
+impl Display for ConfigError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			ConfigError::Io(e) => write!(f, "Couldn't read the config: {}", e),
+			ConfigError::Parse(e) => write!(f, "{}", e),
+			ConfigError::Invalid { key, message } => write!(f, "`{}` {}", key, message),
+		}
+	}
+}
+impl Error for ConfigError {}
+
+fn invalid(key: &str, message: String) -> ConfigError {
+	ConfigError::Invalid {
+		key: key.to_string(),
+		message,
+	}
+}
+fn expected(key: &str, expected: &str, found: &Value) -> ConfigError {
+	invalid(
+		key,
+		format!("should be {}, but it's {}", expected, found.type_name()),
+	)
+}
+
+// Takes known keys out of a table, so that anything left over is unknown.
+struct Fields {
+	table: Table,
+	// For error messages: "nav[1]."
+	prefix: String,
+	known: &'static [&'static str],
+}
+impl Fields {
+	fn new(table: Table, prefix: String, known: &'static [&'static str]) -> Self {
+		Self {
+			table,
+			prefix,
+			known,
+		}
+	}
+	fn take(&mut self, key: &str) -> Option<(String, Value)> {
+		debug_assert!(self.known.contains(&key));
+		let value = self.table.remove(key)?;
+		Some((format!("{}{}", self.prefix, key), value))
+	}
+	fn string(&mut self, key: &str) -> Result<Option<String>, ConfigError> {
+		match self.take(key) {
+			None => Ok(None),
+			Some((_, Value::String(s))) => Ok(Some(s)),
+			Some((key, v)) => Err(expected(&key, "a string", &v)),
+		}
+	}
+	fn required_string(&mut self, key: &str) -> Result<String, ConfigError> {
+		self.string(key)?
+			.ok_or_else(|| invalid(&format!("{}{}", self.prefix, key), "is missing".to_string()))
+	}
+	fn boolean(&mut self, key: &str) -> Result<Option<bool>, ConfigError> {
+		match self.take(key) {
+			None => Ok(None),
+			Some((_, Value::Boolean(b))) => Ok(Some(b)),
+			Some((key, v)) => Err(expected(&key, "true or false", &v)),
+		}
+	}
+	fn table(&mut self, key: &str) -> Result<Option<Table>, ConfigError> {
+		match self.take(key) {
+			None => Ok(None),
+			Some((_, Value::Table(t))) => Ok(Some(t)),
+			Some((key, v)) => Err(expected(&key, "a table", &v)),
+		}
+	}
+	// An array of { name = "", url = "" } tables
+	fn links(&mut self, key: &str) -> Result<Vec<Link>, ConfigError> {
+		let values = match self.take(key) {
+			None => return Ok(Vec::new()),
+			Some((_, Value::Array(a))) => a,
+			Some((key, v)) => return Err(expected(&key, "an array of links", &v)),
+		};
+		let mut links = Vec::new();
+		for (i, value) in values.into_iter().enumerate() {
+			let prefix = format!("{}{}[{}]", self.prefix, key, i);
+			let table = match value {
+				Value::Table(t) => t,
+				v => return Err(expected(&prefix, "a table with a name and url", &v)),
+			};
+			let mut fields = Fields::new(table, prefix + ".", &["name", "url"]);
+			let link = Link {
+				name: fields.required_string("name")?,
+				url: fields.required_string("url")?,
+			};
+			fields.finish()?;
+			links.push(link);
+		}
+		Ok(links)
+	}
+	fn finish(self) -> Result<(), ConfigError> {
+		match self.table.into_keys().next() {
+			Some(key) => Err(invalid(
+				&format!("{}{}", self.prefix, key),
+				format!(
+					"isn't a config key, expected one of: {}",
+					self.known.join(", ")
+				),
+			)),
+			None => Ok(()),
+		}
+	}
+}
+
+impl SiteConfig {
+	pub fn parse(s: &str) -> Result<Self, ConfigError> {
+		let table = toml::parse(&mut Input::from(s)).map_err(ConfigError::Parse)?;
+		let mut fields = Fields::new(
+			table,
+			String::new(),
+			&[
+				"base_url",
+				"title",
+				"description",
+				"author",
+				"content_dir",
+				"output_dir",
+				"template_dir",
+				"static_dir",
+				"cache_path",
+				"rss",
+				"nav",
+				"social",
+				"extra",
+			],
+		);
+		let base_url = fields.required_string("base_url")?;
+		if !base_url.starts_with("https://") && !base_url.starts_with("http://") {
+			return Err(invalid(
+				"base_url",
+				"should be an absolute http(s) url".to_string(),
+			));
+		}
+		let title = fields.required_string("title")?;
+		let config = SiteConfig {
+			// Page urls start with '/'
+			base_url: base_url.trim_end_matches('/').to_string(),
+			description: fields.string("description")?.unwrap_or_default(),
+			author: fields.string("author")?.unwrap_or_else(|| title.clone()),
+			title,
+			content_dir: fields
+				.string("content_dir")?
+				.map_or(PathBuf::from("content"), PathBuf::from),
+			output_dir: fields
+				.string("output_dir")?
+				.map_or(PathBuf::from("public"), PathBuf::from),
+			template_dir: fields
+				.string("template_dir")?
+				.map_or(PathBuf::from("templates"), PathBuf::from),
+			static_dir: fields
+				.string("static_dir")?
+				.map_or(PathBuf::from("static"), PathBuf::from),
+			cache_path: fields
+				.string("cache_path")?
+				.map_or(PathBuf::from(".gen-cache"), PathBuf::from),
+			rss: fields.boolean("rss")?.unwrap_or(false),
+			nav: fields.links("nav")?,
+			social: fields.links("social")?,
+			extra: fields.table("extra")?.unwrap_or_default(),
+		};
+		fields.finish()?;
+		Ok(config)
+	}
+	/// The config as templates see it: `config.title`, `config.nav`, etc.
+	pub fn to_value(&self) -> Value {
+		let string = |s: &str| Value::String(s.to_string());
+		let links = |links: &[Link]| {
+			Value::Array(
+				links
+					.iter()
+					.map(|l| {
+						Value::Table(Table::from([
+							("name".to_string(), string(&l.name)),
+							("url".to_string(), string(&l.url)),
+						]))
+					})
+					.collect(),
+			)
+		};
+		Value::Table(Table::from([
+			("base_url".to_string(), string(&self.base_url)),
+			("title".to_string(), string(&self.title)),
+			("description".to_string(), string(&self.description)),
+			("author".to_string(), string(&self.author)),
+			("rss".to_string(), Value::Boolean(self.rss)),
+			("nav".to_string(), links(&self.nav)),
+			("social".to_string(), links(&self.social)),
+			("extra".to_string(), Value::Table(self.extra.clone())),
+		]))
+	}
+	pub fn load(path: &Path) -> Result<Self, ConfigError> {
+		Self::parse(&fs::read_to_string(path).map_err(ConfigError::Io)?)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_config() {
+		let config = SiteConfig::load(Path::new("config.toml")).unwrap();
+		assert_eq!(config.base_url, "https://evan-brass.github.io");
+		assert_eq!(config.title, "Evan Brass");
+		assert_eq!(config.content_dir, Path::new("content"));
+		assert_eq!(config.template_dir, Path::new("templates"));
+		assert_eq!(config.nav[1].url, "/blog/");
+		assert_eq!(config.social[1].name, "GitHub");
+
+		let config = SiteConfig::parse(
+			r#"
+base_url = "http://localhost:8080/"
+title = "Test"
+output_dir = "out"
+rss = true
+[extra]
+anything = [1, "two"]
+"#,
+		)
+		.unwrap();
+		assert_eq!(config.base_url, "http://localhost:8080");
+		assert_eq!(config.author, "Test");
+		assert_eq!(config.output_dir, Path::new("out"));
+		assert_eq!(config.static_dir, Path::new("static"));
+		assert_eq!(config.cache_path, Path::new(".gen-cache"));
+		assert!(config.rss);
+		assert!(config.nav.is_empty());
+		assert!(config.extra.contains_key("anything"));
+
+		for (s, message) in [
+			("title = \"T\"", "`base_url` is missing"),
+			(
+				"base_url = \"/\"\ntitle = \"T\"",
+				"`base_url` should be an absolute http(s) url",
+			),
+			(
+				"base_url = \"https://a\"\ntitle = 1",
+				"`title` should be a string, but it's an integer",
+			),
+			(
+				"base_url = \"https://a\"\ntitle = \"T\"\ncompile_sass = false",
+				"`compile_sass` isn't a config key, expected one of: base_url, title",
+			),
+			(
+				"base_url = \"https://a\"\ntitle = \"T\"\nnav = [{ name = \"A\" }]",
+				"`nav[0].url` is missing",
+			),
+			(
+				"base_url = \"https://a\"\ntitle = \"T\"\nsocial = [{ name = \"A\", url = \"B\", icon = \"C\" }]",
+				"`social[0].icon` isn't a config key, expected one of: name, url",
+			),
+			(
+				"base_url = \"https://a\"\ntitle = \"T\"\nrss = \"yes\"",
+				"`rss` should be true or false, but it's a string",
+			),
+		] {
+			let e = SiteConfig::parse(s).unwrap_err().to_string();
+			assert!(e.starts_with(message), "{}", e);
+		}
+		assert!(matches!(
+			SiteConfig::parse("base_url = "),
+			Err(ConfigError::Parse(_))
+		));
+	}
+}
@@ -0,0 +1,387 @@
+use std::cmp::Ordering;
+use std::fmt::{self, Display};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Time {
+	pub hour: u8,
+	pub minute: u8,
+	pub second: u8,
+	pub nanosecond: u32,
+	/// Offset from UTC in minutes.
+	pub offset: i16,
+}
+
+/// A plain date (2020-08-03) or an RFC 3339 timestamp (2020-03-27T20:18:42Z).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Date {
+	pub year: u16,
+	pub month: u8,
+	pub day: u8,
+	pub time: Option<Time>,
+}
+
+const MONTHS: [&str; 12] = [
+	"January",
+	"February",
+	"March",
+	"April",
+	"May",
+	"June",
+	"July",
+	"August",
+	"September",
+	"October",
+	"November",
+	"December",
+];
+
+fn is_leap_year(year: u16) -> bool {
+	year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+fn days_in_month(year: u16, month: u8) -> u8 {
+	match month {
+		2 if is_leap_year(year) => 29,
+		2 => 28,
+		4 | 6 | 9 | 11 => 30,
+		_ => 31,
+	}
+}
+// A fixed number of digits, optionally followed by a separator.
+fn field(s: &mut &str, len: usize, separator: Option<char>) -> Option<u32> {
+	let d = s.get(..len)?;
+	if !d.bytes().all(|b| b.is_ascii_digit()) {
+		return None;
+	}
+	let mut rest = &s[len..];
+	if let Some(c) = separator {
+		rest = rest.strip_prefix(c)?;
+	}
+	*s = rest;
+	d.parse().ok()
+}
+
+impl Date {
+	/// Parse a date, returning a description of what was expected if it isn't valid.
+	pub fn parse(s: &str) -> Result<Self, &'static str> {
+		let mut rest = s;
+		const DATE: &str = "A date like 2020-08-03 or 2020-08-03T10:30:00Z";
+		let year = field(&mut rest, 4, Some('-')).ok_or(DATE)? as u16;
+		let month = field(&mut rest, 2, Some('-')).ok_or(DATE)? as u8;
+		let day = field(&mut rest, 2, None).ok_or(DATE)? as u8;
+		if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+			return Err("A date that exists");
+		}
+		if rest.is_empty() {
+			return Ok(Date {
+				year,
+				month,
+				day,
+				time: None,
+			});
+		}
+
+		const TIME: &str = "An RFC 3339 time like T10:30:00Z or T10:30:00-07:00";
+		rest = rest
+			.strip_prefix(|c| c == 'T' || c == 't' || c == ' ')
+			.ok_or(TIME)?;
+		let hour = field(&mut rest, 2, Some(':')).ok_or(TIME)? as u8;
+		let minute = field(&mut rest, 2, Some(':')).ok_or(TIME)? as u8;
+		let second = field(&mut rest, 2, None).ok_or(TIME)? as u8;
+		let mut nanosecond = 0;
+		if let Some(fraction) = rest.strip_prefix('.') {
+			let len = fraction.bytes().take_while(u8::is_ascii_digit).count();
+			if len == 0 {
+				return Err(TIME);
+			}
+			// Anything past nanoseconds is dropped.
+			for (i, b) in fraction.bytes().take(len.min(9)).enumerate() {
+				nanosecond += (b - b'0') as u32 * 10u32.pow(8 - i as u32);
+			}
+			rest = &fraction[len..];
+		}
+		let offset = if let Some(r) = rest.strip_prefix(|c| c == 'Z' || c == 'z') {
+			rest = r;
+			0
+		} else {
+			let sign = if rest.starts_with('+') {
+				1
+			} else if rest.starts_with('-') {
+				-1
+			} else {
+				return Err("A UTC offset (Z, +HH:MM or -HH:MM)");
+			};
+			rest = &rest[1..];
+			let hours = field(&mut rest, 2, Some(':')).ok_or(TIME)?;
+			let minutes = field(&mut rest, 2, None).ok_or(TIME)?;
+			if hours > 23 || minutes > 59 {
+				return Err("A UTC offset that exists");
+			}
+			sign * (hours * 60 + minutes) as i16
+		};
+		if !rest.is_empty() {
+			return Err(TIME);
+		}
+		// 60 seconds is a leap second.
+		if hour > 23 || minute > 59 || second > 60 {
+			return Err("A time that exists");
+		}
+		Ok(Date {
+			year,
+			month,
+			day,
+			time: Some(Time {
+				hour,
+				minute,
+				second,
+				nanosecond,
+				offset,
+			}),
+		})
+	}
+	/// The full RFC 3339 timestamp, which is what Atom wants even for plain dates.
+	pub fn rfc3339(&self) -> String {
+		match self.time {
+			Some(_) => self.to_string(),
+			None => format!("{}T00:00:00Z", self),
+		}
+	}
+	/// Like "Fri, 27 Mar 2020 20:18:42 +0000", which is what RSS wants.
+	pub fn rfc2822(&self) -> String {
+		const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+		let t = self.time.unwrap_or(Time {
+			hour: 0,
+			minute: 0,
+			second: 0,
+			nanosecond: 0,
+			offset: 0,
+		});
+		// The local day of the week, which might not be the same as in UTC.
+		let local = self.timestamp() + t.offset as i64 * 60;
+		let offset = t.offset.abs();
+		format!(
+			"{}, {:02} {} {:04} {:02}:{:02}:{:02} {}{:02}{:02}",
+			DAYS[local.div_euclid(86400).rem_euclid(7) as usize],
+			self.day,
+			&MONTHS[self.month as usize - 1][..3],
+			self.year,
+			t.hour,
+			t.minute,
+			t.second,
+			if t.offset < 0 { '-' } else { '+' },
+			offset / 60,
+			offset % 60
+		)
+	}
+	/// Format like strftime, with %Y, %y, %m, %d, %e, %B, %b, %A, %a, %H, %M, %S and %%. Returns the first unsupported specifier if there is one.
+	pub fn format(&self, format: &str) -> Result<String, char> {
+		const DAYS: [&str; 7] = [
+			"Thursday",
+			"Friday",
+			"Saturday",
+			"Sunday",
+			"Monday",
+			"Tuesday",
+			"Wednesday",
+		];
+		let t = self.time.as_ref();
+		let local = self.timestamp() + t.map_or(0, |t| t.offset as i64 * 60);
+		let day = DAYS[local.div_euclid(86400).rem_euclid(7) as usize];
+		let month = MONTHS[self.month as usize - 1];
+		let mut out = String::new();
+		let mut chars = format.chars();
+		while let Some(c) = chars.next() {
+			if c != '%' {
+				out.push(c);
+				continue;
+			}
+			let s = match chars.next() {
+				Some('Y') => format!("{:04}", self.year),
+				Some('y') => format!("{:02}", self.year % 100),
+				Some('m') => format!("{:02}", self.month),
+				Some('d') => format!("{:02}", self.day),
+				Some('e') => format!("{:2}", self.day),
+				Some('B') => month.to_string(),
+				Some('b') => month[..3].to_string(),
+				Some('A') => day.to_string(),
+				Some('a') => day[..3].to_string(),
+				Some('H') => format!("{:02}", t.map_or(0, |t| t.hour)),
+				Some('M') => format!("{:02}", t.map_or(0, |t| t.minute)),
+				Some('S') => format!("{:02}", t.map_or(0, |t| t.second)),
+				Some('%') => "%".to_string(),
+				c => return Err(c.unwrap_or('%')),
+			};
+			out.push_str(&s);
+		}
+		Ok(out)
+	}
+	/// The UTC date at seconds since the unix epoch, without a time.
+	pub fn from_timestamp(seconds: i64) -> Self {
+		// Civil from days, the inverse of timestamp
+		let z = seconds.div_euclid(86400) + 719468;
+		let era = z.div_euclid(146097);
+		let doe = z - era * 146097;
+		let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+		let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+		let mp = (5 * doy + 2) / 153;
+		let day = doy - (153 * mp + 2) / 5 + 1;
+		let month = if mp < 10 { mp + 3 } else { mp - 9 };
+		let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+		Self {
+			year: year as u16,
+			month: month as u8,
+			day: day as u8,
+			time: None,
+		}
+	}
+	/// Seconds since the unix epoch. Plain dates are midnight UTC.
+	pub fn timestamp(&self) -> i64 {
+		// Days from civil, see http://howardhinnant.github.io/date_algorithms.html
+		let (year, month) = (self.year as i64, self.month as i64);
+		let y = if month <= 2 { year - 1 } else { year };
+		let era = y.div_euclid(400);
+		let yoe = y - era * 400;
+		let doy = (153 * ((month + 9) % 12) + 2) / 5 + self.day as i64 - 1;
+		let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+		let days = era * 146097 + doe - 719468;
+		let mut seconds = days * 86400;
+		if let Some(t) = &self.time {
+			seconds += t.hour as i64 * 3600 + t.minute as i64 * 60 + t.second as i64;
+			seconds -= t.offset as i64 * 60;
+		}
+		seconds
+	}
+}
+impl Ord for Date {
+	fn cmp(&self, other: &Self) -> Ordering {
+		let key = |d: &Date| {
+			let t = d.time.as_ref();
+			(
+				d.timestamp(),
+				t.map_or(0, |t| t.nanosecond),
+				t.is_some(),
+				t.map_or(0, |t| t.offset),
+			)
+		};
+		key(self).cmp(&key(other))
+	}
+}
+impl PartialOrd for Date {
+	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+		Some(self.cmp(other))
+	}
+}
+impl Display for Date {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)?;
+		if let Some(t) = &self.time {
+			write!(f, "T{:02}:{:02}:{:02}", t.hour, t.minute, t.second)?;
+			if t.nanosecond != 0 {
+				let fraction = format!("{:09}", t.nanosecond);
+				write!(f, ".{}", fraction.trim_end_matches('0'))?;
+			}
+			if t.offset == 0 {
+				write!(f, "Z")?;
+			} else {
+				let sign = if t.offset < 0 { '-' } else { '+' };
+				let offset = t.offset.abs();
+				write!(f, "{}{:02}:{:02}", sign, offset / 60, offset % 60)?;
+			}
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_parse() {
+		let d = Date::parse("2020-08-03").unwrap();
+		assert_eq!((d.year, d.month, d.day, d.time), (2020, 8, 3, None));
+		assert_eq!(d.to_string(), "2020-08-03");
+		assert_eq!(d.format("%B %e, %Y").as_deref(), Ok("August  3, 2020"));
+		assert_eq!(d.rfc3339(), "2020-08-03T00:00:00Z");
+		assert_eq!(d.rfc2822(), "Mon, 03 Aug 2020 00:00:00 +0000");
+		let d = Date::parse("2020-03-27T20:18:42-07:00").unwrap();
+		assert_eq!(d.rfc3339(), "2020-03-27T20:18:42-07:00");
+		assert_eq!(d.rfc2822(), "Fri, 27 Mar 2020 20:18:42 -0700");
+		assert_eq!(
+			d.format("%A %e %B %Y, %H:%M:%S %%").as_deref(),
+			Ok("Friday 27 March 2020, 20:18:42 %")
+		);
+		assert_eq!(
+			Date::parse("2020-08-03")
+				.unwrap()
+				.format("%B %e, %Y %a %b %d/%m/%y")
+				.as_deref(),
+			Ok("August  3, 2020 Mon Aug 03/08/20")
+		);
+		assert_eq!(d.format("%Q"), Err('Q'));
+
+		for s in [
+			"2020-03-27T20:18:42Z",
+			"2018-07-17T19:31:29+00:30",
+			"2018-07-17T19:31:29.25-07:00",
+		] {
+			assert_eq!(Date::parse(s).unwrap().to_string(), s);
+		}
+		let d = Date::parse("2018-07-17T19:31:29.000+00:00").unwrap();
+		assert_eq!(d.to_string(), "2018-07-17T19:31:29Z");
+		assert_eq!(Date::parse("2018-07-17t19:31:29z"), Ok(d));
+		assert_eq!(Date::parse("2018-07-17 19:31:29Z"), Ok(d));
+
+		for s in [
+			"",
+			"2020",
+			"2020-8-3",
+			"2020-02-30",
+			"2021-02-29",
+			"2020-13-01",
+			"2020-08-03T",
+			"2020-08-03T10:30",
+			"2020-08-03T10:30:00",
+			"2020-08-03T24:00:00Z",
+			"2020-08-03T10:30:00+25:00",
+			"2020-08-03T10:30:00Z trailing",
+			"August 3rd",
+		] {
+			assert!(Date::parse(s).is_err(), "{}", s);
+		}
+		assert!(Date::parse("2020-02-29").is_ok());
+	}
+
+	#[test]
+	fn test_order() {
+		let d = |s| Date::parse(s).unwrap();
+		assert_eq!(d("1970-01-01").timestamp(), 0);
+		assert_eq!(d("2020-03-27T20:18:42Z").timestamp(), 1585340322);
+		assert_eq!(d("1969-12-31T23:00:00-01:00").timestamp(), 0);
+		assert_eq!(Date::from_timestamp(1585340322), d("2020-03-27"));
+		assert_eq!(Date::from_timestamp(-1), d("1969-12-31"));
+		assert_eq!(
+			Date::from_timestamp(d("2000-02-29").timestamp()),
+			d("2000-02-29")
+		);
+		assert!(d("2020-07-31") < d("2020-08-03"));
+		assert!(d("2020-08-03T01:00:00+02:00") < d("2020-08-03"));
+		assert!(d("2020-08-03") < d("2020-08-03T00:00:00.5Z"));
+		let mut dates = vec![
+			d("2020-07-13T01:36:02Z"),
+			d("2018-07-17T19:34:04Z"),
+			d("2020-08-03"),
+			d("2018-07-17T19:31:29.000+00:00"),
+		];
+		dates.sort();
+		let sorted: Vec<_> = dates.iter().map(|d| d.to_string()).collect();
+		assert_eq!(
+			sorted,
+			[
+				"2018-07-17T19:31:29Z",
+				"2018-07-17T19:34:04Z",
+				"2020-07-13T01:36:02Z",
+				"2020-08-03"
+			]
+		);
+	}
+}
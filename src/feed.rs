@@ -0,0 +1,483 @@
+use super::date::Date;
+use super::html::Escape;
+use std::io::{self, Write};
+
+/// Where a feed lives and what it's about.
+pub struct Feed<'a> {
+	pub title: &'a str,
+	pub description: &'a str,
+	/// Absolute url of the page that the feed follows.
+	pub home: &'a str,
+	/// Absolute url of the feed itself.
+	pub url: &'a str,
+	/// Used when an entry doesn't have authors of its own.
+	pub author: &'a str,
+}
+
+pub struct FeedEntry<'a> {
+	pub title: &'a str,
+	/// Absolute url of the entry's page.
+	pub url: &'a str,
+	pub date: Date,
+	pub updated: Option<Date>,
+	/// Names and optional email addresses.
+	pub authors: &'a [(String, Option<String>)],
+	pub description: &'a str,
+	/// The rendered HTML of the page, with links relative to url.
+	pub content: &'a str,
+}
+impl FeedEntry<'_> {
+	fn updated(&self) -> Date {
+		self.updated.unwrap_or(self.date)
+	}
+}
+
+/// Write an Atom (RFC 4287) feed. Entries should already be sorted newest first.
+pub fn write_atom(o: &mut impl Write, feed: &Feed, entries: &[FeedEntry]) -> io::Result<()> {
+	// A feed has to say when it was updated, even when it's empty.
+	let updated = entries
+		.iter()
+		.map(FeedEntry::updated)
+		.max()
+		.unwrap_or(Date {
+			year: 1970,
+			month: 1,
+			day: 1,
+			time: None,
+		});
+	write!(
+		o,
+		r#"<?xml version="1.0" encoding="UTF-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom" xml:lang="en">
+	<title>{}</title>
+	<subtitle>{}</subtitle>
+	<link href="{}" rel="self" type="application/atom+xml"/>
+	<link href="{}"/>
+	<updated>{}</updated>
+	<id>{}</id>
+	<author><name>{}</name></author>"#,
+		Escape(feed.title),
+		Escape(feed.description),
+		Escape(feed.url),
+		Escape(feed.home),
+		updated.rfc3339(),
+		Escape(feed.url),
+		Escape(feed.author)
+	)?;
+	for entry in entries {
+		write!(
+			o,
+			r#"
+	<entry>
+		<title>{}</title>
+		<published>{}</published>
+		<updated>{}</updated>"#,
+			Escape(entry.title),
+			entry.date.rfc3339(),
+			entry.updated().rfc3339()
+		)?;
+		for (name, email) in entry.authors {
+			write!(o, "\n\t\t<author><name>{}</name>", Escape(name))?;
+			if let Some(email) = email {
+				write!(o, "<email>{}</email>", Escape(email))?;
+			}
+			write!(o, "</author>")?;
+		}
+		write!(
+			o,
+			r#"
+		<link rel="alternate" href="{}" type="text/html"/>
+		<id>{}</id>"#,
+			Escape(entry.url),
+			Escape(entry.url)
+		)?;
+		if !entry.description.is_empty() {
+			write!(
+				o,
+				r#"
+		<summary type="html">{}</summary>"#,
+				Escape(entry.description)
+			)?;
+		}
+		// xml:base makes the relative links in the content resolve against the page.
+		write!(
+			o,
+			r#"
+		<content type="html" xml:base="{}">{}</content>
+	</entry>"#,
+			Escape(entry.url),
+			Escape(entry.content)
+		)?;
+	}
+	write!(o, "\n</feed>\n")
+}
+
+/// Write an RSS 2.0 feed. Entries should already be sorted newest first.
+pub fn write_rss(o: &mut impl Write, feed: &Feed, entries: &[FeedEntry]) -> io::Result<()> {
+	write!(
+		o,
+		r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom">
+	<channel>
+		<title>{}</title>
+		<link>{}</link>
+		<description>{}</description>
+		<language>en</language>
+		<atom:link href="{}" rel="self" type="application/rss+xml"/>"#,
+		Escape(feed.title),
+		Escape(feed.home),
+		Escape(feed.description),
+		Escape(feed.url)
+	)?;
+	if let Some(updated) = entries.iter().map(FeedEntry::updated).max() {
+		write!(
+			o,
+			"\n\t\t<lastBuildDate>{}</lastBuildDate>",
+			updated.rfc2822()
+		)?;
+	}
+	for entry in entries {
+		write!(
+			o,
+			r#"
+		<item>
+			<title>{}</title>
+			<link>{}</link>
+			<guid>{}</guid>
+			<pubDate>{}</pubDate>"#,
+			Escape(entry.title),
+			Escape(entry.url),
+			Escape(entry.url),
+			entry.date.rfc2822()
+		)?;
+		// RSS only has room for authors with an email address.
+		for (name, email) in entry.authors {
+			if let Some(email) = email {
+				write!(
+					o,
+					"\n\t\t\t<author>{} ({})</author>",
+					Escape(email),
+					Escape(name)
+				)?;
+			}
+		}
+		write!(
+			o,
+			r#"
+			<description>{}</description>
+		</item>"#,
+			Escape(entry.content)
+		)?;
+	}
+	write!(o, "\n\t</channel>\n</rss>\n")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// Just enough of an XML parser to check the structure of a feed.
+	#[derive(Debug)]
+	struct Element {
+		name: String,
+		attributes: Vec<(String, String)>,
+		children: Vec<Element>,
+		text: String,
+	}
+	impl Element {
+		fn attribute(&self, name: &str) -> Option<&str> {
+			self.attributes
+				.iter()
+				.find(|(k, _)| k == name)
+				.map(|(_, v)| v.as_str())
+		}
+		fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> + 'a {
+			self.children.iter().filter(move |c| c.name == name)
+		}
+		fn child(&self, name: &str) -> &Element {
+			let mut children = self.children.iter().filter(|c| c.name == name);
+			let child = children
+				.next()
+				.unwrap_or_else(|| panic!("<{}> is missing <{}>", self.name, name));
+			assert!(
+				children.next().is_none(),
+				"<{}> has more than one <{}>",
+				self.name,
+				name
+			);
+			child
+		}
+	}
+	fn unescape(s: &str) -> String {
+		assert!(!s.contains('<'), "Unescaped '<' in {:?}", s);
+		let mut out = String::new();
+		let mut rest = s;
+		while let Some(i) = rest.find('&') {
+			out.push_str(&rest[..i]);
+			let end = rest[i..].find(';').expect("Unterminated entity") + i;
+			out.push(match &rest[i + 1..end] {
+				"amp" => '&',
+				"lt" => '<',
+				"gt" => '>',
+				"quot" => '"',
+				"apos" => '\'',
+				e => panic!("Unknown entity &{};", e),
+			});
+			rest = &rest[end + 1..];
+		}
+		out.push_str(rest);
+		out
+	}
+	fn parse_element(s: &mut &str) -> Element {
+		*s = s.trim_start();
+		*s = s.strip_prefix('<').expect("Expected an element");
+		let end = s
+			.find(|c: char| c.is_whitespace() || c == '>' || c == '/')
+			.unwrap();
+		let mut element = Element {
+			name: s[..end].to_string(),
+			attributes: Vec::new(),
+			children: Vec::new(),
+			text: String::new(),
+		};
+		*s = &s[end..];
+		loop {
+			*s = s.trim_start();
+			if let Some(rest) = s.strip_prefix("/>") {
+				*s = rest;
+				return element;
+			} else if let Some(rest) = s.strip_prefix('>') {
+				*s = rest;
+				break;
+			}
+			let eq = s.find('=').unwrap();
+			let name = s[..eq].trim().to_string();
+			let value = s[eq + 1..]
+				.strip_prefix('"')
+				.expect("Expected a quoted value");
+			let end = value.find('"').unwrap();
+			assert!(
+				element.attribute(&name).is_none(),
+				"Duplicate attribute {}",
+				name
+			);
+			element.attributes.push((name, unescape(&value[..end])));
+			*s = &value[end + 1..];
+		}
+		loop {
+			let text = &s[..s.find('<').expect("Unclosed element")];
+			element.text.push_str(&unescape(text));
+			*s = &s[text.len()..];
+			if let Some(rest) = s.strip_prefix("</") {
+				let end = rest.find('>').unwrap();
+				assert_eq!(rest[..end].trim(), element.name, "Mismatched closing tag");
+				*s = &rest[end + 1..];
+				return element;
+			}
+			element.children.push(parse_element(s));
+		}
+	}
+	fn parse_xml(s: &str) -> Element {
+		let mut rest = s
+			.strip_prefix(r#"<?xml version="1.0" encoding="UTF-8"?>"#)
+			.expect("Missing XML declaration");
+		let root = parse_element(&mut rest);
+		assert!(rest.trim().is_empty(), "Trailing content: {:?}", rest);
+		root
+	}
+
+	// The constraints from RFC 4287 that apply to what we generate.
+	fn validate_atom(feed: &Element) {
+		assert_eq!(feed.name, "feed");
+		assert_eq!(feed.attribute("xmlns"), Some("http://www.w3.org/2005/Atom"));
+		let is_date = |e: &Element| {
+			assert!(e.text.contains('T'), "{} isn't a full timestamp", e.text);
+			Date::parse(&e.text).unwrap_or_else(|e| panic!("{}", e));
+		};
+		let is_url = |s: &str| {
+			assert!(
+				s.starts_with("https://") || s.starts_with("http://"),
+				"{}",
+				s
+			)
+		};
+		let text_construct = |e: &Element| {
+			assert!(matches!(
+				e.attribute("type"),
+				None | Some("text") | Some("html")
+			));
+		};
+		text_construct(feed.child("title"));
+		is_date(feed.child("updated"));
+		is_url(&feed.child("id").text);
+		assert!(feed
+			.children("link")
+			.any(|l| l.attribute("rel") == Some("self")));
+		let feed_author = feed.children("author").count() > 0;
+		for author in feed.children("author") {
+			assert!(!author.child("name").text.is_empty());
+		}
+		for link in feed.children("link") {
+			is_url(link.attribute("href").expect("A link needs an href"));
+		}
+		for entry in feed.children("entry") {
+			text_construct(entry.child("title"));
+			is_url(&entry.child("id").text);
+			is_date(entry.child("updated"));
+			for published in entry.children("published") {
+				is_date(published);
+			}
+			assert!(feed_author || entry.children("author").count() > 0);
+			for author in entry.children("author") {
+				assert!(!author.child("name").text.is_empty());
+			}
+			// Without content there has to be an alternate link.
+			let content = entry.children("content").next();
+			assert!(
+				content.is_some()
+					|| entry
+						.children("link")
+						.any(|l| matches!(l.attribute("rel"), None | Some("alternate")))
+			);
+			for summary in entry.children("summary") {
+				text_construct(summary);
+			}
+			if let Some(content) = content {
+				text_construct(content);
+			}
+			assert!(entry.children("content").count() <= 1);
+		}
+		for child in &feed.children {
+			assert!(
+				[
+					"author",
+					"category",
+					"contributor",
+					"generator",
+					"icon",
+					"id",
+					"link",
+					"logo",
+					"rights",
+					"subtitle",
+					"title",
+					"updated",
+					"entry"
+				]
+				.contains(&child.name.as_str()),
+				"<{}> isn't allowed in a feed",
+				child.name
+			);
+		}
+	}
+
+	fn entries<'a>(authors: &'a [(String, Option<String>)]) -> Vec<FeedEntry<'a>> {
+		vec![
+			FeedEntry {
+				title: "Why I Hated AMP & Why My Mind Has Changed",
+				url: "https://evan-brass.github.io/blog/why-i-hated-amp/",
+				date: Date::parse("2020-07-13T01:36:02Z").unwrap(),
+				updated: Date::parse("2020-08-01").ok(),
+				authors,
+				description: "By hated I mean… <em>hated</em>.",
+				content: r#"<p>By <a href="../reactivity/">hated</a> I mean…</p>"#,
+			},
+			FeedEntry {
+				title: "Reactivity",
+				url: "https://evan-brass.github.io/blog/reactivity/",
+				date: Date::parse("2020-07-31").unwrap(),
+				updated: None,
+				authors: &[],
+				description: "",
+				content: "<p>Everything I know.</p>",
+			},
+		]
+	}
+	const FEED: Feed = Feed {
+		title: "Evan Brass",
+		description: "Mediocre content about life & my technical interests.",
+		home: "https://evan-brass.github.io/",
+		url: "https://evan-brass.github.io/atom.xml",
+		author: "Evan Brass",
+	};
+
+	#[test]
+	fn test_atom() {
+		let authors = [
+			(
+				"Evan Brass".to_string(),
+				Some("evan@example.com".to_string()),
+			),
+			("Someone Else".to_string(), None),
+		];
+		let mut output = Vec::new();
+		write_atom(&mut output, &FEED, &entries(&authors)).unwrap();
+		let feed = parse_xml(&String::from_utf8(output).unwrap());
+		validate_atom(&feed);
+
+		assert_eq!(feed.child("updated").text, "2020-08-01T00:00:00Z");
+		let entries: Vec<_> = feed.children("entry").collect();
+		assert_eq!(entries.len(), 2);
+		let amp = entries[0];
+		assert_eq!(
+			amp.child("title").text,
+			"Why I Hated AMP & Why My Mind Has Changed"
+		);
+		assert_eq!(amp.child("published").text, "2020-07-13T01:36:02Z");
+		assert_eq!(amp.children("author").count(), 2);
+		let content = amp.child("content");
+		assert_eq!(content.attribute("type"), Some("html"));
+		assert_eq!(
+			content.attribute("xml:base"),
+			Some("https://evan-brass.github.io/blog/why-i-hated-amp/")
+		);
+		assert_eq!(
+			content.text,
+			r#"<p>By <a href="../reactivity/">hated</a> I mean…</p>"#
+		);
+		assert_eq!(
+			amp.child("summary").text,
+			"By hated I mean… <em>hated</em>."
+		);
+		assert_eq!(entries[1].children("summary").count(), 0);
+		assert_eq!(entries[1].child("updated").text, "2020-07-31T00:00:00Z");
+
+		// Feeds without any entries are still valid.
+		let mut output = Vec::new();
+		write_atom(&mut output, &FEED, &[]).unwrap();
+		validate_atom(&parse_xml(&String::from_utf8(output).unwrap()));
+	}
+
+	#[test]
+	fn test_rss() {
+		let authors = [(
+			"Evan Brass".to_string(),
+			Some("evan@example.com".to_string()),
+		)];
+		let mut output = Vec::new();
+		write_rss(&mut output, &FEED, &entries(&authors)).unwrap();
+		let rss = parse_xml(&String::from_utf8(output).unwrap());
+		assert_eq!(rss.attribute("version"), Some("2.0"));
+		let channel = rss.child("channel");
+		assert_eq!(channel.child("link").text, "https://evan-brass.github.io/");
+		assert_eq!(
+			channel.child("lastBuildDate").text,
+			"Sat, 01 Aug 2020 00:00:00 +0000"
+		);
+		let items: Vec<_> = channel.children("item").collect();
+		assert_eq!(items.len(), 2);
+		assert_eq!(
+			items[0].child("pubDate").text,
+			"Mon, 13 Jul 2020 01:36:02 +0000"
+		);
+		assert_eq!(
+			items[0].child("author").text,
+			"evan@example.com (Evan Brass)"
+		);
+		assert_eq!(items[1].children("author").count(), 0);
+		assert_eq!(
+			items[1].child("description").text,
+			"<p>Everything I know.</p>"
+		);
+	}
+}
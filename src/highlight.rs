@@ -0,0 +1,652 @@
+// Syntax highlighting for code blocks, with the classes that syntect uses so that static/highlight-*.css can style them.
+use super::html::Escape;
+use std::fmt::Write;
+
+// What the C-like languages (JavaScript, Rust, shell and JSON) have in common, and what's different about them.
+struct Code {
+	keywords: &'static [&'static str],
+	// let, const, fn, etc.
+	storage: &'static [&'static str],
+	// true, false, null
+	constants: &'static [&'static str],
+	// this, self, super
+	variables: &'static [&'static str],
+	types: &'static [&'static str],
+	line_comment: &'static str,
+	block_comment: Option<(&'static str, &'static str)>,
+	quotes: &'static str,
+	// Rust's 'a lifetimes, which start like a char
+	lifetimes: bool,
+	// Rust's println!
+	macros: bool,
+	// The shell's $VAR and ${VAR}
+	dollar_variables: bool,
+	// JSON's "key":
+	keys: bool,
+}
+
+const JAVASCRIPT: Code = Code {
+	keywords: &[
+		"if",
+		"else",
+		"for",
+		"while",
+		"do",
+		"switch",
+		"case",
+		"default",
+		"break",
+		"continue",
+		"return",
+		"throw",
+		"try",
+		"catch",
+		"finally",
+		"new",
+		"delete",
+		"typeof",
+		"instanceof",
+		"in",
+		"of",
+		"await",
+		"yield",
+		"import",
+		"export",
+		"from",
+		"as",
+		"extends",
+		"void",
+	],
+	storage: &[
+		"var", "let", "const", "function", "class", "async", "static", "get", "set",
+	],
+	constants: &["true", "false", "null", "undefined", "NaN", "Infinity"],
+	variables: &["this", "super", "arguments"],
+	types: &[],
+	line_comment: "//",
+	block_comment: Some(("/*", "*/")),
+	quotes: "\"'`",
+	lifetimes: false,
+	macros: false,
+	dollar_variables: false,
+	keys: false,
+};
+const RUST: Code = Code {
+	keywords: &[
+		"if", "else", "for", "while", "loop", "match", "break", "continue", "return", "in", "as",
+		"use", "mod", "crate", "where", "unsafe", "async", "await", "move", "dyn", "extern",
+	],
+	storage: &[
+		"let", "mut", "ref", "fn", "struct", "enum", "trait", "impl", "type", "const", "static",
+		"pub",
+	],
+	constants: &["true", "false"],
+	variables: &["self", "Self", "super"],
+	types: &[
+		"bool", "char", "str", "u8", "u16", "u32", "u64", "u128", "usize", "i8", "i16", "i32",
+		"i64", "i128", "isize", "f32", "f64",
+	],
+	line_comment: "//",
+	block_comment: Some(("/*", "*/")),
+	quotes: "\"'",
+	lifetimes: true,
+	macros: true,
+	dollar_variables: false,
+	keys: false,
+};
+const SHELL: Code = Code {
+	keywords: &[
+		"if", "then", "else", "elif", "fi", "for", "in", "do", "done", "while", "until", "case",
+		"esac", "function", "return", "exit", "export", "local",
+	],
+	storage: &[],
+	constants: &[],
+	variables: &[],
+	types: &[],
+	line_comment: "#",
+	block_comment: None,
+	quotes: "\"'",
+	lifetimes: false,
+	macros: false,
+	dollar_variables: true,
+	keys: false,
+};
+const JSON: Code = Code {
+	keywords: &[],
+	storage: &[],
+	constants: &["true", "false", "null"],
+	variables: &[],
+	types: &[],
+	line_comment: "",
+	block_comment: None,
+	quotes: "\"",
+	lifetimes: false,
+	macros: false,
+	dollar_variables: false,
+	keys: true,
+};
+
+fn is_ident(c: char) -> bool {
+	c.is_alphanumeric() || c == '_' || c == '$'
+}
+fn is_css_ident(c: char) -> bool {
+	c.is_alphanumeric() || c == '_' || c == '-'
+}
+fn ident_len(s: &str, f: fn(char) -> bool) -> usize {
+	s.find(|c: char| !f(c)).unwrap_or(s.len())
+}
+
+struct Highlighter<'a> {
+	code: &'a str,
+	pos: usize,
+	// Embedded code (like a <script> in html) stops early.
+	end: usize,
+	// Classes and the range of code that they're for.
+	spans: Vec<(&'static str, usize, usize)>,
+}
+impl<'a> Highlighter<'a> {
+	fn rest(&self) -> &'a str {
+		&self.code[self.pos..self.end]
+	}
+	fn peek(&self) -> Option<char> {
+		self.rest().chars().next()
+	}
+	fn take(&mut self, class: &'static str, len: usize) {
+		if len == 0 {
+			return;
+		}
+		let (start, end) = (self.pos, self.pos + len);
+		self.pos = end;
+		match self.spans.last_mut() {
+			Some((last, _, last_end)) if *last == class => *last_end = end,
+			_ => self.spans.push((class, start, end)),
+		}
+	}
+	fn take_while(&mut self, class: &'static str, f: fn(char) -> bool) {
+		let len = ident_len(self.rest(), f);
+		self.take(class, len);
+	}
+	fn take_char(&mut self, class: &'static str) {
+		let len = self.peek().map_or(0, char::len_utf8);
+		self.take(class, len);
+	}
+
+	fn string(&mut self, quote: char, code: &Code) {
+		let rest = self.rest();
+		let mut chars = rest.char_indices().skip(1);
+		let mut len = rest.len();
+		while let Some((i, c)) = chars.next() {
+			// Single quoted strings in the shell don't have escapes.
+			if c == '\\' && !(code.dollar_variables && quote == '\'') {
+				chars.next();
+			} else if c == quote {
+				len = i + 1;
+				break;
+			}
+		}
+		let class = if code.keys && rest[len..].trim_start().starts_with(':') {
+			"support type property-name"
+		} else {
+			match quote {
+				'"' => "string quoted double",
+				'\'' => "string quoted single",
+				_ => "string quoted other",
+			}
+		};
+		let end = self.pos + len;
+		while self.pos < end {
+			let rest = &self.code[self.pos..end];
+			match rest.find('\\') {
+				Some(i) if !(code.dollar_variables && quote == '\'') => {
+					self.take(class, i);
+					let escaped = self.code[self.pos + 1..end].chars().next();
+					self.take(
+						"constant character escape",
+						1 + escaped.map_or(0, char::len_utf8),
+					);
+				}
+				_ => self.take(class, rest.len()),
+			}
+		}
+	}
+	fn number(&mut self) {
+		let rest = self.rest();
+		let len = rest
+			.char_indices()
+			.find(|&(i, c)| {
+				!(c.is_ascii_alphanumeric()
+					|| c == '_' || (c == '.'
+					&& rest[i + 1..].starts_with(|c: char| c.is_ascii_digit())))
+			})
+			.map_or(rest.len(), |(i, _)| i);
+		self.take("constant numeric", len);
+	}
+
+	fn code(&mut self, code: &Code) {
+		// For naming what comes after fn, class, etc.
+		let mut last_word = "";
+		while let Some(c) = self.peek() {
+			let rest = self.rest();
+			let after_space = self.code[..self.pos]
+				.chars()
+				.next_back()
+				.map_or(true, char::is_whitespace);
+			if c.is_whitespace() {
+				self.take_while("", char::is_whitespace);
+				continue;
+			} else if !code.line_comment.is_empty()
+				&& rest.starts_with(code.line_comment)
+				// The shell's # only starts a comment at the start of a word.
+				&& (code.line_comment != "#" || after_space)
+			{
+				self.take("comment", rest.find('\n').unwrap_or(rest.len()));
+			} else if let Some((open, close)) = code
+				.block_comment
+				.filter(|(open, _)| rest.starts_with(open))
+			{
+				let len = rest[open.len()..]
+					.find(close)
+					.map_or(rest.len(), |i| open.len() + i + close.len());
+				self.take("comment", len);
+			} else if c == '\'' && code.lifetimes && {
+				let len = ident_len(&rest[1..], is_ident);
+				len > 0 && !rest[1 + len..].starts_with('\'')
+			} {
+				let len = 1 + ident_len(&rest[1..], is_ident);
+				self.take("storage modifier lifetime", len);
+			} else if code.quotes.contains(c) {
+				self.string(c, code);
+			} else if c.is_ascii_digit()
+				|| (c == '.' && rest[1..].starts_with(|c: char| c.is_ascii_digit()))
+			{
+				self.number();
+			} else if c == '$' && code.dollar_variables {
+				let len = match rest[1..].strip_prefix('{') {
+					Some(braced) => braced.find('}').map_or(rest.len(), |i| i + 3),
+					None if rest[1..].starts_with(|c: char| "?@#!*-".contains(c)) => 2,
+					None => 1 + ident_len(&rest[1..], |c| c.is_alphanumeric() || c == '_'),
+				};
+				self.take("variable other", len);
+			} else if is_ident(c) {
+				let word = &rest[..ident_len(rest, is_ident)];
+				let next = rest[word.len()..].trim_start();
+				let class = if code.keywords.contains(&word) {
+					"keyword control"
+				} else if code.storage.contains(&word) {
+					"storage type"
+				} else if code.constants.contains(&word) {
+					"constant language"
+				} else if code.variables.contains(&word) {
+					"variable language"
+				} else if code.types.contains(&word) {
+					"support type"
+				} else if code.macros && next.starts_with('!') && !next.starts_with("!=") {
+					"support function macro"
+				} else if last_word == "fn" || last_word == "function" {
+					"entity name function"
+				} else if ["class", "struct", "enum", "trait", "type"].contains(&last_word) {
+					"entity name class"
+				} else if next.starts_with('(') {
+					"variable function"
+				} else if word.starts_with(|c: char| c.is_uppercase()) {
+					"support class"
+				} else {
+					""
+				};
+				self.take(class, word.len());
+				if class == "support function macro" {
+					self.take(class, 1);
+				}
+				last_word = word;
+				continue;
+			} else if "=+-*/%<>!&|^~?:".contains(c) {
+				self.take("keyword operator", 1);
+			} else {
+				self.take_char("");
+			}
+			last_word = "";
+		}
+	}
+
+	fn css(&mut self) {
+		// Whether each open block has declarations in it, or rules like in @media.
+		let mut blocks: Vec<bool> = Vec::new();
+		let mut at_rule = false;
+		// After a property's ':'
+		let mut value = false;
+		while let Some(c) = self.peek() {
+			let rest = self.rest();
+			let declarations = blocks.last() == Some(&true);
+			if c.is_whitespace() {
+				self.take_while("", char::is_whitespace);
+			} else if let Some(comment) = rest.strip_prefix("/*") {
+				let len = comment.find("*/").map_or(rest.len(), |i| i + 4);
+				self.take("comment", len);
+			} else if c == '"' || c == '\'' {
+				self.string(c, &JSON);
+			} else if c == '{' {
+				blocks.push(!at_rule);
+				at_rule = false;
+				value = false;
+				self.take("", 1);
+			} else if c == '}' || c == ';' {
+				if c == '}' {
+					blocks.pop();
+				}
+				at_rule = false;
+				value = false;
+				self.take("", 1);
+			} else if c == '@' {
+				let len = 1 + ident_len(&rest[1..], is_css_ident);
+				self.take("keyword control at-rule", len);
+				at_rule = true;
+			} else if declarations && !value {
+				if c == ':' {
+					value = true;
+					self.take("", 1);
+				} else if is_css_ident(c) {
+					self.take_while("support type property-name", is_css_ident);
+				} else {
+					self.take_char("");
+				}
+			} else if value || at_rule {
+				let number = rest.strip_prefix('-').unwrap_or(rest);
+				if c == '#' {
+					let len = 1 + ident_len(&rest[1..], |c| c.is_ascii_hexdigit());
+					self.take("constant other color", len);
+				} else if number.starts_with(|c: char| c.is_ascii_digit())
+					|| (number.starts_with('.')
+						&& number[1..].starts_with(|c: char| c.is_ascii_digit()))
+				{
+					let len = rest.len() - number.len()
+						+ ident_len(number, |c| c.is_ascii_digit() || c == '.');
+					self.take("constant numeric", len);
+					let unit = ident_len(self.rest(), |c| c.is_alphabetic() || c == '%');
+					self.take("keyword other unit", unit);
+				} else if c == '!' {
+					let len = 1 + ident_len(&rest[1..], is_css_ident);
+					self.take("keyword other important", len);
+				} else if is_css_ident(c) {
+					let len = ident_len(rest, is_css_ident);
+					if rest[len..].starts_with('(') {
+						self.take("support function", len);
+					} else {
+						self.take("support constant property-value", len);
+					}
+				} else {
+					self.take_char("");
+				}
+			} else {
+				// Selectors
+				let (class, prefix) = match c {
+					'.' => ("entity other attribute-name class", 1),
+					'#' => ("entity other attribute-name id", 1),
+					':' if rest.starts_with("::") => {
+						("entity other attribute-name pseudo-element", 2)
+					}
+					':' => ("entity other attribute-name pseudo-class", 1),
+					c if is_css_ident(c) || c == '*' => ("entity name tag", 0),
+					_ => ("", c.len_utf8()),
+				};
+				let len = prefix + ident_len(&rest[prefix..], is_css_ident);
+				self.take(class, len.max(c.len_utf8()));
+			}
+		}
+	}
+
+	// Returns the tag's name if it opens an element.
+	fn tag(&mut self) -> Option<&'a str> {
+		let closing = self.rest().starts_with("</");
+		self.take("punctuation definition tag", if closing { 2 } else { 1 });
+		let len = ident_len(self.rest(), |c| c.is_alphanumeric() || c == '-' || c == ':');
+		let name = &self.rest()[..len];
+		self.take("entity name tag", len);
+		while let Some(c) = self.peek() {
+			let rest = self.rest();
+			if c == '>' {
+				self.take("punctuation definition tag", 1);
+				return if closing { None } else { Some(name) };
+			} else if rest.starts_with("/>") {
+				self.take("punctuation definition tag", 2);
+				return None;
+			} else if c.is_whitespace() || c == '=' {
+				self.take_char("");
+			} else if c == '"' || c == '\'' {
+				let len = rest[1..].find(c).map_or(rest.len(), |i| i + 2);
+				self.take(
+					if c == '"' {
+						"string quoted double"
+					} else {
+						"string quoted single"
+					},
+					len,
+				);
+			} else if self.code[..self.pos].trim_end().ends_with('=') {
+				self.take_while("string unquoted", |c| !c.is_whitespace() && c != '>');
+			} else {
+				self.take_while("entity other attribute-name", |c| {
+					!c.is_whitespace() && !"=>/\"'".contains(c)
+				});
+				if self.rest().starts_with('/') && !self.rest().starts_with("/>") {
+					self.take_char("");
+				}
+			}
+		}
+		None
+	}
+
+	fn html(&mut self) {
+		while let Some(c) = self.peek() {
+			let rest = self.rest();
+			if let Some(comment) = rest.strip_prefix("<!--") {
+				let len = comment.find("-->").map_or(rest.len(), |i| i + 7);
+				self.take("comment", len);
+			} else if rest.starts_with("<!") {
+				let len = rest.find('>').map_or(rest.len(), |i| i + 1);
+				self.take("meta tag sgml doctype", len);
+			} else if c == '<'
+				&& rest[1..].starts_with(|c: char| c.is_ascii_alphabetic() || c == '/')
+			{
+				let name = self.tag().map(str::to_ascii_lowercase);
+				// Scripts and styles are highlighted as what they are, up to the tag that closes them.
+				if let Some(name @ ("script" | "style")) = name.as_deref() {
+					let close = format!("</{}", name);
+					let len = self
+						.rest()
+						.to_ascii_lowercase()
+						.find(&close)
+						.unwrap_or(self.rest().len());
+					let end = self.end;
+					self.end = self.pos + len;
+					if name == "script" {
+						self.code(&JAVASCRIPT);
+					} else {
+						self.css();
+					}
+					self.end = end;
+				}
+			} else if c == '&' {
+				let len = ident_len(&rest[1..], |c| c.is_alphanumeric() || c == '#');
+				if rest[1 + len..].starts_with(';') && len > 0 {
+					self.take("constant character entity", len + 2);
+				} else {
+					self.take("", 1);
+				}
+			} else {
+				let len = rest[c.len_utf8()..]
+					.find(['<', '&'])
+					.map_or(rest.len(), |i| i + c.len_utf8());
+				self.take("", len);
+			}
+		}
+	}
+}
+
+/// Highlight code written in language (a code block's fence language like "js" or "rust") as html. Returns None if the language isn't one that can be highlighted.
+pub fn highlight(language: &str, code: &str) -> Option<String> {
+	let (scope, lex): (&str, fn(&mut Highlighter)) = match language.to_ascii_lowercase().as_str() {
+		"javascript" | "js" | "mjs" | "jsx" => ("source js", |h| h.code(&JAVASCRIPT)),
+		"rust" | "rs" => ("source rust", |h| h.code(&RUST)),
+		"shell" | "sh" | "bash" | "zsh" | "console" => ("source shell", |h| h.code(&SHELL)),
+		"json" => ("source json", |h| h.code(&JSON)),
+		"css" => ("source css", |h| h.css()),
+		"html" | "htm" | "xml" | "svg" => ("text html basic", |h| h.html()),
+		_ => return None,
+	};
+	let mut h = Highlighter {
+		code,
+		pos: 0,
+		end: code.len(),
+		spans: Vec::new(),
+	};
+	lex(&mut h);
+	let mut html = format!(r#"<span class="{}">"#, scope);
+	for (class, start, end) in h.spans {
+		let text = Escape(&code[start..end]);
+		if class.is_empty() {
+			write!(html, "{}", text).unwrap();
+		} else {
+			write!(html, r#"<span class="{}">{}</span>"#, class, text).unwrap();
+		}
+	}
+	html.push_str("</span>");
+	Some(html)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// The highlighted code as (class, text) pairs, without the unclassed text between them.
+	fn spans(language: &str, code: &str) -> Vec<(String, String)> {
+		let html = highlight(language, code).unwrap();
+		let mut spans = Vec::new();
+		let mut rest = html.as_str();
+		while let Some(start) = rest.find(r#"<span class=""#) {
+			rest = &rest[start + 13..];
+			let class_end = rest.find('"').unwrap();
+			let class = rest[..class_end].to_string();
+			rest = &rest[class_end + 2..];
+			let text_end = rest.find('<').unwrap();
+			spans.push((class, rest[..text_end].to_string()));
+			rest = &rest[text_end..];
+		}
+		spans.remove(0);
+		spans
+	}
+	fn has(spans: &[(String, String)], class: &str, text: &str) -> bool {
+		spans.iter().any(|(c, t)| c == class && t == text)
+	}
+
+	#[test]
+	fn test_languages() {
+		let js = spans(
+			"javascript",
+			"// Count\nconst n = await fetch(`/a`).then(r => r.json()); class A extends B {}\nlet s = 'it\\'s' + 0.5;",
+		);
+		assert!(has(&js, "comment", "// Count"));
+		assert!(has(&js, "storage type", "const"));
+		assert!(has(&js, "keyword control", "await"));
+		assert!(has(&js, "variable function", "fetch"));
+		assert!(has(&js, "string quoted other", "`/a`"));
+		assert!(has(&js, "entity name class", "A"));
+		assert!(has(&js, "support class", "B"));
+		assert!(has(&js, "string quoted single", "'it"));
+		assert!(has(&js, "constant character escape", "\\'"));
+		assert!(has(&js, "constant numeric", "0.5"));
+		assert!(has(&js, "keyword operator", "=&gt;"));
+
+		let rust = spans(
+			"rs",
+			"fn parse<'i>(s: &'i str) -> u8 { println!(\"{}\", 'x'); 1..2 }",
+		);
+		assert!(has(&rust, "storage type", "fn"));
+		assert!(has(&rust, "entity name function", "parse"));
+		assert!(has(&rust, "storage modifier lifetime", "'i"));
+		assert!(has(&rust, "support type", "u8"));
+		assert!(has(&rust, "support function macro", "println!"));
+		assert!(has(&rust, "string quoted single", "'x'"));
+		assert!(has(&rust, "constant numeric", "1"));
+
+		let shell = spans(
+			"bash",
+			"# Build\nif [ -z \"$HOME\" ]; then echo a#b ${PATH}; fi",
+		);
+		assert!(has(&shell, "comment", "# Build"));
+		assert!(has(&shell, "keyword control", "if"));
+		assert!(has(&shell, "string quoted double", "&quot;$HOME&quot;"));
+		assert!(has(&shell, "variable other", "${PATH}"));
+		assert!(!shell
+			.iter()
+			.any(|(c, t)| c == "comment" && t.contains("a#b")));
+
+		let json = spans("json", r#"{"key": ["a\n", 1e3, true, null]}"#);
+		assert!(has(&json, "support type property-name", "&quot;key&quot;"));
+		assert!(has(&json, "string quoted double", "&quot;a"));
+		assert!(has(&json, "constant numeric", "1e3"));
+		assert!(has(&json, "constant language", "null"));
+
+		let css = spans(
+			"css",
+			"@media (max-width: 600px) { nav > a.active:hover, #x::after { margin: -0.5em auto !important; color: #fdf6e3; } }",
+		);
+		assert!(has(&css, "keyword control at-rule", "@media"));
+		assert!(has(&css, "entity name tag", "nav"));
+		assert!(has(&css, "entity other attribute-name class", ".active"));
+		assert!(has(
+			&css,
+			"entity other attribute-name pseudo-class",
+			":hover"
+		));
+		assert!(has(&css, "entity other attribute-name id", "#x"));
+		assert!(has(
+			&css,
+			"entity other attribute-name pseudo-element",
+			"::after"
+		));
+		assert!(has(&css, "support type property-name", "margin"));
+		assert!(has(&css, "constant numeric", "-0.5"));
+		assert!(has(&css, "keyword other unit", "em"));
+		assert!(has(&css, "support constant property-value", "auto"));
+		assert!(has(&css, "keyword other important", "!important"));
+		assert!(has(&css, "constant other color", "#fdf6e3"));
+
+		let html = spans(
+			"html",
+			"<!DOCTYPE html>\n<!-- c -->\n<template id=x><p class=\"a\">&amp; Hi</p></template><script>let a = 1 < 2;</script>",
+		);
+		assert!(has(&html, "meta tag sgml doctype", "&lt;!DOCTYPE html&gt;"));
+		assert!(has(&html, "comment", "&lt;!-- c --&gt;"));
+		assert!(has(&html, "entity name tag", "template"));
+		assert!(has(&html, "entity other attribute-name", "id"));
+		assert!(has(&html, "string unquoted", "x"));
+		assert!(has(&html, "string quoted double", "&quot;a&quot;"));
+		assert!(has(&html, "constant character entity", "&amp;amp;"));
+		assert!(has(&html, "punctuation definition tag", "&lt;/"));
+		// The script is javascript.
+		assert!(has(&html, "storage type", "let"));
+		assert!(has(&html, "keyword operator", "&lt;"));
+		assert!(has(&html, "entity name tag", "script"));
+	}
+
+	#[test]
+	fn test_fallback() {
+		assert_eq!(highlight("brainfuck", "+[>.<]"), None);
+		assert_eq!(highlight("", "<b>"), None);
+		// Characters outside ASCII anywhere a token could start.
+		let text = "✓ a {} → .é #é :é @é !é $é 'é' \"é\" &é; <é>\n× {}";
+		for language in ["js", "rust", "bash", "json", "css", "html"] {
+			let html = highlight(language, text).unwrap();
+			for c in ['✓', '→', '×'] {
+				assert!(html.contains(c), "{} lost {}", language, c);
+			}
+		}
+		assert_eq!(
+			highlight("JS", "a<b"),
+			Some(
+				r#"<span class="source js">a<span class="keyword operator">&lt;</span>b</span>"#
+					.to_string()
+			)
+		);
+	}
+}
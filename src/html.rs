@@ -0,0 +1,601 @@
+use super::highlight::highlight;
+use super::image::Images;
+use super::parser::{reference_label, Attributes, Block, Inline, LinkTarget, Shortcode};
+use super::shortcode::Shortcodes;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::{self, Display};
+use std::io::{self, Write};
+
+// Elements that can't have children and don't get a closing tag.
+pub const VOID_ELEMENTS: [&str; 14] = [
+	"area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+	"track", "wbr",
+];
+
+/// Escapes text so that it can be used in either element content or a quoted attribute value.
+pub struct Escape<'a>(pub &'a str);
+impl Display for Escape<'_> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let mut last = 0;
+		for (i, c) in self.0.char_indices() {
+			let replacement = match c {
+				'&' => "&amp;",
+				'<' => "&lt;",
+				'>' => "&gt;",
+				'"' => "&quot;",
+				_ => continue,
+			};
+			f.write_str(&self.0[last..i])?;
+			f.write_str(replacement)?;
+			last = i + c.len_utf8();
+		}
+		f.write_str(&self.0[last..])
+	}
+}
+
+/// What a renderer needs to know about the document it's rendering.
+pub struct Context<'a, 'i> {
+	/// Directory of the source document, relative to the content directory and separated by '/'.
+	pub source_dir: &'a str,
+	/// Directory that the page is written to, relative to the output directory.
+	pub output_dir: &'a str,
+	pub references: &'a HashMap<String, (&'i str, Option<&'i str>)>,
+	pub shortcodes: &'a Shortcodes,
+	/// The content directory's images, for their sizes and other formats.
+	pub images: &'a Images,
+	/// The images that the document shows, by their path from the content directory.
+	pub used: RefCell<BTreeSet<String>>,
+}
+impl<'a, 'i> Context<'a, 'i> {
+	// Whether url is relative to the document rather than the site's root or another site.
+	fn is_relative(url: &str) -> bool {
+		let has_scheme = url
+			.split(|c| c == '/' || c == '?' || c == '#')
+			.next()
+			.map_or(false, |first| first.contains(':'));
+		!(url.is_empty() || url.starts_with('#') || url.starts_with('/') || has_scheme)
+	}
+	// The segments of a relative path from the content directory.
+	fn target<'s>(&'s self, path: &'s str) -> Vec<&'s str> {
+		let mut target = self
+			.source_dir
+			.split('/')
+			.filter(|s| !s.is_empty())
+			.collect::<Vec<_>>();
+		for segment in path.split('/') {
+			match segment {
+				"" | "." => {}
+				".." => {
+					target.pop();
+				}
+				s => target.push(s),
+			}
+		}
+		target
+	}
+	/// The path of the file that a relative url points to, from the content directory.
+	pub fn source_path(&self, url: &str) -> Option<String> {
+		if !Self::is_relative(url) || url.contains(&['?', '#'][..]) {
+			return None;
+		}
+		Some(self.target(url).join("/"))
+	}
+	/// Relative links are written relative to the source document, but the page might not end up next to it (blog/post.md becomes blog/post/index.html), so rewrite them to be relative to the output page instead.
+	pub fn resolve<'u>(&self, url: &'u str) -> Cow<'u, str> {
+		if !Self::is_relative(url) {
+			return Cow::Borrowed(url);
+		}
+		let (path, suffix) = url.split_at(url.find(|c| c == '?' || c == '#').unwrap_or(url.len()));
+		let mut target = self.target(path);
+		let mut is_dir = path.is_empty() || path.ends_with('/');
+		// Documents are written into a directory of their own.
+		if let Some(document) = target.last().and_then(|last| last.strip_suffix(".md")) {
+			target.pop();
+			if document != "index" {
+				target.push(document);
+			}
+			is_dir = true;
+		}
+		let output = self
+			.output_dir
+			.split('/')
+			.filter(|s| !s.is_empty())
+			.collect::<Vec<_>>();
+		let common = output
+			.iter()
+			.zip(target.iter())
+			.take_while(|(a, b)| a == b)
+			.count();
+		let mut ret = "../".repeat(output.len() - common);
+		ret.push_str(&target[common..].join("/"));
+		if is_dir && common < target.len() {
+			ret.push('/');
+		}
+		if ret.is_empty() {
+			ret.push_str("./");
+		}
+		ret.push_str(suffix);
+		Cow::Owned(ret)
+	}
+	fn shortcode(&self, shortcode: &Shortcode) -> io::Result<String> {
+		self.shortcodes
+			.render(shortcode)
+			.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+	}
+}
+
+fn render_attributes(o: &mut impl Write, attributes: &Attributes) -> io::Result<()> {
+	if let Some(id) = attributes.id {
+		write!(o, r#" id="{}""#, Escape(id))?;
+	}
+	if !attributes.classes.is_empty() {
+		write!(o, r#" class="{}""#, Escape(&attributes.classes.join(" ")))?;
+	}
+	// Sort the key / value pairs so that the output doesn't depend on the HashMap's order.
+	let mut kvs = attributes.attributes.iter().collect::<Vec<_>>();
+	kvs.sort();
+	for (k, v) in kvs {
+		write!(o, r#" {}="{}""#, k, Escape(v))?;
+	}
+	Ok(())
+}
+
+/// Only the text of some inlines, used where markup isn't allowed like an image's alt.
+fn plain_text(s: &mut String, inlines: &[Inline]) {
+	for inline in inlines {
+		match inline {
+			Inline::Text(t) => s.push_str(t),
+			Inline::Symbol(c) => s.push(*c),
+			Inline::LineBreak => s.push(' '),
+			Inline::RawHTML(_) | Inline::Shortcode(_) => {}
+			Inline::Span(children, _)
+			| Inline::Image(children, _, _)
+			| Inline::Link(children, _, _) => plain_text(s, children),
+			Inline::Strong(children)
+			| Inline::Emphasis(children)
+			| Inline::InlineCode(children)
+			| Inline::Inserted(children)
+			| Inline::Deleted(children)
+			| Inline::Marked(children)
+			| Inline::Cite(children)
+			| Inline::Superscript(children)
+			| Inline::Subscript(children) => plain_text(s, children),
+		}
+	}
+}
+fn render_plain(o: &mut impl Write, inlines: &[Inline]) -> io::Result<()> {
+	let mut s = String::new();
+	plain_text(&mut s, inlines);
+	write!(o, "{}", Escape(&s))
+}
+
+/// The text of a document's first paragraphs, cut off after length characters. Used when a document doesn't have a summary of its own.
+pub fn summary(blocks: &[Block], length: usize) -> String {
+	let mut s = String::new();
+	for block in blocks {
+		if let Block::Paragraph(_, inlines) = block {
+			if !s.is_empty() {
+				s.push(' ');
+			}
+			plain_text(&mut s, inlines);
+			if s.chars().count() > length {
+				break;
+			}
+		}
+	}
+	let s = s.split_whitespace().collect::<Vec<_>>().join(" ");
+	match s.char_indices().nth(length) {
+		Some((end, _)) => format!("{}…", s[..end].trim_end()),
+		None => s,
+	}
+}
+
+// An image that's in other formats too is a picture, so that browsers can pick the best one they show.
+// Images get their size so that the page doesn't move as they load, which they do once they're scrolled to.
+fn render_image(
+	o: &mut impl Write,
+	cx: &Context,
+	alt: &[Inline],
+	attributes: &Attributes,
+	src: &str,
+) -> io::Result<()> {
+	let path = cx.source_path(src);
+	let picture = path.as_deref().and_then(|p| cx.images.picture(p));
+	// The other formats are next to the image, so their urls only differ in the file name.
+	let url = |path: &str| {
+		let dir = &src[..src.rfind('/').map_or(0, |i| i + 1)];
+		let name = &path[path.rfind('/').map_or(0, |i| i + 1)..];
+		cx.resolve(&format!("{}{}", dir, name)).into_owned()
+	};
+	let mut img = cx.resolve(src).into_owned();
+	let mut size = None;
+	let is_picture = picture.as_ref().map_or(false, |p| !p.sources.is_empty());
+	if let Some(picture) = &picture {
+		let mut used = cx.used.borrow_mut();
+		used.insert(picture.src.to_string());
+		if is_picture {
+			write!(o, "<picture>")?;
+			for (path, mime_type) in &picture.sources {
+				used.insert(path.to_string());
+				write!(
+					o,
+					r#"<source srcset="{}" type="{}">"#,
+					Escape(&url(path)),
+					mime_type
+				)?;
+			}
+			img = url(picture.src);
+		}
+		size = picture.size;
+	}
+	write!(o, r#"<img src="{}" alt=""#, Escape(&img))?;
+	render_plain(o, alt)?;
+	write!(o, r#"""#)?;
+	render_attributes(o, attributes)?;
+	let has = |name| attributes.attributes.contains_key(name);
+	if let Some(size) = size.filter(|_| !has("width") && !has("height")) {
+		write!(o, r#" width="{}" height="{}""#, size.width, size.height)?;
+	}
+	if !has("loading") {
+		write!(o, r#" loading="lazy""#)?;
+	}
+	if !has("decoding") {
+		write!(o, r#" decoding="async""#)?;
+	}
+	write!(o, ">")?;
+	if is_picture {
+		write!(o, "</picture>")?;
+	}
+	Ok(())
+}
+
+pub fn render_inlines(o: &mut impl Write, cx: &Context, inlines: &[Inline]) -> io::Result<()> {
+	for inline in inlines {
+		let (tag, children) = match inline {
+			Inline::Text(t) => {
+				write!(o, "{}", Escape(t))?;
+				continue;
+			}
+			Inline::Symbol(c) => {
+				write!(o, "{}", Escape(c.encode_utf8(&mut [0; 4])))?;
+				continue;
+			}
+			Inline::RawHTML(html) => {
+				write!(o, "{}", html)?;
+				continue;
+			}
+			Inline::LineBreak => {
+				write!(o, "<br>")?;
+				continue;
+			}
+			Inline::Shortcode(shortcode) => {
+				write!(o, "{}", cx.shortcode(shortcode)?)?;
+				continue;
+			}
+			Inline::Image(alt, attributes, src) => {
+				render_image(o, cx, alt, attributes, src)?;
+				continue;
+			}
+			Inline::Span(children, attributes) => {
+				write!(o, "<span")?;
+				render_attributes(o, attributes)?;
+				write!(o, ">")?;
+				render_inlines(o, cx, children)?;
+				write!(o, "</span>")?;
+				continue;
+			}
+			Inline::Link(children, attributes, target) => {
+				let (url, title) = match target {
+					LinkTarget::Url(url, title) => (*url, *title),
+					LinkTarget::Reference(label, _)
+						if let Some((url, title)) = cx.references.get(&reference_label(label)) =>
+					{
+						(*url, *title)
+					}
+					LinkTarget::Reference(_, source) => {
+						// Without a definition this isn't a link, just what was written.
+						write!(o, "{}", Escape(source))?;
+						continue;
+					}
+				};
+				write!(o, r#"<a href="{}""#, Escape(&cx.resolve(url)))?;
+				if let Some(title) = title {
+					write!(o, r#" title="{}""#, Escape(title))?;
+				}
+				render_attributes(o, attributes)?;
+				write!(o, ">")?;
+				render_inlines(o, cx, children)?;
+				write!(o, "</a>")?;
+				continue;
+			}
+			Inline::Strong(children) => ("strong", children),
+			Inline::Emphasis(children) => ("em", children),
+			Inline::InlineCode(children) => ("code", children),
+			Inline::Inserted(children) => ("ins", children),
+			Inline::Deleted(children) => ("del", children),
+			Inline::Marked(children) => ("mark", children),
+			Inline::Cite(children) => ("cite", children),
+			Inline::Superscript(children) => ("sup", children),
+			Inline::Subscript(children) => ("sub", children),
+		};
+		write!(o, "<{}>", tag)?;
+		render_inlines(o, cx, children)?;
+		write!(o, "</{}>", tag)?;
+	}
+	Ok(())
+}
+
+fn render_list(
+	o: &mut impl Write,
+	cx: &Context,
+	tag: &str,
+	attributes: &Attributes,
+	items: &[Block],
+) -> io::Result<()> {
+	write!(o, "<{}", tag)?;
+	render_attributes(o, attributes)?;
+	writeln!(o, ">")?;
+	for item in items {
+		write!(o, "<li>")?;
+		render_block(o, cx, item)?;
+		writeln!(o, "</li>")?;
+	}
+	writeln!(o, "</{}>", tag)
+}
+
+pub fn render_block(o: &mut impl Write, cx: &Context, block: &Block) -> io::Result<()> {
+	match block {
+		Block::Paragraph(attributes, inlines) => {
+			write!(o, "<p")?;
+			render_attributes(o, attributes)?;
+			write!(o, ">")?;
+			render_inlines(o, cx, inlines)?;
+			writeln!(o, "</p>")
+		}
+		Block::Heading(level, attributes, inlines) => {
+			write!(o, "<h{}", level)?;
+			render_attributes(o, attributes)?;
+			write!(o, ">")?;
+			render_inlines(o, cx, inlines)?;
+			writeln!(o, "</h{}>", level)
+		}
+		Block::CodeBlock(language, attributes, code) => {
+			let highlighted = highlight(language, code);
+			write!(o, "<pre")?;
+			if highlighted.is_some() {
+				// The stylesheets give .code its colors.
+				let mut attributes = attributes.clone();
+				attributes.classes.insert(0, "code");
+				render_attributes(o, &attributes)?;
+			} else {
+				render_attributes(o, attributes)?;
+			}
+			write!(o, ">")?;
+			if language.is_empty() {
+				write!(o, "<code>")?;
+			} else {
+				write!(o, r#"<code class="language-{}">"#, Escape(language))?;
+			}
+			match highlighted {
+				Some(html) => writeln!(o, "{}</code></pre>", html),
+				None => writeln!(o, "{}</code></pre>", Escape(code)),
+			}
+		}
+		Block::HorizontalRule(attributes) => {
+			write!(o, "<hr")?;
+			render_attributes(o, attributes)?;
+			writeln!(o, ">")
+		}
+		Block::HtmlTag(tag, attributes, children) => {
+			write!(o, "<{}", tag)?;
+			render_attributes(o, attributes)?;
+			writeln!(o, ">")?;
+			if VOID_ELEMENTS.contains(tag) {
+				return Ok(());
+			}
+			render_blocks(o, cx, children)?;
+			writeln!(o, "</{}>", tag)
+		}
+		Block::UList(attributes, items) => render_list(o, cx, "ul", attributes, items),
+		Block::OList(attributes, items) => render_list(o, cx, "ol", attributes, items),
+		Block::Raw(html) => writeln!(o, "{}", html),
+		Block::Shortcode(shortcode) => writeln!(o, "{}", cx.shortcode(shortcode)?),
+	}
+}
+
+pub fn render_blocks(o: &mut impl Write, cx: &Context, blocks: &[Block]) -> io::Result<()> {
+	for block in blocks {
+		render_block(o, cx, block)?;
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::image::Size;
+	use crate::parser::parse_document;
+	use crate::parser2::Input;
+
+	fn render_at(s: &str, source_dir: &str, output_dir: &str) -> String {
+		let document = parse_document(&mut Input::from(s)).unwrap();
+		let cx = Context {
+			source_dir,
+			output_dir,
+			references: &document.references,
+			shortcodes: &Shortcodes::default(),
+			images: &Images::default(),
+			used: RefCell::default(),
+		};
+		let mut output = Vec::new();
+		render_blocks(&mut output, &cx, &document.blocks).unwrap();
+		String::from_utf8(output).unwrap()
+	}
+	fn render(s: &str) -> String {
+		render_at(s, "", "")
+	}
+
+	#[test]
+	fn test_escape() {
+		assert_eq!(
+			Escape(r#"<a href="x">Tom & Jerry's</a>"#).to_string(),
+			"&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry's&lt;/a&gt;"
+		);
+	}
+
+	#[test]
+	fn test_inlines() {
+		assert_eq!(
+			render("= Title\n\nSome **strong _and em_** text & `<code>`\n"),
+			"<p>Some <strong>strong <em>and em</em></strong> text &amp; <code>&lt;code&gt;</code></p>\n"
+		);
+		assert_eq!(
+			render("= Title\n\n![An **image** \"here\"](a.png).wide{loading: \"lazy\", decoding: \"async\"}\n"),
+			"<p><img src=\"a.png\" alt=\"An image &quot;here&quot;\" class=\"wide\" decoding=\"async\" loading=\"lazy\"></p>\n"
+		);
+		assert_eq!(
+			render("= Title\n\n<del>old</del> \\<not html\\> [span]#s.a.b\n"),
+			"<p><del>old</del> &lt;not html&gt; <span id=\"s\" class=\"a b\">span</span></p>\n"
+		);
+	}
+
+	#[test]
+	fn test_blocks() {
+		assert_eq!(
+			render("= Title\n\n==.big Heading\n\n---#rule\n\n```rust\nfn main() {}\n```\n"),
+			"<h2 class=\"big\">Heading</h2>\n<hr id=\"rule\">\n<pre class=\"code\"><code class=\"language-rust\"><span class=\"source rust\"><span class=\"storage type\">fn</span> <span class=\"entity name function\">main</span>() {}\n</span></code></pre>\n"
+		);
+		assert_eq!(
+			render("= Title\n\n```brainfuck\n<+>\n```\n"),
+			"<pre><code class=\"language-brainfuck\">&lt;+&gt;\n</code></pre>\n"
+		);
+		assert_eq!(
+			render("= Title\n\nsection.intro{data-x: \"1\"}\n\tarticle\n\t\tHello there.\n\tbr\n"),
+			"<section class=\"intro\" data-x=\"1\">\n<article>\n<p>Hello there.</p>\n</article>\n<br>\n</section>\n"
+		);
+	}
+
+	#[test]
+	fn test_links() {
+		assert_eq!(
+			render("= Title\n\nSee [the *spec*](https://promisesaplus.com/ \"Promises/A+\").external and <https://v8.dev>.\n"),
+			"<p>See <a href=\"https://promisesaplus.com/\" title=\"Promises/A+\" class=\"external\">the <em>spec</em></a> and <a href=\"https://v8.dev\">https://v8.dev</a>.</p>\n"
+		);
+		assert_eq!(
+			render("= Title\n\n[Web3.0-test][code] and [*missing*][nope].x and [Code][]\n\n[code]: https://github.com/evan-brass/web3.0-test 'Code'\n"),
+			"<p><a href=\"https://github.com/evan-brass/web3.0-test\" title=\"Code\">Web3.0-test</a> and [*missing*][nope].x and <a href=\"https://github.com/evan-brass/web3.0-test\" title=\"Code\">Code</a></p>\n"
+		);
+		// Labels are matched whatever their case and spacing.
+		assert_eq!(
+			render("= Title\n\n[a][The  Spec] [b][the\tspec ]\n\n[THE SPEC]: spec.html\n"),
+			"<p><a href=\"spec.html\">a</a> <a href=\"spec.html\">b</a></p>\n"
+		);
+	}
+
+	#[test]
+	fn test_summary() {
+		let summary = |s: &str, length| {
+			let document = parse_document(&mut Input::from(s)).unwrap();
+			super::summary(&document.blocks, length)
+		};
+		let s =
+			"= Title\n\n== Heading\n\nSome *bold* text.\n\n```\ncode\n```\n\nA [link](x) & more.\n";
+		assert_eq!(summary(s, 100), "Some bold text. A link & more.");
+		assert_eq!(summary(s, 15), "Some bold text.…");
+		assert_eq!(summary(s, 16), "Some bold text.…");
+		assert_eq!(summary("= Title\n", 10), "");
+	}
+
+	#[test]
+	fn test_resolve() {
+		// content/blog/reactivity.md is written to public/blog/reactivity/index.html
+		let references = HashMap::new();
+		let cx = Context {
+			source_dir: "blog",
+			output_dir: "blog/reactivity",
+			references: &references,
+			shortcodes: &Shortcodes::default(),
+			images: &Images::default(),
+			used: RefCell::default(),
+		};
+		assert_eq!(cx.resolve("https://v8.dev/blog"), "https://v8.dev/blog");
+		assert_eq!(cx.resolve("mailto:me@example.com"), "mailto:me@example.com");
+		assert_eq!(cx.resolve("/about/"), "/about/");
+		assert_eq!(cx.resolve("#detecting-change"), "#detecting-change");
+		assert_eq!(cx.resolve("calendar.md"), "../calendar/");
+		assert_eq!(
+			cx.resolve("./template-elements/index.md#why"),
+			"../template-elements/#why"
+		);
+		assert_eq!(
+			cx.resolve("../projects/Mandelbrot/eq1.mml"),
+			"../../projects/Mandelbrot/eq1.mml"
+		);
+		assert_eq!(cx.resolve("reactivity.md"), "./");
+		// content/blog/template-elements/index.md is written right next to its images.
+		let cx = Context {
+			source_dir: "blog/template-elements",
+			output_dir: "blog/template-elements",
+			references: &references,
+			shortcodes: &Shortcodes::default(),
+			images: &Images::default(),
+			used: RefCell::default(),
+		};
+		assert_eq!(cx.resolve("./get-single.png"), "get-single.png");
+		assert_eq!(cx.resolve("../reactivity.md?x=1"), "../reactivity/?x=1");
+		assert_eq!(
+			render_at(
+				"= T\n\n[Reactivity](../reactivity.md) ![x](a.png)\n",
+				"blog/t",
+				"blog/t"
+			),
+			"<p><a href=\"../reactivity/\">Reactivity</a> <img src=\"a.png\" alt=\"x\" loading=\"lazy\" decoding=\"async\"></p>\n"
+		);
+	}
+
+	#[test]
+	fn test_images() {
+		let mut images = Images::default();
+		let size = Some(Size {
+			width: 640,
+			height: 480,
+		});
+		images.add("blog/fsm/diagram.webp".to_string(), size);
+		images.add("blog/fsm/diagram.jpeg".to_string(), size);
+		images.add("blog/logo.png".to_string(), None);
+		// content/blog/fsm.md is written to public/blog/fsm/index.html
+		let document = parse_document(&mut Input::from(
+			"= T\n\n![A diagram](fsm/diagram.webp) ![Logo](logo.png){width: \"10\"} ![](https://example.com/x.png)\n",
+		))
+		.unwrap();
+		let cx = Context {
+			source_dir: "blog",
+			output_dir: "blog/fsm",
+			references: &document.references,
+			shortcodes: &Shortcodes::default(),
+			images: &images,
+			used: RefCell::default(),
+		};
+		let mut output = Vec::new();
+		render_blocks(&mut output, &cx, &document.blocks).unwrap();
+		assert_eq!(
+			String::from_utf8(output).unwrap(),
+			concat!(
+				"<p><picture><source srcset=\"diagram.webp\" type=\"image/webp\">",
+				"<img src=\"diagram.jpeg\" alt=\"A diagram\" width=\"640\" height=\"480\" loading=\"lazy\" decoding=\"async\"></picture> ",
+				"<img src=\"../logo.png\" alt=\"Logo\" width=\"10\" loading=\"lazy\" decoding=\"async\"> ",
+				"<img src=\"https://example.com/x.png\" alt=\"\" loading=\"lazy\" decoding=\"async\"></p>\n"
+			)
+		);
+		assert_eq!(
+			cx.used.into_inner().into_iter().collect::<Vec<_>>(),
+			[
+				"blog/fsm/diagram.jpeg",
+				"blog/fsm/diagram.webp",
+				"blog/logo.png"
+			]
+		);
+	}
+}
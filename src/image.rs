@@ -0,0 +1,224 @@
+use std::collections::BTreeMap;
+
+/// An image's intrinsic width and height, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Size {
+	pub width: u32,
+	pub height: u32,
+}
+
+fn u16_be(b: &[u8], at: usize) -> Option<u32> {
+	Some(u16::from_be_bytes(b.get(at..at + 2)?.try_into().ok()?) as u32)
+}
+fn u16_le(b: &[u8], at: usize) -> Option<u32> {
+	Some(u16::from_le_bytes(b.get(at..at + 2)?.try_into().ok()?) as u32)
+}
+fn u24_le(b: &[u8], at: usize) -> Option<u32> {
+	let b = b.get(at..at + 3)?;
+	Some(b[0] as u32 | (b[1] as u32) << 8 | (b[2] as u32) << 16)
+}
+fn u32_be(b: &[u8], at: usize) -> Option<u32> {
+	Some(u32::from_be_bytes(b.get(at..at + 4)?.try_into().ok()?))
+}
+fn u32_le(b: &[u8], at: usize) -> Option<u32> {
+	Some(u32::from_le_bytes(b.get(at..at + 4)?.try_into().ok()?))
+}
+
+/// Read the size from a PNG, GIF, JPEG or WebP's header. None for anything else, or if it's cut short.
+pub fn size(b: &[u8]) -> Option<Size> {
+	let (width, height) = if b.starts_with(b"\x89PNG\r\n\x1a\n") {
+		// The IHDR chunk is always first.
+		(u32_be(b, 16)?, u32_be(b, 20)?)
+	} else if b.starts_with(b"GIF87a") || b.starts_with(b"GIF89a") {
+		(u16_le(b, 6)?, u16_le(b, 8)?)
+	} else if b.starts_with(b"\xff\xd8") {
+		jpeg_size(b)?
+	} else if b.starts_with(b"RIFF") && b.get(8..12) == Some(b"WEBP") {
+		match b.get(12..16)? {
+			// Lossy: the size is in the key frame's header, after its start code.
+			b"VP8 " => (u16_le(b, 26)? & 0x3fff, u16_le(b, 28)? & 0x3fff),
+			// Lossless: 14 bits each, minus one, after the signature byte.
+			b"VP8L" => {
+				let bits = u32_le(b, 21)?;
+				((bits & 0x3fff) + 1, (bits >> 14 & 0x3fff) + 1)
+			}
+			// Extended: 24 bits each, minus one, after the flags.
+			b"VP8X" => (u24_le(b, 24)? + 1, u24_le(b, 27)? + 1),
+			_ => return None,
+		}
+	} else {
+		return None;
+	};
+	Some(Size { width, height })
+}
+
+// A JPEG's size is in its start of frame segment, which can come after any number of others.
+fn jpeg_size(b: &[u8]) -> Option<(u32, u32)> {
+	let mut at = 2;
+	loop {
+		if *b.get(at)? != 0xff {
+			return None;
+		}
+		// Markers can be padded with any number of 0xff.
+		while *b.get(at + 1)? == 0xff {
+			at += 1;
+		}
+		let marker = *b.get(at + 1)?;
+		match marker {
+			// Restart markers and TEM don't have a length.
+			0x01 | 0xd0..=0xd7 => at += 2,
+			// Start of frame, except for DHT, JPG and DAC which share the range.
+			0xc0..=0xcf if !matches!(marker, 0xc4 | 0xc8 | 0xcc) => {
+				return Some((u16_be(b, at + 7)?, u16_be(b, at + 5)?));
+			}
+			// The image data starts without a frame.
+			0xd9 | 0xda => return None,
+			_ => at += 2 + u16_be(b, at + 2)? as usize,
+		}
+	}
+}
+
+/// The MIME type for an image's extension.
+pub fn mime_type(extension: &str) -> Option<&'static str> {
+	match extension.to_ascii_lowercase().as_str() {
+		"avif" => Some("image/avif"),
+		"webp" => Some("image/webp"),
+		"png" => Some("image/png"),
+		"jpg" | "jpeg" => Some("image/jpeg"),
+		"gif" => Some("image/gif"),
+		"svg" => Some("image/svg+xml"),
+		_ => None,
+	}
+}
+
+// Formats that not every browser shows, which need a fallback, best first.
+const MODERN: [&str; 2] = ["image/avif", "image/webp"];
+
+fn extension(path: &str) -> Option<&str> {
+	let name = &path[path.rfind('/').map_or(0, |i| i + 1)..];
+	name.rfind('.').map(|i| &name[i + 1..])
+}
+
+/// How to show an image: the img's src, and the sources that browsers should pick from before it.
+#[derive(Debug, PartialEq)]
+pub struct Picture<'p> {
+	pub src: &'p str,
+	pub size: Option<Size>,
+	// (path, MIME type), best first
+	pub sources: Vec<(&'p str, &'static str)>,
+}
+
+/// Every image in the content directory, by its path from there, separated by '/'.
+#[derive(Debug, Default, Hash)]
+pub struct Images {
+	images: BTreeMap<String, Option<Size>>,
+}
+impl Images {
+	pub fn add(&mut self, path: String, size: Option<Size>) {
+		self.images.insert(path, size);
+	}
+	pub fn paths(&self) -> impl Iterator<Item = &str> {
+		self.images.keys().map(String::as_str)
+	}
+	/// The same image in the other formats next to it: diagram.webp for diagram.jpeg.
+	pub fn formats<'s>(&'s self, path: &'s str) -> impl Iterator<Item = &'s str> {
+		let stem = path
+			.rfind('.')
+			.filter(|&i| i > path.rfind('/').map_or(0, |s| s + 1))
+			.map_or(path, |i| &path[..i + 1]);
+		self.images
+			.range(stem.to_string()..)
+			.map(|(p, _)| p.as_str())
+			.take_while(move |p| p.starts_with(stem))
+			.filter(move |p| *p != path && !p[stem.len()..].contains(&['.', '/'][..]))
+	}
+	/// How to show the image at path. Modern formats are offered as sources, with one that every browser shows as the img.
+	pub fn picture<'s>(&'s self, path: &'s str) -> Option<Picture<'s>> {
+		let size = *self.images.get(path)?;
+		let mime = |p: &str| extension(p).and_then(mime_type);
+		let mut candidates = Some(path)
+			.into_iter()
+			.chain(self.formats(path))
+			.collect::<Vec<_>>();
+		// The referenced image is the fallback, unless it needs one itself.
+		let src = candidates
+			.iter()
+			.copied()
+			.find(|p| mime(p).map_or(false, |m| !MODERN.contains(&m)))
+			.unwrap_or(path);
+		candidates.retain(|p| *p != src);
+		let mut sources = candidates
+			.into_iter()
+			.filter_map(|p| Some((p, mime(p).filter(|m| MODERN.contains(m))?)))
+			.collect::<Vec<_>>();
+		sources.sort_by_key(|(_, m)| MODERN.iter().position(|modern| modern == m));
+		Some(Picture {
+			src,
+			size: self.images.get(src).copied().flatten().or(size),
+			sources,
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_size() {
+		let size = |width, height| Some(Size { width, height });
+		let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+		png.extend([0, 0, 2, 0x80, 0, 0, 1, 0xe0]);
+		assert_eq!(super::size(&png), size(640, 480));
+		assert_eq!(super::size(b"GIF89a\x10\0\x08\0"), size(16, 8));
+		// An APP0 segment before the frame, and padding before the frame's marker
+		let jpeg = b"\xff\xd8\xff\xe0\0\x04ab\xff\xff\xc0\0\x11\x08\x01\x2c\x01\x90";
+		assert_eq!(super::size(jpeg), size(400, 300));
+		let mut webp = b"RIFF\0\0\0\0WEBPVP8X\x0a\0\0\0\x10\0\0\0".to_vec();
+		webp.extend([0x7f, 0x02, 0, 0xdf, 0x01, 0]);
+		assert_eq!(super::size(&webp), size(640, 480));
+		let mut webp = b"RIFF\0\0\0\0WEBPVP8L\0\0\0\0\x2f".to_vec();
+		webp.extend((639u32 | 479 << 14).to_le_bytes());
+		assert_eq!(super::size(&webp), size(640, 480));
+		assert_eq!(super::size(&png[..20]), None);
+		assert_eq!(super::size(b"<svg></svg>"), None);
+	}
+
+	#[test]
+	fn test_picture() {
+		let size = Some(Size {
+			width: 2,
+			height: 1,
+		});
+		let mut images = Images::default();
+		for path in [
+			"a/d.webp",
+			"a/d.jpeg",
+			"a/d.avif",
+			"a/d.x.png",
+			"a/logo.png",
+			"b/d.png",
+		] {
+			images.add(path.to_string(), size);
+		}
+		assert_eq!(
+			images.picture("a/d.webp"),
+			Some(Picture {
+				src: "a/d.jpeg",
+				size,
+				sources: vec![("a/d.avif", "image/avif"), ("a/d.webp", "image/webp")],
+			})
+		);
+		assert_eq!(images.picture("a/d.jpeg"), images.picture("a/d.webp"));
+		assert_eq!(images.formats("a/d.x.png").count(), 0);
+		assert_eq!(
+			images.picture("a/logo.png"),
+			Some(Picture {
+				src: "a/logo.png",
+				size,
+				sources: vec![],
+			})
+		);
+		assert_eq!(images.picture("a/missing.png"), None);
+	}
+}
@@ -0,0 +1,279 @@
+use std::borrow::Cow;
+use std::io::{self, Write};
+
+use super::{AttributeError, Attributes, Kind, AST};
+use crate::html::{Escape, VOID_ELEMENTS};
+
+fn is_block(kind: &Kind) -> bool {
+	matches!(kind,
+		Kind::Heading(_) | Kind::Paragraph |
+		Kind::Tag(_) | Kind::CodeBlock(_) |
+		Kind::BlockQuote | Kind::OrderedList |
+		Kind::UnorderedList
+	)
+}
+fn is_list(kind: Option<&Kind>) -> bool {
+	matches!(kind, Some(Kind::OrderedList | Kind::UnorderedList))
+}
+fn element<'i>(kind: &Kind<'i>) -> Cow<'i, str> {
+	Cow::Borrowed(match kind {
+		Kind::Strong => "strong",
+		Kind::Emphasis => "em",
+		Kind::Mark => "mark",
+		Kind::Code => "code",
+		Kind::Quote => "q",
+		Kind::Cite => "cite",
+		Kind::Insert => "ins",
+		Kind::Delete => "del",
+		Kind::Super => "sup",
+		Kind::Sub => "sub",
+		Kind::Span => "span",
+		Kind::Link(_) => "a",
+		Kind::Heading(level) => return Cow::Owned(format!("h{}", level)),
+		Kind::Paragraph => "p",
+		Kind::Tag(name) => name,
+		// The language goes on the inner <code>
+		Kind::CodeBlock(_) => "pre",
+		Kind::BlockQuote => "blockquote",
+		Kind::OrderedList => "ol",
+		Kind::UnorderedList => "ul"
+	})
+}
+fn write_attributes(w: &mut impl Write, attributes: &Attributes) -> io::Result<()> {
+	if let Some(id) = attributes.id {
+		write!(w, r#" id="{}""#, Escape(id))?;
+	}
+	if !attributes.classes.is_empty() {
+		write!(w, r#" class="{}""#, Escape(&attributes.classes.join(" ")))?;
+	}
+	// HashMap order isn't stable, so sort to keep the output reproducible.
+	let mut attributes: Vec<_> = attributes.attributes.iter().collect();
+	attributes.sort();
+	for (key, value) in attributes {
+		if value.is_empty() {
+			write!(w, " {}", key)?;
+		} else {
+			write!(w, r#" {}="{}""#, key, Escape(value))?;
+		}
+	}
+	Ok(())
+}
+
+/// Write the HTML for a stream of events as they arrive, without building a tree. The first malformed attributes stop it with an InvalidData error.
+pub fn push_html<'i>(iter: impl Iterator<Item = Result<AST<'i>, AttributeError>>, w: &mut impl Write) -> io::Result<()> {
+	// Which elements are open, so that the children of a list can be wrapped in <li>
+	let mut stack: Vec<Kind<'i>> = Vec::new();
+	// Each block in a list is an item of its own, but a run of inline events is one item between them.
+	let mut item_open = false;
+	for event in iter {
+		let event = event.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+		let in_list = is_list(stack.last());
+		let own_item = in_list && match &event {
+			AST::Start(_, kind) => is_block(kind),
+			AST::Rule(_) => true,
+			_ => false
+		};
+		if in_list {
+			if item_open && (own_item || matches!(event, AST::End(_))) {
+				w.write_all(b"</li>")?;
+				item_open = false;
+			}
+			if own_item {
+				w.write_all(b"<li>")?;
+			} else if !item_open && !matches!(event, AST::End(_)) {
+				w.write_all(b"<li>")?;
+				item_open = true;
+			}
+		}
+		match event {
+			AST::Text(text) => {
+				write!(w, "{}", Escape(text))?;
+			},
+			AST::Start(attributes, kind) => {
+				write!(w, "<{}", element(&kind))?;
+				if let Kind::Link(link) = &kind {
+					write!(w, r#" href="{}""#, Escape(link.url))?;
+					if let Some(title) = link.title {
+						write!(w, r#" title="{}""#, Escape(title))?;
+					}
+				}
+				if let Some(attributes) = &attributes {
+					write_attributes(w, attributes)?;
+				}
+				w.write_all(b">")?;
+				if let Kind::CodeBlock(language) = kind {
+					if language.is_empty() {
+						w.write_all(b"<code>")?;
+					} else {
+						write!(w, r#"<code class="language-{}">"#, Escape(language))?;
+					}
+				}
+				stack.push(kind);
+				// A Start is closed by its End, so it's too early to close the <li>
+				continue;
+			},
+			AST::End(kind) => {
+				stack.pop();
+				let name = element(&kind);
+				if let Kind::CodeBlock(_) = kind {
+					w.write_all(b"</code></pre>")?;
+				} else if !VOID_ELEMENTS.contains(&&*name) {
+					write!(w, "</{}>", name)?;
+				}
+				if is_block(&kind) && is_list(stack.last()) {
+					w.write_all(b"</li>")?;
+				}
+				if is_block(&kind) {
+					w.write_all(b"\n")?;
+				}
+				continue;
+			},
+			AST::Rule(attributes) => {
+				w.write_all(b"<hr")?;
+				write_attributes(w, &attributes)?;
+				w.write_all(b">")?;
+			},
+			AST::Image(attributes, alt, src) => {
+				write!(w, r#"<img src="{}" alt="{}""#, Escape(src), Escape(alt))?;
+				write_attributes(w, &attributes)?;
+				w.write_all(b">")?;
+			},
+			AST::LineBreak => {
+				w.write_all(b"<br>\n")?;
+			}
+		}
+		if own_item {
+			w.write_all(b"</li>")?;
+		}
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::justwrite::{Link, Parser};
+	use std::collections::HashMap;
+
+	fn render(events: Vec<AST>) -> String {
+		let mut out = Vec::new();
+		push_html(events.into_iter().map(Ok), &mut out).unwrap();
+		String::from_utf8(out).unwrap()
+	}
+
+	#[test]
+	fn inlines() {
+		let link = Kind::Link(Link { url: "https://example.com/?a&b", title: Some("Example") });
+		assert_eq!(render(vec![
+			AST::Start(None, Kind::Paragraph),
+			AST::Text("Some "),
+			AST::Start(None, Kind::Strong),
+			AST::Text("<strong>"),
+			AST::End(Kind::Strong),
+			AST::Text(" text"),
+			AST::LineBreak,
+			AST::Start(None, link),
+			AST::Start(None, Kind::Super),
+			AST::Text("1"),
+			AST::End(Kind::Super),
+			AST::End(link),
+			AST::Image(Attributes::default(), "A \"quote\"", "image.webp"),
+			AST::End(Kind::Paragraph),
+		]), concat!(
+			"<p>Some <strong>&lt;strong&gt;</strong> text<br>\n",
+			r#"<a href="https://example.com/?a&amp;b" title="Example"><sup>1</sup></a>"#,
+			r#"<img src="image.webp" alt="A &quot;quote&quot;"></p>"#, "\n"
+		));
+	}
+
+	#[test]
+	fn blocks() {
+		let attributes = Attributes {
+			id: Some("top"),
+			classes: vec!["wide", "dark"],
+			attributes: vec![("style", "color: red".into()), ("data-x", "1".into()), ("open", "".into())].into_iter().collect()
+		};
+		assert_eq!(render(vec![
+			AST::Start(Some(attributes), Kind::Tag("section")),
+			AST::Start(None, Kind::Heading(2)),
+			AST::Text("Title"),
+			AST::End(Kind::Heading(2)),
+			AST::Start(None, Kind::BlockQuote),
+			AST::Text("Quoted"),
+			AST::End(Kind::BlockQuote),
+			AST::Start(None, Kind::CodeBlock("rust")),
+			AST::Text("if a < b {}"),
+			AST::End(Kind::CodeBlock("rust")),
+			AST::Start(None, Kind::Tag("br")),
+			AST::End(Kind::Tag("br")),
+			AST::End(Kind::Tag("section")),
+			AST::Rule(Attributes::default()),
+		]), concat!(
+			r#"<section id="top" class="wide dark" data-x="1" open style="color: red">"#,
+			"<h2>Title</h2>\n",
+			"<blockquote>Quoted</blockquote>\n",
+			r#"<pre><code class="language-rust">if a &lt; b {}</code></pre>"#, "\n",
+			"<br>\n",
+			"</section>\n",
+			"<hr>"
+		));
+	}
+
+	#[test]
+	fn lists() {
+		assert_eq!(render(vec![
+			AST::Start(None, Kind::UnorderedList),
+			AST::Start(None, Kind::Paragraph),
+			AST::Text("One"),
+			AST::End(Kind::Paragraph),
+			AST::Text("Two"),
+			AST::Start(None, Kind::OrderedList),
+			AST::Text("Nested"),
+			AST::End(Kind::OrderedList),
+			AST::End(Kind::UnorderedList),
+		]), "<ul><li><p>One</p></li>\n<li>Two</li><li><ol><li>Nested</li></ol></li>\n</ul>\n");
+	}
+
+	#[test]
+	fn list_items_with_inlines() {
+		let link = Kind::Link(Link { url: "/three", title: None });
+		assert_eq!(render(vec![
+			AST::Start(None, Kind::OrderedList),
+			AST::Start(None, Kind::Paragraph),
+			AST::Text("One"),
+			AST::End(Kind::Paragraph),
+			AST::Text("Two "),
+			AST::Start(None, Kind::Strong),
+			AST::Text("bold"),
+			AST::End(Kind::Strong),
+			AST::Rule(Attributes::default()),
+			AST::Start(None, link),
+			AST::Text("Three"),
+			AST::End(link),
+			AST::Image(Attributes::default(), "", "3.webp"),
+			AST::End(Kind::OrderedList),
+		]), concat!(
+			"<ol><li><p>One</p></li>\n",
+			"<li>Two <strong>bold</strong></li>",
+			"<li><hr></li>",
+			r#"<li><a href="/three">Three</a><img src="3.webp" alt=""></li></ol>"#, "\n"
+		));
+	}
+	#[test]
+	fn from_source() {
+		let source = "section.intro\n\tSee [the docs](/docs).\n\n---.wide\n";
+		let mut out = Vec::new();
+		push_html(Parser::new(source), &mut out).unwrap();
+		assert_eq!(String::from_utf8(out).unwrap(), concat!(
+			r#"<section class="intro"><p>See <a href="/docs">the docs</a>.</p>"#, "\n",
+			"</section>\n",
+			r#"<hr class="wide">"#
+		));
+
+		// Malformed attributes are an error rather than being left out of the page.
+		let mut out = Vec::new();
+		let e = push_html(Parser::new("Text\n\n--- {a: \"x\n"), &mut out).unwrap_err();
+		assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+		assert!(e.to_string().contains("a closing '\"'"), "{}", e);
+	}
+}
@@ -1,6 +1,7 @@
 use std::str::Lines;
 use std::iter::Peekable;
 
+use super::{ctori, Attributes};
 use crate::packrat::{LexRes, Lexer as LexerTrait};
 
 const CONTROLS: &'static str = "*_`+-^~#\"'@![]()=";
@@ -10,87 +11,137 @@ pub enum Token<'i> {
 	Indent, Dedent,
 	Newline, BlankLine,
 	Control(char),
-	Tag<&'i str>
+	Tag(&'i str)
 }
 enum LexerState {
+	// Between lines
 	Newline,
+	// Matching the line against the first n levels of indentation
 	Indent(usize),
+	// The attributes that follow a tag
+	Attributes,
 	Str
 }
 pub struct Lexer<'i> {
 	state: LexerState,
 	lines: Peekable<Lines<'i>>,
+	// What's left of the current line
+	line: &'i str,
 	indentation: Vec<&'i str>
 }
+fn is_blank(line: &&str) -> bool {
+	line.trim_start().is_empty()
+}
+// A line that holds nothing but a tag name and its attributes opens a tag.
+fn tag(line: &str) -> Option<(&str, &str)> {
+	if !line.starts_with(|c: char| c.is_ascii_alphabetic()) {
+		return None;
+	}
+	let rest = line.trim_start_matches(ctori);
+	let mut attributes = rest;
+	match Attributes::new(&mut attributes) {
+		Ok(_) if attributes.is_empty() => Some((&line[..(line.len() - rest.len())], rest)),
+		_ => None
+	}
+}
+impl<'i> Lexer<'i> {
+	// What's left of the line after the last Text or Control. Urls are full of control characters, so links are read from here instead of from the tokens.
+	pub fn rest(&self) -> &'i str {
+		self.line
+	}
+	pub fn set_rest(&mut self, rest: &'i str) {
+		self.line = rest;
+	}
+}
 impl<'i> Iterator for Lexer<'i> {
-	type Item = LexRes<'i, Token, ()>;
+	type Item = LexRes<'i, Token<'i>, ()>;
 	fn next(&mut self) -> Option<Self::Item> {
-		if let Some(l) = self.lines.peek() {
+		loop {
 			match self.state {
 				LexerState::Newline => {
-					self.state = LexerState::Indent(0);
-					return Some(LexRes::Token(if l.trim_start().is_empty() {
-						self.lines.next();
-						while self.lines.peek().map(|s| s.trim_start().is_empty()).unwrap_or(false) {
-							self.lines.next();
-						}
-						Token::BlankLine
+					let mut blank = false;
+					while self.lines.next_if(is_blank).is_some() {
+						blank = true;
+					}
+					if let Some(l) = self.lines.next() {
+						self.line = l;
+						self.state = LexerState::Indent(0);
+						return Some(LexRes::Token(if blank {
+							Token::BlankLine
+						} else {
+							Token::Newline
+						}));
 					} else {
-						Token::Newline
-					}))
+						// Close any indentation that's still open at the end of the input.
+						return self.indentation.pop().map(|_| LexRes::Token(Token::Dedent));
+					}
 				},
 				LexerState::Indent(i) => {
-					
-					self.lines.peek_mut()
+					if i < self.indentation.len() {
+						// Match against our current indentation
+						if let Some(rest) = self.line.strip_prefix(self.indentation[i]) {
+							self.line = rest;
+							self.state = LexerState::Indent(i + 1);
+						} else {
+							self.indentation.pop();
+							return Some(LexRes::Token(Token::Dedent));
+						}
+					} else {
+						self.state = LexerState::Str;
+						// Check if there's any more indentation
+						let no_indent = self.line.trim_start();
+						if no_indent.len() < self.line.len() {
+							self.indentation.push(&self.line[..(self.line.len() - no_indent.len())]);
+							self.line = no_indent;
+							return Some(LexRes::Token(Token::Indent));
+						}
+						if let Some((name, attributes)) = tag(self.line) {
+							self.line = attributes;
+							self.state = LexerState::Attributes;
+							return Some(LexRes::Token(Token::Tag(name)));
+						}
+					}
+				},
+				LexerState::Attributes => {
+					self.state = LexerState::Newline;
+					if !self.line.is_empty() {
+						return Some(LexRes::Text(self.line));
+					}
 				},
 				LexerState::Str => {
-	
-				}
-			}
-		} else if self.indentation.len() > 0 {
-			self.indentation.pop();
-			Some(LexRes::Token(Token::Dedent))
-		} else {
-			None
-		}
-		if let Some(l) = self.lines.peek() {
-			// Send a newline / blankline if needed
-			if !self.sent_newline {
-				self.sent_newline = true;
-				
-			}
-			// Match against our current indentation
-			let mut line = *l;
-			for indent in self.indentation.iter() {
-				if let Some(rest) = line.strip_prefix(*indent) {
-					line = rest;
-				} else {
-					self.indentation.pop();
-					return Some(LexRes::Token(Token::Dedent));
+					let mut chars = self.line.chars();
+					match chars.next() {
+						None => {
+							self.state = LexerState::Newline;
+						},
+						Some(c) if CONTROLS.contains(c) => {
+							self.line = chars.as_str();
+							return Some(LexRes::Token(Token::Control(c)));
+						},
+						Some(_) => {
+							let end = self.line.find(|c| CONTROLS.contains(c)).unwrap_or(self.line.len());
+							let text = &self.line[..end];
+							self.line = &self.line[end..];
+							return Some(LexRes::Text(text));
+						}
+					}
 				}
 			}
-			// Check if there's any more indentation
-			let no_indent = line.trim_start();
-			if no_indent.len() < line.len() {
-				self.indentation.push(&line[..(line.len() - no_indent.len())]);
-				return Some(LexRes::Token(Token::Indent));
-			}
-			// Return the rest of the line
-			self.sent_newline = false;
-			self.lines.next();
-			// TODO: Handle symbols.
-			return Some(LexRes::Text(no_indent))
 		}
 	}
 }
 impl<'i> LexerTrait<'i> for Lexer<'i> {
-	type Token = Token;
+	type Token = Token<'i>;
 	type LexError = ();
 	fn new(s: &'i str) -> Self {
+		let mut lines = s.lines().peekable();
+		// Blank lines before the first line don't separate anything.
+		while lines.next_if(is_blank).is_some() {}
 		Self {
-			lines: s.lines().peekable(),
-			indentation: Vec::new(),
-			state: LexState::Indent(0)
+			state: LexerState::Indent(0),
+			line: lines.next().unwrap_or(""),
+			lines,
+			indentation: Vec::new()
 		}
 	}
 }
@@ -98,7 +149,7 @@ impl<'i> LexerTrait<'i> for Lexer<'i> {
 #[cfg(test)]
 mod tests {
 	use super::*;
-	use Token::{Indent, Dedent, Newline, BlankLine};
+	use Token::{Indent, Dedent, Newline, BlankLine, Control, Tag};
 
 	#[test]
 	fn t1() {
@@ -133,4 +184,42 @@ a multi line string.
 			]
 		);
 	}
-}
\ No newline at end of file
+
+	#[test]
+	fn t2() {
+		let input = "
+section.intro#top
+	Some *strong* text!
+
+	= A [link](url)
+Hello.
+";
+		assert_eq!(
+			Lexer::new(input).collect::<Vec<_>>(),
+			vec![
+				LexRes::Token(Tag("section")),
+				LexRes::Text(".intro#top"),
+				LexRes::Token(Newline),
+				LexRes::Token(Indent),
+				LexRes::Text("Some "),
+				LexRes::Token(Control('*')),
+				LexRes::Text("strong"),
+				LexRes::Token(Control('*')),
+				LexRes::Text(" text"),
+				LexRes::Token(Control('!')),
+				LexRes::Token(BlankLine),
+				LexRes::Token(Control('=')),
+				LexRes::Text(" A "),
+				LexRes::Token(Control('[')),
+				LexRes::Text("link"),
+				LexRes::Token(Control(']')),
+				LexRes::Token(Control('(')),
+				LexRes::Text("url"),
+				LexRes::Token(Control(')')),
+				LexRes::Token(Newline),
+				LexRes::Token(Dedent),
+				LexRes::Text("Hello."),
+			]
+		);
+	}
+}
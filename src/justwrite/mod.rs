@@ -1,9 +1,14 @@
-use std::collections::HashMap;
-use std::iter::Peekable;
+#![allow(unused)]
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::fmt::Display;
 use std::str::pattern::Pattern;
 
+pub mod html;
 mod lexer;
 use crate::packrat::{LexRes, Lexer as LexerTrait};
+use crate::parser::trim_bare_url;
 use lexer::{Lexer, Token};
 
 pub fn take_prefix<'i, P: Pattern<'i>>(input: &mut &'i str, p: P) -> Option<&'i str> {
@@ -24,39 +29,206 @@ pub fn take_until<'i, P: Pattern<'i>>(input: &mut &'i str, p: P) -> &'i str {
 	}
 }
 
-#[derive(Debug, Clone, PartialEq)]
-struct Attributes<'i> {
+fn ctori(c: char) -> bool {
+	c.is_ascii_alphanumeric() || c == '-' || c == '_'
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttributeError {
+	// Byte offset into the attributes, or into the whole document when it comes from the Parser
+	pub at: usize,
+	pub expected: &'static str
+}
+impl Display for AttributeError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "Malformed attributes, expected {} at {}", self.expected, self.at)
+	}
+}
+impl Error for AttributeError {}
+
+// Where in the input an attribute failed to parse, and what was expected there.
+type AttributeResult<'i, T> = Result<T, (&'i str, &'static str)>;
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Attributes<'i> {
 	id: Option<&'i str>,
 	classes: Vec<&'i str>,
-	attributes: HashMap<&'i str, &'i str>
+	// Boolean attributes have an empty value. Values with escapes in them have to be owned.
+	attributes: HashMap<&'i str, Cow<'i, str>>
 }
 impl<'i> Attributes<'i> {
-	fn new(input: &mut &'i str) -> Self {
-		let mut id = None;
-		if take_prefix(input, "#").is_some() {
-			id = Some(take_until(input, |c: char| !c.is_ascii_alphanumeric()));
+	// Any number of #id, .class and {key: "value"} in any order. Only a malformed {...} is an error, a lone '.' or '#' (like the period at the end of a sentence) just isn't part of the attributes.
+	pub fn new(input: &mut &'i str) -> Result<Self, AttributeError> {
+		let mut rest = *input;
+		let mut ret = Self::default();
+		loop {
+			let before = rest;
+			if take_prefix(&mut rest, '#').is_some() {
+				let id = take_until(&mut rest, |c: char| !ctori(c));
+				if !id.is_empty() {
+					ret.id = Some(id);
+					continue;
+				}
+			} else if take_prefix(&mut rest, '.').is_some() {
+				let class = take_until(&mut rest, |c: char| !ctori(c));
+				if !class.is_empty() {
+					ret.classes.push(class);
+					continue;
+				}
+			} else if take_prefix(&mut rest, '{').is_some() {
+				if let Err((at, expected)) = Self::pairs(&mut rest, &mut ret.attributes) {
+					return Err(AttributeError {
+						at: input.len() - at.len(),
+						expected
+					});
+				}
+				continue;
+			}
+			rest = before;
+			break;
 		}
-		let mut classes = Vec::new();
-		while take_prefix(input, ".").is_some() {
-			classes.push(take_until(input, |c: char| !c.is_ascii_alphanumeric()));
+		*input = rest;
+		Ok(ret)
+	}
+	// The rest of a {key: "value", other: x, boolean} block
+	fn pairs(input: &mut &'i str, attributes: &mut HashMap<&'i str, Cow<'i, str>>) -> AttributeResult<'i, ()> {
+		loop {
+			*input = input.trim_start();
+			if take_prefix(input, '}').is_some() {
+				return Ok(());
+			}
+			let key = take_until(input, |c: char| !ctori(c));
+			if key.is_empty() {
+				return Err((input, "an attribute name or '}'"));
+			}
+			let value = if let Some(rest) = input.trim_start().strip_prefix(':') {
+				*input = rest.trim_start();
+				Self::value(input)?
+			} else {
+				Cow::Borrowed("")
+			};
+			attributes.insert(key, value);
+			let rest = input.trim_start();
+			if let Some(rest) = rest.strip_prefix(',') {
+				*input = rest;
+			} else if !rest.starts_with('}') && rest.len() == input.len() {
+				return Err((input, "',' or '}'"));
+			}
 		}
-		let mut attributes = HashMap::new();
-		if take_prefix(input, "{").is_some() {
-			unimplemented!()
+	}
+	fn value(input: &mut &'i str) -> AttributeResult<'i, Cow<'i, str>> {
+		if let Some(quoted) = input.strip_prefix('"') {
+			let mut owned: Option<String> = None;
+			let mut chars = quoted.char_indices();
+			while let Some((i, c)) = chars.next() {
+				match c {
+					'"' => {
+						let value = match owned {
+							Some(s) => Cow::Owned(s),
+							None => Cow::Borrowed(&quoted[..i])
+						};
+						*input = &quoted[i + 1..];
+						return Ok(value);
+					},
+					'\\' => {
+						let s = owned.get_or_insert_with(|| quoted[..i].to_string());
+						match chars.next() {
+							Some((_, escaped)) => s.push(escaped),
+							None => break
+						}
+					},
+					c => {
+						if let Some(s) = &mut owned {
+							s.push(c);
+						}
+					}
+				}
+			}
+			Err((input, "a closing '\"'"))
+		} else {
+			let value = take_until(input, |c: char| c.is_whitespace() || c == ',' || c == '}' || c == '"');
+			if value.is_empty() {
+				Err((input, "an attribute value"))
+			} else {
+				Ok(Cow::Borrowed(value))
+			}
 		}
-		Self { id, classes, attributes }
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Link<'i> {
+	url: &'i str,
+	title: Option<&'i str>
+}
+impl<'i> Link<'i> {
+	// The (url "title") that follows a link's [text].
+	fn destination(input: &mut &'i str) -> Option<Self> {
+		let mut rest = *input;
+		take_prefix(&mut rest, "(")?;
+		let url = take_until(&mut rest, |c: char| c == ')' || c.is_whitespace());
+		let title = Self::title(&mut rest);
+		take_prefix(&mut rest, ")")?;
+		*input = rest;
+		Some(Self { url, title })
+	}
+	fn title(input: &mut &'i str) -> Option<&'i str> {
+		let mut rest = input.trim_start();
+		let quote = take_prefix(&mut rest, |c: char| c == '"' || c == '\'')?;
+		let title = take_until(&mut rest, quote);
+		take_prefix(&mut rest, quote)?;
+		*input = rest;
+		Some(title)
+	}
+	// <scheme:...> or a bare http(s) url.
+	fn autolink(input: &mut &'i str) -> Option<Self> {
+		let mut rest = *input;
+		let url = if take_prefix(&mut rest, "<").is_some() {
+			let url = take_until(&mut rest, |c: char| c == '>' || c.is_whitespace());
+			take_prefix(&mut rest, ">")?;
+			if !url.contains(':') {
+				return None;
+			}
+			url
+		} else if rest.starts_with("https://") || rest.starts_with("http://") {
+			let url = trim_bare_url(take_until(&mut rest, char::is_whitespace));
+			rest = &input[url.len()..];
+			url
+		} else {
+			return None;
+		};
+		*input = rest;
+		Some(Self { url, title: None })
+	}
+	// A reference definition on a line of its own: [label]: url "title"
+	fn definition(line: &'i str) -> Option<(&'i str, Self)> {
+		let mut rest = line.trim();
+		take_prefix(&mut rest, "[")?;
+		Self::defined(rest)
+	}
+	// What follows the '[' of a reference definition
+	fn defined(mut rest: &'i str) -> Option<(&'i str, Self)> {
+		let label = take_until(&mut rest, ']');
+		take_prefix(&mut rest, "]:")?;
+		rest = rest.trim_start();
+		let url = take_until(&mut rest, char::is_whitespace);
+		let title = Self::title(&mut rest);
+		if url.is_empty() || !rest.trim().is_empty() {
+			return None;
+		}
+		Some((label, Self { url, title }))
 	}
 }
 
 #[rustfmt::skip]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum Kind<'i> {
+pub enum Kind<'i> {
 	// Inline:
 	Strong, Emphasis, Mark,
 	Code, Quote, Cite,
 	Insert, Delete,
 	Super, Sub,
-	Span, Link,
+	Span, Link(Link<'i>),
 	// Blocks
 	Heading(u8),
 	Paragraph,
@@ -66,84 +238,288 @@ enum Kind<'i> {
 	OrderedList, UnorderedList,
 }
 #[derive(Debug, Clone, PartialEq)]
-enum AST<'i> {
+pub enum AST<'i> {
 	Text(&'i str),
 	// Open / Close
 	Start(Option<Attributes<'i>>, Kind<'i>),
 	End(Kind<'i>),
 	// Void tags
 	Rule(Attributes<'i>),
+	// alt, src
 	Image(Attributes<'i>, &'i str, &'i str),
 	LineBreak,
 }
 
-struct Parser<'i> {
+// The text of a link up to its ']', which can have brackets of its own as long as they're balanced.
+fn link_text(rest: &str) -> Option<&str> {
+	let mut depth = 0;
+	for (i, c) in rest.char_indices() {
+		match c {
+			'[' => depth += 1,
+			']' if depth == 0 => return Some(&rest[..i]),
+			']' => depth -= 1,
+			_ => {}
+		}
+	}
+	None
+}
+
+pub struct Parser<'i> {
+	input: &'i str,
 	beginning: bool,
-	lexer: Peekable<Lexer<'i>>,
-	stack: Vec<Kind<'i>>
+	lexer: Lexer<'i>,
+	// Links are read from what the lexer has left of the line, so it can't be more than one item ahead.
+	peeked: Option<Option<LexRes<'i, Token<'i>, ()>>>,
+	stack: Vec<Kind<'i>>,
+	// For each open indentation, how many of the stack's elements were open before it. Its Dedent closes the rest.
+	indents: Vec<usize>,
+	// A tag was just opened, and the indented block after it (if there is one) is its content.
+	open_tag: bool,
+	// Reference definitions can come after the links that use them, so they're collected up front.
+	references: HashMap<&'i str, Link<'i>>,
+	// How much of the line the open link's ']' leaves, and what's after the rest of the link.
+	open_link: Option<(usize, &'i str)>,
+	// Events that are known before they're asked for, like the text and end of an autolink.
+	pending: VecDeque<AST<'i>>
 }
 impl<'i> Parser<'i> {
-	fn is_empty(&mut self) -> bool {
-		self.lexer.peek() == Some(&LexRes::Text(""))
-	}
 	pub fn new(input: &'i str) -> Self {
 		Self {
+			input,
 			beginning: true,
-			lexer: Lexer::new(input).peekable(),
-			stack: Vec::new()
+			lexer: Lexer::new(input),
+			peeked: None,
+			stack: Vec::new(),
+			indents: Vec::new(),
+			open_tag: false,
+			references: input.lines().filter_map(Link::definition).collect(),
+			open_link: None,
+			pending: VecDeque::new()
+		}
+	}
+	fn peek(&mut self) -> Option<LexRes<'i, Token<'i>, ()>> {
+		let lexer = &mut self.lexer;
+		self.peeked.get_or_insert_with(|| lexer.next()).clone()
+	}
+	fn advance(&mut self) -> Option<LexRes<'i, Token<'i>, ()>> {
+		match self.peeked.take() {
+			Some(peeked) => peeked,
+			None => self.lexer.next()
+		}
+	}
+	// Where s, which has to be part of the input, starts in it.
+	fn offset(&self, s: &str) -> usize {
+		s.as_ptr() as usize - self.input.as_ptr() as usize
+	}
+	// The control character that was just taken, as it is in the input.
+	fn control(&self, c: char) -> &'i str {
+		let end = self.offset(self.lexer.rest());
+		&self.input[end - c.len_utf8()..end]
+	}
+	// The first autolink in the text that was just peeked: where it starts, and what's left of the line after it. Bare urls have to start a word.
+	fn autolink(&self, text: &'i str) -> Option<(usize, Link<'i>, &'i str)> {
+		let rest = self.lexer.rest();
+		let line_end = self.offset(rest) + rest.len();
+		text.char_indices().find_map(|(i, c)| {
+			let start = self.offset(text) + i;
+			let before = self.input[..start].chars().next_back();
+			if c != '<' && !before.map_or(true, |b| b.is_whitespace() || b == '(') {
+				return None;
+			}
+			let mut line = &self.input[start..line_end];
+			let link = Link::autolink(&mut line)?;
+			Some((i, link, line))
+		})
+	}
+	// What follows a link's '[': its text, its (url "title") or [label], and any attributes after that. None if it isn't a link after all.
+	fn link(&self, rest: &'i str) -> Option<(&'i str, Link<'i>, Option<Attributes<'i>>, &'i str)> {
+		let text = link_text(rest)?;
+		let mut after = &rest[text.len() + 1..];
+		let link = match Link::destination(&mut after) {
+			Some(link) => link,
+			None => {
+				take_prefix(&mut after, "[")?;
+				let label = take_until(&mut after, ']');
+				take_prefix(&mut after, "]")?;
+				// [label][] is short for [label][label]
+				*self.references.get(if label.is_empty() { text } else { label })?
+			}
+		};
+		let attributes = Attributes::new(&mut after).ok().filter(|a| *a != Attributes::default());
+		Some((text, link, attributes, after))
+	}
+	// Blocks start at the beginning of a line.
+	fn block(&mut self, next: LexRes<'i, Token<'i>, ()>) -> Option<Result<AST<'i>, AttributeError>> {
+		if self.open_tag && !matches!(next, LexRes::Token(Token::Newline | Token::BlankLine)) {
+			// A tag that isn't followed by an indented block is empty.
+			self.open_tag = false;
+			return self.stack.pop().map(|kind| Ok(AST::End(kind)));
+		}
+		match next {
+			LexRes::Token(Token::Newline | Token::BlankLine) => {
+				self.advance();
+				self.next()
+			},
+			LexRes::Token(Token::Control('-')) if self.lexer.rest().starts_with("--") => {
+				self.advance();
+				let mut rest = self.lexer.rest()[2..].trim_start();
+				let start = self.offset(rest);
+				self.lexer.set_rest("");
+				// A bad rule is reported and skipped, and parsing carries on with the next line.
+				let attributes = match Attributes::new(&mut rest) {
+					Ok(attributes) => attributes,
+					Err(e) => return Some(Err(AttributeError { at: start + e.at, ..e }))
+				};
+				if !rest.trim().is_empty() {
+					return Some(Err(AttributeError {
+						at: self.offset(rest),
+						expected: "the end of the line"
+					}));
+				}
+				Some(Ok(AST::Rule(attributes)))
+			},
+			LexRes::Token(Token::Control('[')) if Link::defined(self.lexer.rest()).is_some() => {
+				// Definitions were collected up front.
+				self.advance();
+				self.lexer.set_rest("");
+				self.next()
+			},
+			LexRes::Token(Token::Tag(name)) => {
+				self.advance();
+				// The lexer only makes a tag of a line whose attributes parse, but they're still checked here.
+				let mut attributes = Attributes::default();
+				if let Some(LexRes::Text(text)) = self.peek() {
+					self.advance();
+					let mut rest = text;
+					attributes = match Attributes::new(&mut rest) {
+						Ok(attributes) => attributes,
+						Err(e) => return Some(Err(AttributeError { at: self.offset(text) + e.at, ..e }))
+					};
+				}
+				self.open_tag = true;
+				self.stack.push(Kind::Tag(name));
+				Some(Ok(AST::Start(Some(attributes).filter(|a| *a != Attributes::default()), Kind::Tag(name))))
+			},
+			LexRes::Text(_) | LexRes::Token(Token::Control(_)) => {
+				self.beginning = false;
+				self.stack.push(Kind::Paragraph);
+				Some(Ok(AST::Start(None, Kind::Paragraph)))
+			},
+			LexRes::Token(Token::Indent | Token::Dedent) | LexRes::LexError(_) => unreachable!("handled by next()")
+		}
+	}
+	// Inline elements, until a blank line ends the block.
+	fn inline(&mut self, next: LexRes<'i, Token<'i>, ()>) -> Option<AST<'i>> {
+		match next {
+			LexRes::Text(text) => match self.autolink(text).filter(|_| self.open_link.is_none()) {
+				Some((0, link, after)) => {
+					self.advance();
+					self.lexer.set_rest(after);
+					self.pending.push_back(AST::Text(link.url));
+					self.pending.push_back(AST::End(Kind::Link(link)));
+					Some(AST::Start(None, Kind::Link(link)))
+				},
+				Some((i, ..)) => {
+					self.peeked = Some(Some(LexRes::Text(&text[i..])));
+					Some(AST::Text(&text[..i]))
+				},
+				None => {
+					self.advance();
+					Some(AST::Text(text))
+				}
+			},
+			LexRes::Token(Token::Control('[')) => {
+				self.advance();
+				let rest = self.lexer.rest();
+				match self.link(rest).filter(|_| self.open_link.is_none()) {
+					Some((text, link, attributes, after)) => {
+						self.open_link = Some((rest.len() - text.len() - 1, after));
+						self.stack.push(Kind::Link(link));
+						Some(AST::Start(attributes, Kind::Link(link)))
+					},
+					None => Some(AST::Text(self.control('[')))
+				}
+			},
+			LexRes::Token(Token::Control(']')) => {
+				self.advance();
+				match self.open_link {
+					Some((left, after)) if left == self.lexer.rest().len() => {
+						self.open_link = None;
+						self.lexer.set_rest(after);
+						self.stack.pop().map(AST::End)
+					},
+					_ => Some(AST::Text(self.control(']')))
+				}
+			},
+			LexRes::Token(Token::Control('!')) => {
+				self.advance();
+				let rest = self.lexer.rest();
+				match rest.strip_prefix('[').and_then(|rest| self.link(rest)) {
+					Some((alt, link, attributes, after)) => {
+						self.lexer.set_rest(after);
+						Some(AST::Image(attributes.unwrap_or_default(), alt, link.url))
+					},
+					None => Some(AST::Text(self.control('!')))
+				}
+			},
+			LexRes::Token(Token::Control(c)) => {
+				self.advance();
+				Some(AST::Text(self.control(c)))
+			},
+			LexRes::Token(Token::Newline) => {
+				self.advance();
+				Some(AST::Text("\n"))
+			},
+			LexRes::Token(Token::BlankLine) => {
+				self.advance();
+				self.beginning = true;
+				self.stack.pop().map(AST::End)
+			},
+			// A line of a paragraph that looks like a tag is just a word.
+			LexRes::Token(Token::Tag(name)) => {
+				self.advance();
+				Some(AST::Text(name))
+			},
+			LexRes::Token(Token::Indent | Token::Dedent) | LexRes::LexError(_) => unreachable!("handled by next()")
 		}
 	}
 }
 impl<'i> Iterator for Parser<'i> {
-	type Item = AST<'i>;
+	type Item = Result<AST<'i>, AttributeError>;
 	fn next(&mut self) -> Option<Self::Item> {
-		if self.is_empty() {
-			self.beginning = true;
-			self.lexer.next();
+		if let Some(event) = self.pending.pop_front() {
+			return Some(Ok(event));
 		}
-		match self.lexer.peek_mut() {
-			Some(LexRes::Text(t)) => {
-				if self.beginning {
-					self.beginning = false;
-					// Block elements
-					if take_prefix(t, "---").is_some() {
-						let attributes = Attributes::new(t);
-						assert!(self.is_empty());
-						self.lexer.next();
-						let temp = self.lexer.peek();
-						assert!(
-							temp.is_none() ||
-							temp == Some(&LexRes::Token(Token::Newline)) ||
-							temp == Some(&LexRes::Token(Token::BlankLine))
-						);
-						Some(AST::Rule(attributes))
-					} else {
-						unimplemented!()
-					}
-				} else {
-					// Inline elements
-					unimplemented!()
-				}
+		match self.peek() {
+			Some(LexRes::Token(Token::Indent)) => {
+				self.advance();
+				// The indented block is the content of the tag just before it. Indented lines after anything else just carry on with it.
+				let kept = self.stack.len() - self.open_tag as usize;
+				self.open_tag = false;
+				self.indents.push(kept);
+				self.next()
 			},
 			Some(LexRes::Token(Token::Dedent)) => {
-				// pop a block off the stack?
-				if let Some(top) = self.stack.pop() {
-					match top {
-						Kind::Heading(_) | Kind::Paragraph |
-						Kind::Tag(_) | Kind::CodeBlock(_) |
-						Kind::BlockQuote | Kind::OrderedList |
-						Kind::UnorderedList => self.next(),
-						_ => panic!("Unexpected Dedent Token. {:?}", top)
-					}
-				} else {
-					panic!("Unexpected Dedent Token: Stack was empty.")
+				self.advance();
+				// Close everything that was opened inside the indented block, innermost first.
+				let kept = self.indents.pop().unwrap_or(0);
+				while self.stack.len() > kept {
+					let kind = self.stack.pop().unwrap();
+					self.pending.push_back(AST::End(kind));
 				}
+				self.open_tag = false;
+				self.beginning = !self.stack.contains(&Kind::Paragraph);
+				self.next()
 			},
-			Some(LexRes::Token(_)) => {
-				// Handle other stuff?
-				unimplemented!()
+			// The lexer doesn't have errors of its own.
+			Some(LexRes::LexError(())) => {
+				self.advance();
+				self.next()
 			},
-			_ => None
+			Some(next) if self.beginning => self.block(next),
+			Some(next) => self.inline(next).map(Ok),
+			// Close whatever's still open at the end of the input.
+			None => self.stack.pop().map(|kind| Ok(AST::End(kind)))
 		}
 	}
 }
@@ -155,11 +531,195 @@ mod tests {
 	#[test]
 	fn t1() {
 		let mut t = "#this.cant.be.real";
-		assert_eq!(Attributes::new(&mut t), Attributes {
+		assert_eq!(Attributes::new(&mut t), Ok(Attributes {
 			id: Some("this"),
 			classes: vec!["cant", "be", "real"],
 			attributes: HashMap::new()
-		});
+		}));
 		assert!(t.is_empty());
 	}
-}
\ No newline at end of file
+
+	#[test]
+	fn attributes() {
+		let mut t = r#".a{href: "x.html", title: "A \"quoted\" title"}#first.b{hidden width: 100,}#second. Rest"#;
+		let attributes = Attributes::new(&mut t).unwrap();
+		assert_eq!(t, ". Rest");
+		assert_eq!(attributes.id, Some("second"));
+		assert_eq!(attributes.classes, vec!["a", "b"]);
+		assert_eq!(attributes.attributes, vec![
+			("href", Cow::Borrowed("x.html")),
+			("title", Cow::Owned(r#"A "quoted" title"#.to_string())),
+			("hidden", Cow::Borrowed("")),
+			("width", Cow::Borrowed("100")),
+		].into_iter().collect());
+
+		// Punctuation that isn't an attribute is left alone.
+		for s in [". End", "#", "", " {a: b}"] {
+			let mut t = s;
+			assert_eq!(Attributes::new(&mut t), Ok(Attributes::default()));
+			assert_eq!(t, s);
+		}
+
+		for (s, at, expected) in [
+			(r#"{a: "unterminated}"#, 4, "a closing '\"'"),
+			("{a: }", 4, "an attribute value"),
+			(r#"{a: "x"y}"#, 7, "',' or '}'"),
+			(r#"#id{a: "x"#, 7, "a closing '\"'"),
+			("{=}", 1, "an attribute name or '}'"),
+			("{a", 2, "',' or '}'"),
+		] {
+			let mut t = s;
+			assert_eq!(Attributes::new(&mut t), Err(AttributeError { at, expected }), "{}", s);
+			assert_eq!(t, s);
+		}
+	}
+
+	#[test]
+	fn links() {
+		let mut t = r#"(https://promisesaplus.com/ "Promises/A+").external"#;
+		assert_eq!(Link::destination(&mut t), Some(Link {
+			url: "https://promisesaplus.com/",
+			title: Some("Promises/A+")
+		}));
+		assert_eq!(t, ".external");
+		let mut t = "(missing-paren";
+		assert_eq!(Link::destination(&mut t), None);
+		assert_eq!(t, "(missing-paren");
+
+		let mut t = "https://v8.dev/features/promise-combinators). Here's";
+		assert_eq!(Link::autolink(&mut t), Some(Link {
+			url: "https://v8.dev/features/promise-combinators",
+			title: None
+		}));
+		assert_eq!(t, "). Here's");
+		let mut t = "https://en.wikipedia.org/wiki/Rust_(programming_language)).";
+		assert_eq!(Link::autolink(&mut t).map(|l| l.url), Some("https://en.wikipedia.org/wiki/Rust_(programming_language)"));
+		assert_eq!(t, ").");
+		let mut t = "<mailto:me@example.com> hi";
+		assert_eq!(Link::autolink(&mut t).map(|l| l.url), Some("mailto:me@example.com"));
+		assert_eq!(t, " hi");
+		assert_eq!(Link::autolink(&mut "<div>"), None);
+
+		assert_eq!(Link::definition("[code]: https://github.com/evan-brass 'GitHub'"), Some(("code", Link {
+			url: "https://github.com/evan-brass",
+			title: Some("GitHub")
+		})));
+		assert_eq!(Link::definition("[not] a definition"), None);
+	}
+	#[test]
+	fn parse_links() {
+		let input = r#"See [the spec](https://promisesaplus.com/ "Promises/A+").external and [my code][gh],
+or https://en.wikipedia.org/wiki/Rust_(programming_language). ![A [diagram]](d.webp) [not a link]
+
+[gh]: https://github.com/evan-brass 'GitHub'
+"#;
+		let spec = Kind::Link(Link { url: "https://promisesaplus.com/", title: Some("Promises/A+") });
+		let gh = Kind::Link(Link { url: "https://github.com/evan-brass", title: Some("GitHub") });
+		let wiki = "https://en.wikipedia.org/wiki/Rust_(programming_language)";
+		let wiki_link = Kind::Link(Link { url: wiki, title: None });
+		assert_eq!(Parser::new(input).collect::<Result<Vec<_>, _>>(), Ok(vec![
+			AST::Start(None, Kind::Paragraph),
+			AST::Text("See "),
+			AST::Start(Some(Attributes { classes: vec!["external"], ..Attributes::default() }), spec),
+			AST::Text("the spec"),
+			AST::End(spec),
+			AST::Text(" and "),
+			AST::Start(None, gh),
+			AST::Text("my code"),
+			AST::End(gh),
+			AST::Text(","),
+			AST::Text("\n"),
+			AST::Text("or "),
+			AST::Start(None, wiki_link),
+			AST::Text(wiki),
+			AST::End(wiki_link),
+			AST::Text(". "),
+			AST::Image(Attributes::default(), "A [diagram]", "d.webp"),
+			AST::Text(" "),
+			AST::Text("["),
+			AST::Text("not a link"),
+			AST::Text("]"),
+			AST::End(Kind::Paragraph),
+		]));
+	}
+	#[test]
+	fn parse_rules() {
+		let input = "Some text here.\n\n---.wide\n--- {key: \"unterminated\n---#top trailing text\n\nMore text here.";
+		let wide = Attributes { classes: vec!["wide"], ..Attributes::default() };
+		assert_eq!(Parser::new(input).collect::<Vec<_>>(), vec![
+			Ok(AST::Start(None, Kind::Paragraph)),
+			Ok(AST::Text("Some text here.")),
+			Ok(AST::End(Kind::Paragraph)),
+			Ok(AST::Rule(wide)),
+			Err(AttributeError { at: input.find('"').unwrap(), expected: "a closing '\"'" }),
+			Err(AttributeError { at: input.find(" trailing").unwrap(), expected: "the end of the line" }),
+			Ok(AST::Start(None, Kind::Paragraph)),
+			Ok(AST::Text("More text here.")),
+			Ok(AST::End(Kind::Paragraph)),
+		]);
+	}
+	#[test]
+	fn parse_blocks() {
+		// The input from the lexer's t2.
+		let input = "
+section.intro#top
+	Some *strong* text!
+
+	= A [link](url)
+Hello.
+";
+		let section = Kind::Tag("section");
+		let link = Kind::Link(Link { url: "url", title: None });
+		assert_eq!(Parser::new(input).collect::<Result<Vec<_>, _>>(), Ok(vec![
+			AST::Start(Some(Attributes { id: Some("top"), classes: vec!["intro"], ..Attributes::default() }), section),
+			AST::Start(None, Kind::Paragraph),
+			AST::Text("Some "),
+			AST::Text("*"),
+			AST::Text("strong"),
+			AST::Text("*"),
+			AST::Text(" text"),
+			AST::Text("!"),
+			AST::End(Kind::Paragraph),
+			AST::Start(None, Kind::Paragraph),
+			AST::Text("="),
+			AST::Text(" A "),
+			AST::Start(None, link),
+			AST::Text("link"),
+			AST::End(link),
+			AST::Text("\n"),
+			AST::End(Kind::Paragraph),
+			AST::End(section),
+			AST::Start(None, Kind::Paragraph),
+			AST::Text("Hello."),
+			AST::End(Kind::Paragraph),
+		]));
+
+		// A word on its own line is a tag, and the indented lines after it are its content.
+		assert_eq!(Parser::new("Para\n\tindented\n").collect::<Result<Vec<_>, _>>(), Ok(vec![
+			AST::Start(None, Kind::Tag("Para")),
+			AST::Start(None, Kind::Paragraph),
+			AST::Text("indented"),
+			AST::End(Kind::Paragraph),
+			AST::End(Kind::Tag("Para")),
+		]));
+		// Indented lines after anything else carry on the paragraph, and a tag without any is empty.
+		assert_eq!(Parser::new("Two words\n\tindented\nend.").collect::<Result<Vec<_>, _>>(), Ok(vec![
+			AST::Start(None, Kind::Paragraph),
+			AST::Text("Two words"),
+			AST::Text("\n"),
+			AST::Text("indented"),
+			AST::Text("\n"),
+			AST::Text("end."),
+			AST::End(Kind::Paragraph),
+		]));
+		assert_eq!(Parser::new("hr\nSome text\nword").collect::<Result<Vec<_>, _>>(), Ok(vec![
+			AST::Start(None, Kind::Tag("hr")),
+			AST::End(Kind::Tag("hr")),
+			AST::Start(None, Kind::Paragraph),
+			AST::Text("Some text"),
+			AST::Text("\n"),
+			AST::Text("word"),
+			AST::End(Kind::Paragraph),
+		]));
+	}
+}
@@ -1,129 +1,1373 @@
 #![feature(option_result_contains, path_try_exists, if_let_guard, pattern)]
 use std::{
+	collections::BTreeSet,
 	fs::{self, File},
+	hash::{Hash, Hasher},
 	io,
 	io::Write,
+	mem,
 	path::{Path, PathBuf},
+	sync::{
+		atomic::{AtomicUsize, Ordering},
+		Arc, Mutex,
+	},
 };
 
+mod asciimath;
+mod cache;
+mod cli;
+mod config;
+mod date;
+mod feed;
+mod highlight;
+mod html;
+mod image;
 mod justwrite;
 mod packrat;
 mod parser;
 mod parser2;
+mod pool;
+mod report;
+mod serve;
+mod shortcode;
+mod template;
+mod toml;
+use cache::Cache;
+use cli::{Args, Command, Verbosity};
+use config::SiteConfig;
+use date::Date;
+use feed::{Feed, FeedEntry};
+use image::Images;
+use parser::SortBy;
 use parser2::Input;
+use report::{Problem, Report};
+use shortcode::Shortcodes;
+use template::Templates;
+use toml::Value;
 
-fn render_document(mut output: File, contents: &str) -> io::Result<()> {
+// Join a relative path with '/' so that it can be used in urls.
+fn url_path(path: &Path) -> String {
+	path.components()
+		.map(|c| c.as_os_str().to_string_lossy())
+		.collect::<Vec<_>>()
+		.join("/")
+}
+
+// Lowercase words separated by '-', for use in urls: "Finite State Machines" -> "finite-state-machines"
+fn slugify(s: &str) -> String {
+	s.split(|c: char| !c.is_alphanumeric())
+		.filter(|w| !w.is_empty())
+		.map(str::to_lowercase)
+		.collect::<Vec<_>>()
+		.join("-")
+}
+
+// Say which file an error is about.
+fn in_file(path: &Path) -> impl Fn(io::Error) -> io::Error + '_ {
+	move |e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e))
+}
+
+// Whether path, in dir, is a symlink to dir or one of the directories it's in, so that following it would never end.
+fn is_loop(path: &Path, dir: &Path) -> io::Result<bool> {
+	if !fs::symlink_metadata(path)?.file_type().is_symlink() {
+		return Ok(false);
+	}
+	Ok(fs::canonicalize(dir)?.starts_with(fs::canonicalize(path)?))
+}
+
+// Every document in dir and its subdirectories, drafts included. Symlinks that loop are skipped.
+fn documents(dir: &Path, found: &mut Vec<PathBuf>) -> io::Result<()> {
+	for entry in fs::read_dir(dir)? {
+		let path = entry?.path();
+		if path.is_dir() && !is_loop(&path, dir)? {
+			documents(&path, found)?;
+		} else if path.extension().contains(&"md") {
+			found.push(path);
+		}
+	}
+	found.sort();
+	Ok(())
+}
+
+// Parse just the header to see whether a document is a draft. If it doesn't parse then it isn't skipped, so that rendering it reports the error.
+fn is_draft(contents: &str) -> bool {
+	parser::parse_header(&mut Input::from(contents)).map_or(false, |h| h.draft)
+}
+
+// Everything section indexes and feeds need to know about a page. The build cache keeps them for pages that don't need to be rendered again.
+#[derive(Debug, Default, Clone, PartialEq, Hash)]
+struct Entry {
+	title: String,
+	// Path from the root of the site
+	url: String,
+	date: Option<Date>,
+	updated: Option<Date>,
+	weight: Option<i64>,
+	description: String,
+	reading_time: usize,
+	// Tags and keywords
+	tags: Vec<String>,
+	authors: Vec<(String, Option<String>)>,
+	draft: bool,
+	content: String,
+	// The images it shows, from the content directory, to find the ones that no page does.
+	images: Vec<String>,
+}
+impl Entry {
+	fn new(
+		document: &parser::Document,
+		contents: &str,
+		url: String,
+		content: String,
+		images: Vec<String>,
+	) -> Self {
+		let header = &document.header;
+		let description = match header.summary {
+			Some(summary) => summary.to_string(),
+			None if !header.description.is_empty() => header.description.to_string(),
+			None => html::summary(&document.blocks, 228),
+		};
+		// The header ends at the first blank line.
+		let words: usize = contents
+			.lines()
+			.skip_while(|l| !l.trim().is_empty())
+			.map(|l| l.split_whitespace().count())
+			.sum();
+		let mut tags: Vec<String> = Vec::new();
+		for tag in header.tags.iter().chain(&header.keywords) {
+			let tag = tag.trim();
+			// A tag without a slug couldn't have a page.
+			if !slugify(tag).is_empty() && !tags.iter().any(|t| slugify(t) == slugify(tag)) {
+				tags.push(tag.to_string());
+			}
+		}
+		Self {
+			title: header.title.to_string(),
+			url,
+			date: header.date,
+			updated: header.updated,
+			weight: header.weight,
+			description,
+			// At 200 words per minute, rounded up.
+			reading_time: (words + 199) / 200,
+			tags,
+			authors: header
+				.authors
+				.iter()
+				.map(|a| (a.name.trim().to_string(), a.email.map(str::to_string)))
+				.collect(),
+			draft: header.draft,
+			content,
+			images,
+		}
+	}
+	// The page as templates see it, with the same names as Zola's page variable.
+	fn to_value(&self, config: &SiteConfig) -> Value {
+		let mut page = vec![
+			("title", string(&self.title)),
+			("description", string(&self.description)),
+			("summary", string(&self.description)),
+			("path", string(&self.url)),
+			(
+				"permalink",
+				string(&format!("{}{}", config.base_url, self.url)),
+			),
+			("reading_time", Value::Integer(self.reading_time as i64)),
+			("content", string(&self.content)),
+			("draft", Value::Boolean(self.draft)),
+			("toc", Value::Array(Vec::new())),
+			(
+				"taxonomies",
+				table(vec![(
+					"tags",
+					Value::Array(self.tags.iter().map(|t| string(t)).collect()),
+				)]),
+			),
+		];
+		if let Some(date) = self.date {
+			page.push(("date", Value::Datetime(date)));
+		}
+		if let Some(updated) = self.updated {
+			page.push(("updated", Value::Datetime(updated)));
+		}
+		let mut extra = Vec::new();
+		if let Some((author, _)) = self.authors.first() {
+			extra.push(("author", string(author)));
+		}
+		page.push(("extra", table(extra)));
+		table(page)
+	}
+}
+
+fn string(s: &str) -> Value {
+	Value::String(s.to_string())
+}
+fn table(pairs: Vec<(&str, Value)>) -> Value {
+	Value::Table(pairs.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+}
+
+// Template contexts always have the config, and then the page or section being rendered.
+fn render_template(
+	mut output: impl Write,
+	templates: &Templates,
+	name: &str,
+	config: &SiteConfig,
+	mut context: Vec<(&str, Value)>,
+) -> io::Result<()> {
+	context.push(("config", config.to_value()));
+	context.push(("lang", string("en")));
+	let context = match table(context) {
+		Value::Table(t) => t,
+		_ => unreachable!(),
+	};
+	let html = templates
+		.render(name, &context)
+		.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+	output.write_all(html.as_bytes())
+}
+
+// What every document and index is rendered with.
+struct RenderContext<'a> {
+	templates: &'a Templates,
+	shortcodes: &'a Shortcodes,
+	images: &'a Images,
+	config: &'a SiteConfig,
+}
+
+fn render_document(
+	output: impl Write,
+	rcx: &RenderContext,
+	contents: &str,
+	source_dir: &str,
+	output_dir: &str,
+) -> io::Result<Entry> {
 	let mut input = Input::from(contents);
-	let document =
-		parser::parse_document(&mut input).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-	println!("{:#?}", document);
-	let o = &mut output;
-	write!(
-		o,
-		r#"<!DOCTYPE html>
-<html lang="en">
-	<head>
-		<meta charset="UTF-8">
-		<title>{}</title>
-		<meta name="description" content="{}">
-		<meta name="keywords" content="">
-	</head>
-	<body>
-		<header>
-			<h1>Evan Brass</h1>
-			<p>A mediocre blog about stuff</p>
-			<nav>
-				<a href="/about/">About</a>
-				<a href="/blog/">Blog</a>
-				<a href="/projects/">Projects</a>
-			</nav>
-		</header>
-		<main>"#,
-		document.header.title, document.header.description
+	let document = parser::parse_document(&mut input)
+		.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+	let cx = html::Context {
+		source_dir,
+		output_dir,
+		references: &document.references,
+		shortcodes: rcx.shortcodes,
+		images: rcx.images,
+		used: Default::default(),
+	};
+	// Feeds want the content on its own.
+	let mut content = Vec::new();
+	html::render_blocks(&mut content, &cx, &document.blocks)?;
+	let entry = Entry::new(
+		&document,
+		contents,
+		format!("/{}/", output_dir)
+			.replace("//", "/")
+			.replace(' ', "%20"),
+		String::from_utf8_lossy(&content).into_owned(),
+		cx.used.into_inner().into_iter().collect(),
+	);
+	render_template(
+		output,
+		rcx.templates,
+		"page.html",
+		rcx.config,
+		vec![("page", entry.to_value(rcx.config))],
 	)?;
+	Ok(entry)
+}
 
-	write!(
-		o,
-		r#"
-		</main>
-		<footer>
+// A directory's listing, with an optional _index.md for its title, description, sort order and introduction.
+fn render_index(
+	output: impl Write,
+	rcx: &RenderContext,
+	index: Option<&str>,
+	default_title: &str,
+	output_dir: &str,
+	mut entries: Vec<&Entry>,
+) -> io::Result<()> {
+	let mut input = Input::from(index.unwrap_or(""));
+	let document = match index {
+		Some(_) => Some(
+			parser::parse_document(&mut input)
+				.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+		),
+		None => None,
+	};
+	let header = document.as_ref().map(|d| &d.header);
+	match header.and_then(|h| h.sort_by).unwrap_or(SortBy::Date) {
+		// Newest first, undated pages last.
+		SortBy::Date => entries.sort_by(|a, b| b.date.cmp(&a.date)),
+		SortBy::Title => entries.sort_by(|a, b| a.title.cmp(&b.title)),
+		// Lightest first, pages without a weight last.
+		SortBy::Weight => entries.sort_by_key(|e| (e.weight.is_none(), e.weight)),
+	}
 
-		</footer>
-	</body>
-</html>"#
-	)?;
-	Ok(())
+	let mut content = Vec::new();
+	if let Some(document) = &document {
+		let cx = html::Context {
+			source_dir: output_dir,
+			output_dir,
+			references: &document.references,
+			shortcodes: rcx.shortcodes,
+			images: rcx.images,
+			used: Default::default(),
+		};
+		html::render_blocks(&mut content, &cx, &document.blocks)?;
+	}
+	let path = format!("/{}/", output_dir).replace("//", "/");
+	let section = table(vec![
+		("title", string(header.map_or(default_title, |h| h.title))),
+		("description", string(header.map_or("", |h| h.description))),
+		("content", string(&String::from_utf8_lossy(&content))),
+		(
+			"permalink",
+			string(&format!("{}{}", rcx.config.base_url, path)),
+		),
+		("path", string(&path)),
+		(
+			"pages",
+			Value::Array(entries.iter().map(|e| e.to_value(rcx.config)).collect()),
+		),
+	]);
+	render_template(
+		output,
+		rcx.templates,
+		"section.html",
+		rcx.config,
+		vec![("section", section)],
+	)
 }
 
-struct Site {
-	src: PathBuf,
+// A document that discovery found, to be rendered to dest.
+struct Document {
+	path: PathBuf,
 	dest: PathBuf,
+	contents: String,
+	// The hash of its contents for the cache, and of the images once they've been read since they're part of its html
+	key: u64,
+}
+
+// A directory without an index.md, which gets an index of its pages.
+struct Section {
+	dir: PathBuf,
+	// Its _index.md
+	index: Option<String>,
+	// Its documents and the index.md of its subdirectories, as indexes into Found::documents
+	documents: Vec<usize>,
+}
+
+// Everything in the content and static directories, in the order they're listed in.
+#[derive(Default)]
+struct Found {
+	documents: Vec<Document>,
+	sections: Vec<Section>,
+	// Files that are copied as they are: source -> dest
+	files: Vec<(PathBuf, PathBuf)>,
+}
+
+// Every path in dir, sorted so that builds don't depend on the order the file system lists them in.
+fn read_dir_sorted(dir: &Path) -> io::Result<Vec<PathBuf>> {
+	let mut paths = fs::read_dir(dir)?
+		.map(|e| e.map(|e| e.path()))
+		.collect::<io::Result<Vec<_>>>()?;
+	paths.sort();
+	Ok(paths)
+}
+
+struct Site {
+	config: SiteConfig,
+	templates: Templates,
+	shortcodes: Shortcodes,
+	// Render drafts instead of skipping them.
+	drafts: bool,
+	// What the last build wrote. Outputs that would be made from the same inputs are skipped.
+	cache: Cache,
+	verbosity: Verbosity,
+	// How many files this build has written, for the summary.
+	written: AtomicUsize,
+	// What was wrong with the sources, reported at the end so that one bad document doesn't stop the rest of the site being built.
+	problems: Mutex<Report>,
 }
 impl Site {
-	fn handle_dir(&self, dir: &Path) -> io::Result<()> {
-		// TODO: Need to be able to sort the index.
-		let _index: Vec<String> = Vec::new();
-		for entry in fs::read_dir(dir)?.into_iter() {
-			let entry = entry?;
-			let ft = entry.file_type()?;
-			let path = entry.path();
+	fn render_context<'a>(&'a self, images: &'a Images) -> RenderContext<'a> {
+		RenderContext {
+			templates: &self.templates,
+			shortcodes: &self.shortcodes,
+			images,
+			config: &self.config,
+		}
+	}
+	// Write an output, and the directories it's in.
+	fn write(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+		fs::create_dir_all(path.parent().unwrap())
+			.and_then(|()| fs::write(path, contents))
+			.map_err(in_file(path))?;
+		self.written.fetch_add(1, Ordering::Relaxed);
+		if self.verbosity == Verbosity::Verbose {
+			println!("Wrote {}", path.display());
+		}
+		Ok(())
+	}
+	// Record an error in a source for the report, or return it if it's from reading or writing files.
+	fn problem(&self, path: &Path, e: io::Error) -> io::Result<()> {
+		if e.kind() != io::ErrorKind::InvalidData {
+			return Err(in_file(path)(e));
+		}
+		self.add_problem(Problem::from_io(path, &e));
+		Ok(())
+	}
+	fn add_problem(&self, problem: Problem) {
+		self.problems.lock().unwrap().add(problem);
+	}
+	// Write an output unless the last build already wrote it from inputs with the same key, and record it in next either way.
+	// It's rendered in memory first so that if that fails the last build's output is left as it was.
+	fn output(
+		&self,
+		next: &mut Cache,
+		path: &Path,
+		key: u64,
+		write: impl FnOnce(&mut Vec<u8>) -> io::Result<()>,
+	) -> io::Result<()> {
+		if !self.cache.is_fresh(path, key) {
+			let mut contents = Vec::new();
+			if let Err(e) = write(&mut contents) {
+				next.keep_output(&self.cache, path);
+				return Err(e);
+			}
+			self.write(path, &contents)?;
+		}
+		next.add_output(path, key);
+		Ok(())
+	}
+	// Find every document, section and file in dir and its subdirectories. Returns the directory's index.md if it has one, otherwise the directory is a section and gets an index of its pages.
+	fn discover(&self, dir: &Path, found: &mut Found) -> io::Result<Option<usize>> {
+		let (src, out) = (&self.config.content_dir, &self.config.output_dir);
+		// A draft index.md makes the whole directory a draft, including its images and other files.
+		if let Ok(contents) = fs::read_to_string(dir.join("index.md")) {
+			if !self.drafts && is_draft(&contents) {
+				return Ok(None);
+			}
+		}
+		let mut listed = Vec::new();
+		let mut page = None;
+		let mut section = None;
+		for path in read_dir_sorted(dir)? {
+			// Symlinks are followed, as long as they go somewhere and don't loop.
+			let ft = match fs::metadata(&path) {
+				Ok(m) => m.file_type(),
+				Err(e) if e.kind() == io::ErrorKind::NotFound => {
+					self.add_problem(Problem::new(
+						&path,
+						"is a symlink to something that doesn't exist",
+					));
+					continue;
+				}
+				Err(e) => return Err(in_file(&path)(e)),
+			};
+			if ft.is_dir() && is_loop(&path, dir)? {
+				self.add_problem(Problem::new(
+					&path,
+					"is a symlink to a directory that it's in",
+				));
+				continue;
+			}
+			let mut dest = out.join(path.strip_prefix(src).unwrap());
 			if ft.is_dir() {
-				self.handle_dir(&path)?;
-			} else if ft.is_file() {
-				let mut dest;
-				if path.extension().contains(&"md") {
-					if path.file_name().unwrap() != "index.md" {
-						dest = self.dest.join(path.strip_prefix(&self.src).unwrap());
-						dest.set_extension("");
-						dest.push("index.html");
-					} else {
-						dest = self.dest.join(path.strip_prefix(&self.src).unwrap());
-						dest.set_extension("html");
+				if let Some(page) = self.discover(&path, found)? {
+					listed.push(page);
+				}
+			} else if !ft.is_file() {
+				self.add_problem(Problem::new(&path, "isn't a file or a directory"));
+			} else if path.file_name().unwrap() == "_index.md" {
+				match fs::read_to_string(&path) {
+					Ok(s) => section = Some(s),
+					Err(e) => self.problem(&path, e)?,
+				}
+			} else if path.extension().contains(&"md") {
+				// Not being UTF-8 is a problem with the document.
+				let contents = match fs::read_to_string(&path) {
+					Ok(c) => c,
+					Err(e) => {
+						self.problem(&path, e)?;
+						continue;
 					}
-				} else {
-					dest = self.dest.join(path.strip_prefix(&self.src).unwrap());
+				};
+				if !self.drafts && is_draft(&contents) {
+					continue;
 				}
-				// println!("{:?}", dest);
-
-				// Create the parent directory if it doesn't already exist.
-				if !dest.parent().unwrap().try_exists()? {
-					fs::create_dir_all(dest.parent().unwrap())?;
+				let is_index = path.file_name().unwrap() == "index.md";
+				if is_index {
+					dest.set_extension("html");
+				} else {
+					dest.set_extension("");
+					dest.push("index.html");
 				}
-				if path.extension().contains(&"md") {
-					// Render the document
-					let contents = fs::read_to_string(path)?;
-					let post = std::fs::File::create(dest)?;
-					render_document(post, &contents)?;
+				found.documents.push(Document {
+					key: cache::hash(&contents),
+					path,
+					dest,
+					contents,
+				});
+				if is_index {
+					page = Some(found.documents.len() - 1);
 				} else {
-					fs::copy(path, dest)?;
+					listed.push(found.documents.len() - 1);
 				}
 			} else {
-				panic!(
-					"Symlinks are not allowed in the content directory: {:?}",
-					path
-				);
+				found.files.push((path, dest));
+			}
+		}
+		if page.is_none() {
+			found.sections.push(Section {
+				dir: dir.to_path_buf(),
+				index: section,
+				documents: listed,
+			});
+		}
+		Ok(page)
+	}
+	// Everything in dir, to be copied into dest as is.
+	fn discover_static(
+		&self,
+		dir: &Path,
+		dest: &Path,
+		files: &mut Vec<(PathBuf, PathBuf)>,
+	) -> io::Result<()> {
+		for path in read_dir_sorted(dir)? {
+			let dest = dest.join(path.file_name().unwrap());
+			if path.is_dir() {
+				self.discover_static(&path, &dest, files)?;
+			} else {
+				files.push((path, dest));
 			}
 		}
+		Ok(())
+	}
+	// Copy a file unless it's the same as what the last build copied. Returns its key, and its size if it's an image.
+	fn copy(&self, path: &Path, dest: &Path) -> io::Result<(u64, Option<image::Size>)> {
+		let contents = fs::read(path).map_err(in_file(path))?;
+		let key = cache::hash(&contents);
+		if !self.cache.is_fresh(dest, key) {
+			self.write(dest, &contents)?;
+		}
+		Ok((key, image::size(&contents)))
+	}
+	// Parse and render a document, unless the last build rendered it from the same contents. Returns its page, and its html if that needs writing.
+	fn render(&self, document: &Document, images: &Images) -> io::Result<(Entry, Option<Vec<u8>>)> {
+		if let Some(entry) = self
+			.cache
+			.page(&document.path, document.key, &document.dest)
+		{
+			return Ok((entry.clone(), None));
+		}
+		let (src, out) = (&self.config.content_dir, &self.config.output_dir);
+		let source_dir = url_path(document.path.parent().unwrap().strip_prefix(src).unwrap());
+		let output_dir = url_path(document.dest.parent().unwrap().strip_prefix(out).unwrap());
+		let mut html = Vec::new();
+		let entry = render_document(
+			&mut html,
+			&self.render_context(images),
+			&document.contents,
+			&source_dir,
+			&output_dir,
+		)?;
+		Ok((entry, Some(html)))
+	}
+	// Write a section's index, which lists its pages.
+	fn write_section(
+		&self,
+		next: &mut Cache,
+		section: &Section,
+		images: &Images,
+		entries: Vec<&Entry>,
+	) -> io::Result<()> {
+		let (src, out) = (&self.config.content_dir, &self.config.output_dir);
+		let dir = &section.dir;
+		let output_dir = out.join(dir.strip_prefix(src).unwrap());
+		let name = dir
+			.file_name()
+			.filter(|_| dir != src)
+			.map_or("Home".into(), |n| n.to_string_lossy());
+		// Sections are named after their directory: blog -> Blog
+		let mut chars = name.chars();
+		let title = chars
+			.next()
+			.map_or(String::new(), |c| c.to_uppercase().chain(chars).collect());
+		let path = output_dir.join("index.html");
+		let output_dir = url_path(output_dir.strip_prefix(out).unwrap());
+		// The index lists its pages, so it changes when any of them do.
+		let key = cache::hash(&(&section.index, &title, &entries, images));
+		let source = match section.index {
+			Some(_) => dir.join("_index.md"),
+			None => path.clone(),
+		};
+		self.output(next, &path, key, |output| {
+			render_index(
+				output,
+				&self.render_context(images),
+				section.index.as_deref(),
+				&title,
+				&output_dir,
+				entries,
+			)
+		})
+		.or_else(|e| self.problem(&source, e))
+	}
+	// Write atom.xml (and rss.xml) into dir, with every dated page. Drafts are only in pages when building with --drafts.
+	fn write_feeds(
+		&self,
+		next: &mut Cache,
+		dir: &Path,
+		title: &str,
+		pages: &[&Entry],
+	) -> io::Result<()> {
+		let mut pages = pages.to_vec();
+		pages.sort_by(|a, b| b.date.cmp(&a.date));
+		let urls: Vec<_> = pages
+			.iter()
+			.map(|p| format!("{}{}", self.config.base_url, p.url))
+			.collect();
+		let entries: Vec<_> = pages
+			.iter()
+			.zip(&urls)
+			.filter_map(|(p, url)| {
+				Some(FeedEntry {
+					title: &p.title,
+					url,
+					date: p.date?,
+					updated: p.updated,
+					authors: &p.authors,
+					description: &p.description,
+					content: &p.content,
+				})
+			})
+			.collect();
+		let path = url_path(dir.strip_prefix(&self.config.output_dir).unwrap());
+		let home = format!("{}/{}", self.config.base_url, path);
+		let home = if path.is_empty() { home } else { home + "/" };
 
+		fs::create_dir_all(dir)?;
+		let key = cache::hash(&(title, pages));
+		let atom_url = format!("{}atom.xml", home);
+		let feed = Feed {
+			title,
+			description: &self.config.description,
+			home: &home,
+			url: &atom_url,
+			author: &self.config.author,
+		};
+		self.output(next, &dir.join("atom.xml"), key, |f| {
+			feed::write_atom(f, &feed, &entries)
+		})?;
+		if self.config.rss {
+			let rss_url = format!("{}rss.xml", home);
+			let feed = Feed {
+				url: &rss_url,
+				..feed
+			};
+			self.output(next, &dir.join("rss.xml"), key, |f| {
+				feed::write_rss(f, &feed, &entries)
+			})?;
+		}
 		Ok(())
 	}
-	pub fn build(self) -> io::Result<()> {
-		self.handle_dir(&self.src)?;
+	// The build is done in phases: discovery, then parsing and rendering the documents and copying files across a thread pool, then gathering their pages into the indexes, tags and feeds, then writing.
+	// Everything is put back in the order discovery found it in before it's used, so the output doesn't depend on how the threads were scheduled.
+	pub fn build(self: Arc<Self>) -> io::Result<()> {
+		let mut found = Found::default();
+		if self.config.static_dir.is_dir() {
+			let (dir, dest) = (&self.config.static_dir, &self.config.output_dir);
+			self.discover_static(dir, dest, &mut found.files)?;
+		}
+		self.discover(&self.config.content_dir, &mut found)?;
+		let Found {
+			mut documents,
+			sections,
+			files,
+		} = found;
+		let mut next = Cache::new(self.cache.site);
 
+		// Images and other files are most of what's written, so they're copied in parallel too. The content directory's images are kept for the documents to show.
+		let site = Arc::clone(&self);
+		let copied = pool::map(files, move |(path, dest)| {
+			let copied = site.copy(&path, &dest);
+			(path, dest, copied)
+		});
+		let mut images = Images::default();
+		for (path, dest, copied) in copied {
+			let (key, size) = copied?;
+			next.add_output(&dest, key);
+			let is_image = path
+				.extension()
+				.and_then(|e| e.to_str())
+				.and_then(image::mime_type);
+			if let (Some(_), Ok(path)) = (is_image, path.strip_prefix(&self.config.content_dir)) {
+				images.add(url_path(path), size);
+			}
+		}
+		let images = Arc::new(images);
+		let images_key = cache::hash(&images);
+		for document in &mut documents {
+			document.key = cache::hash(&(document.key, images_key));
+		}
+
+		let (site, shown) = (Arc::clone(&self), Arc::clone(&images));
+		let rendered = pool::map(documents, move |document| {
+			let result = site.render(&document, &shown);
+			(document, result)
+		});
+		// A document that fails keeps the last build's page, if there was one, so that the indexes still link to it.
+		let mut pages = Vec::new();
+		let mut page_of = Vec::new();
+		let mut writes = Vec::new();
+		for (document, result) in rendered {
+			let (path, dest) = (&document.path, &document.dest);
+			let entry = match result {
+				Ok((entry, html)) => {
+					next.add_page(path, document.key, dest, entry.clone());
+					if let Some(html) = html {
+						writes.push((dest.clone(), html));
+					}
+					Some(entry)
+				}
+				Err(e) => {
+					self.problem(path, e)?;
+					next.keep_page(&self.cache, path, dest)
+				}
+			};
+			page_of.push(entry.map(|entry| {
+				pages.push(entry);
+				pages.len() - 1
+			}));
+		}
+		let site = Arc::clone(&self);
+		for written in pool::map(writes, move |(dest, html)| site.write(&dest, &html)) {
+			written?;
+		}
+
+		for section in &sections {
+			let entries = section
+				.documents
+				.iter()
+				.filter_map(|&i| page_of[i])
+				.map(|i| &pages[i])
+				.collect();
+			self.write_section(&mut next, section, &images, entries)?;
+		}
+
+		// An image that no page shows was probably left behind when a document stopped using it.
+		let shown = pages
+			.iter()
+			.flat_map(|p| &p.images)
+			.map(String::as_str)
+			.collect::<BTreeSet<_>>();
+		for path in images.paths().filter(|p| !shown.contains(p)) {
+			if self.verbosity >= Verbosity::Normal {
+				let path = self.config.content_dir.join(path);
+				eprintln!("warning: {} isn't shown by any page", path.display());
+			}
+		}
+		// Group pages by tag. Tags that only differ by case or punctuation are the same tag, and the first spelling seen is the one that's shown.
+		let mut tags: Vec<(String, &str, Vec<&Entry>)> = Vec::new();
+		for page in &pages {
+			for tag in &page.tags {
+				let slug = slugify(tag);
+				match tags.iter_mut().find(|(s, ..)| *s == slug) {
+					Some((.., tagged)) => tagged.push(page),
+					None => tags.push((slug, tag, vec![page])),
+				}
+			}
+		}
+		tags.sort_by(|a, b| a.0.cmp(&b.0));
+
+		let tags_dir = self.config.output_dir.join("tags");
+		fs::create_dir_all(&tags_dir)?;
+		let taxonomy = table(vec![("name", string("tags"))]);
+		let terms: Vec<Value> = tags
+			.iter()
+			.map(|(slug, name, tagged)| {
+				let mut tagged = tagged.clone();
+				tagged.sort_by(|a, b| b.date.cmp(&a.date).then_with(|| a.title.cmp(&b.title)));
+				table(vec![
+					("name", string(name)),
+					("slug", string(slug)),
+					(
+						"permalink",
+						string(&format!("{}/tags/{}/", self.config.base_url, slug)),
+					),
+					(
+						"pages",
+						Value::Array(tagged.iter().map(|p| p.to_value(&self.config)).collect()),
+					),
+				])
+			})
+			.collect();
+		// Templates can fail on any page, so these are reported like the documents are.
+		let path = tags_dir.join("index.html");
+		self.output(&mut next, &path, cache::hash(&tags), |output| {
+			render_template(
+				output,
+				&self.templates,
+				"tags/list.html",
+				&self.config,
+				vec![
+					("taxonomy", taxonomy.clone()),
+					("terms", Value::Array(terms.clone())),
+				],
+			)
+		})
+		.or_else(|e| self.problem(&path, e))?;
+		for (tag, term) in tags.iter().zip(terms) {
+			let dir = tags_dir.join(&tag.0);
+			fs::create_dir_all(&dir)?;
+			let path = dir.join("index.html");
+			self.output(&mut next, &path, cache::hash(tag), |output| {
+				render_template(
+					output,
+					&self.templates,
+					"tags/single.html",
+					&self.config,
+					vec![("taxonomy", taxonomy.clone()), ("term", term)],
+				)
+			})
+			.or_else(|e| self.problem(&path, e))?;
+		}
+		// Only depends on the templates and config, which every key does.
+		let path = self.config.output_dir.join("404.html");
+		self.output(&mut next, &path, 0, |output| {
+			render_template(
+				output,
+				&self.templates,
+				"404.html",
+				&self.config,
+				Vec::new(),
+			)
+		})
+		.or_else(|e| self.problem(&path, e))?;
+
+		// One feed for the whole site, and one for each tag.
+		let all: Vec<_> = pages.iter().collect();
+		self.write_feeds(&mut next, &self.config.output_dir, &self.config.title, &all)?;
+		for (slug, name, tagged) in &tags {
+			let title = format!("{} - {}", name, self.config.title);
+			self.write_feeds(&mut next, &tags_dir.join(slug), &title, tagged)?;
+		}
+
+		// Outputs that this build didn't write are for sources that were deleted, drafts, tags that no page has anymore, etc.
+		let removed = self.cache.remove_stale(&next, &self.config.output_dir)?;
+		next.save(&self.config.cache_path)?;
+		let written = self.written.load(Ordering::Relaxed);
+		if self.verbosity >= Verbosity::Normal {
+			println!(
+				"Built {} pages into {}: {} files written, {} unchanged, {} removed",
+				pages.len(),
+				self.config.output_dir.display(),
+				written,
+				next.output_count() - written,
+				removed
+			);
+		}
+		self.report()
+	}
+	// The problems found, as an error if there were any.
+	fn report(&self) -> io::Result<()> {
+		let problems = mem::take(&mut *self.problems.lock().unwrap());
+		if !problems.is_empty() {
+			return Err(io::Error::new(io::ErrorKind::InvalidData, problems));
+		}
 		Ok(())
 	}
+	// Parse and render every document, drafts included, without writing anything. Returns how many there are.
+	fn check(self: Arc<Self>) -> io::Result<usize> {
+		let mut paths = Vec::new();
+		documents(&self.config.content_dir, &mut paths)?;
+		let count = paths.len();
+		let site = Arc::clone(&self);
+		let checked = pool::map(paths, move |path| {
+			let result = site.check_document(&path);
+			(path, result)
+		});
+		for (path, result) in checked {
+			result.or_else(|e| self.problem(&path, e))?;
+		}
+		self.report()?;
+		Ok(count)
+	}
+	fn check_document(&self, path: &Path) -> io::Result<()> {
+		let (src, out) = (&self.config.content_dir, &self.config.output_dir);
+		let contents = fs::read_to_string(path)?;
+		let source_dir = url_path(path.parent().unwrap().strip_prefix(src).unwrap());
+		let images = Images::default();
+		let rcx = self.render_context(&images);
+		if path.file_name().unwrap() == "_index.md" {
+			render_index(
+				io::sink(),
+				&rcx,
+				Some(&contents),
+				"",
+				&source_dir,
+				Vec::new(),
+			)
+		} else {
+			let output_dir = url_path(&out.join(&source_dir));
+			render_document(io::sink(), &rcx, &contents, &source_dir, &output_dir).map(|_| ())
+		}
+	}
 }
-impl Default for Site {
-	fn default() -> Self {
-		Self {
-			src: PathBuf::from("content"),
-			dest: PathBuf::from("public"),
+
+// Load the config, templates and shortcodes, with the command line's overrides. base_url replaces the config's, for serving the site locally.
+fn load_site(args: &Args, base_url: Option<&str>) -> io::Result<Site> {
+	load_site_from(Path::new("config.toml"), args, base_url)
+}
+fn load_site_from(config_path: &Path, args: &Args, base_url: Option<&str>) -> io::Result<Site> {
+	let mut config =
+		SiteConfig::load(config_path).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+	if let Some(src) = &args.src {
+		config.content_dir = src.clone();
+	}
+	if let Some(dest) = &args.dest {
+		config.output_dir = dest.clone();
+	}
+	if let Some(base_url) = base_url.or(args.base_url.as_deref()) {
+		config.base_url = base_url.trim_end_matches('/').to_string();
+	}
+	let mut templates = Templates::load(&config.template_dir)
+		.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+	let base_url = config.base_url.clone();
+	templates.register_function("get_url", move |args| match args.get("path") {
+		Some(Value::String(path)) => Ok(string(&format!(
+			"{}/{}",
+			base_url,
+			path.trim_start_matches('/')
+		))),
+		_ => Err("`path` should be a string".to_string()),
+	});
+	let base_url = config.base_url.clone();
+	templates.register_function("get_taxonomy_url", move |args| {
+		match (args.get("kind"), args.get("name")) {
+			(Some(Value::String(kind)), Some(Value::String(name))) => {
+				Ok(string(&format!("{}/{}/{}/", base_url, kind, slugify(name))))
+			}
+			_ => Err("`kind` and `name` should be strings".to_string()),
+		}
+	});
+	let mut shortcodes = Shortcodes::load(&config.template_dir.join("shortcodes"))
+		.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+	shortcodes.register("math", shortcode::math);
+	// Everything that every output depends on. When it changes the whole site is rebuilt.
+	let mut site = cache::Fnv::default();
+	fs::read(config_path)?.hash(&mut site);
+	cache::hash_dir(&mut site, &config.template_dir)?;
+	(&config.content_dir, &config.output_dir, &config.base_url).hash(&mut site);
+	args.drafts.hash(&mut site);
+	// A new build of the generator could render anything differently.
+	std::env::current_exe()
+		.and_then(fs::metadata)
+		.and_then(|m| m.modified())
+		.ok()
+		.hash(&mut site);
+	let cache = Cache::load(&config.cache_path, site.finish());
+	Ok(Site {
+		config,
+		templates,
+		shortcodes,
+		drafts: args.drafts,
+		cache,
+		verbosity: args.verbosity,
+		written: AtomicUsize::new(0),
+		problems: Mutex::default(),
+	})
+}
+
+// Returns the exit code.
+fn run(args: Args) -> io::Result<i32> {
+	match &args.command {
+		Command::Help => print!("{}", cli::USAGE),
+		Command::Build => Arc::new(load_site(&args, None)?).build()?,
+		Command::Serve => {
+			let site = load_site(&args, None)?;
+			let config = site.config;
+			let watched = [
+				config.content_dir.clone(),
+				config.template_dir.clone(),
+				config.static_dir.clone(),
+				PathBuf::from("config.toml"),
+			];
+			serve::serve(&args.address, &config.output_dir, &watched, |url| {
+				load_site(&args, Some(args.base_url.as_deref().unwrap_or(url)))
+					.map(Arc::new)
+					.and_then(Site::build)
+					.map_err(|e| e.to_string())
+			})?;
+		}
+		Command::Check => {
+			let checked = Arc::new(load_site(&args, None)?).check()?;
+			if args.verbosity >= Verbosity::Normal {
+				println!("Checked {} documents", checked);
+			}
+		}
+		Command::New { section, title } => {
+			let config = load_site(&args, None)?.config;
+			let dir = config.content_dir.join(section);
+			let slug = slugify(title);
+			if slug.is_empty() {
+				return Err(io::Error::new(
+					io::ErrorKind::InvalidInput,
+					"The title needs a letter or number for the file name",
+				));
+			}
+			let path = dir.join(slug + ".md");
+			fs::create_dir_all(&dir)?;
+			let today = std::time::SystemTime::now()
+				.duration_since(std::time::UNIX_EPOCH)
+				.map_or(0, |d| d.as_secs() as i64);
+			// create_new so that an existing document is never overwritten.
+			File::options()
+				.write(true)
+				.create_new(true)
+				.open(&path)
+				.map_err(in_file(&path))?
+				.write_all(cli::new_document(title, Date::from_timestamp(today)).as_bytes())?;
+			if args.verbosity >= Verbosity::Normal {
+				println!("Created {}", path.display());
+			}
+		}
+		Command::Fmt => {
+			let site = load_site(&args, None)?;
+			let mut paths = Vec::new();
+			documents(&site.config.content_dir, &mut paths)?;
+			let mut untidy = 0;
+			for path in &paths {
+				let contents = fs::read_to_string(path).map_err(in_file(path))?;
+				let formatted = cli::format_document(&contents);
+				if formatted == contents {
+					continue;
+				}
+				// Tidying mustn't change the page, like an empty "* " list item becoming a "*" paragraph.
+				// Only the whitespace at the ends of lines changes, and code blocks are left alone, so any other difference is in the markup.
+				let render = |s: &str| {
+					let images = Images::default();
+					let entry =
+						render_document(io::sink(), &site.render_context(&images), s, "", "");
+					entry.ok().map(|mut e| {
+						for text in [&mut e.content, &mut e.description] {
+							*text = text.split_whitespace().collect();
+						}
+						e
+					})
+				};
+				if render(&contents) != render(&formatted) {
+					eprintln!(
+						"{}: not formatted, because it would change how it renders",
+						path.display()
+					);
+					continue;
+				}
+				untidy += 1;
+				if args.check {
+					println!("{}", path.display());
+				} else {
+					fs::write(path, formatted)?;
+					if args.verbosity >= Verbosity::Verbose {
+						println!("Formatted {}", path.display());
+					}
+				}
+			}
+			if args.check && untidy > 0 {
+				return Ok(1);
+			}
+			if args.verbosity >= Verbosity::Normal && !args.check {
+				println!("Formatted {} of {} documents", untidy, paths.len());
+			}
+		}
+		Command::Clean => {
+			let config = load_site(&args, None)?.config;
+			let out = &config.output_dir;
+			// Deleting the output directory mustn't take the sources with it.
+			let sources = [
+				&config.content_dir,
+				&config.template_dir,
+				&config.static_dir,
+			];
+			if out.as_os_str().is_empty()
+				|| sources.iter().any(|s| {
+					fs::canonicalize(s)
+						.ok()
+						.zip(fs::canonicalize(out).ok())
+						.map_or(false, |(s, o)| s.starts_with(o))
+				}) {
+				return Err(io::Error::new(
+					io::ErrorKind::InvalidInput,
+					format!(
+						"Not deleting {} because it has the site's sources in it",
+						out.display()
+					),
+				));
+			}
+			for path in [out, &config.cache_path] {
+				let result = if path.is_dir() {
+					fs::remove_dir_all(path)
+				} else {
+					fs::remove_file(path)
+				};
+				match result {
+					Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(in_file(path)(e)),
+					_ => {}
+				}
+			}
+			if args.verbosity >= Verbosity::Normal {
+				println!("Deleted {} and the build cache", out.display());
+			}
 		}
 	}
+	Ok(0)
+}
+fn main() {
+	let args = match cli::parse(std::env::args().skip(1)) {
+		Ok(args) => args,
+		Err(e) => {
+			eprintln!("{}\n\n{}", e, cli::USAGE);
+			std::process::exit(cli::EXIT_USAGE);
+		}
+	};
+	let code = run(args).unwrap_or_else(|e| {
+		eprintln!("{}", e);
+		// Content errors are InvalidData, anything else is from reading and writing files.
+		match e.kind() {
+			io::ErrorKind::InvalidData => cli::EXIT_DATA,
+			io::ErrorKind::InvalidInput => cli::EXIT_USAGE,
+			_ => cli::EXIT_IO,
+		}
+	});
+	std::process::exit(code);
 }
 
-fn main() -> io::Result<()> {
-	Site::default().build()?;
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::path::PathBuf;
 
-	Ok(())
+	// A content directory in a temp directory, built with the repo's templates.
+	struct TestSite {
+		dir: PathBuf,
+	}
+	impl TestSite {
+		fn new(name: &str, files: &[(&str, &str)]) -> Self {
+			let dir =
+				std::env::temp_dir().join(format!("gen-site-test-{}-{}", name, std::process::id()));
+			let _ = fs::remove_dir_all(&dir);
+			for (path, contents) in files {
+				let path = dir.join("content").join(path);
+				fs::create_dir_all(path.parent().unwrap()).unwrap();
+				fs::write(path, contents).unwrap();
+			}
+			let config = format!(
+				"base_url = \"https://example.com\"\ntitle = \"Test\"\ncontent_dir = {:?}\noutput_dir = {:?}\ntemplate_dir = {:?}\nstatic_dir = {:?}\ncache_path = {:?}\n",
+				dir.join("content"),
+				dir.join("public"),
+				Path::new(env!("CARGO_MANIFEST_DIR")).join("templates"),
+				dir.join("static"),
+				dir.join("cache"),
+			);
+			fs::write(dir.join("config.toml"), config).unwrap();
+			Self { dir }
+		}
+		fn build(&self, drafts: bool) -> io::Result<()> {
+			let args = Args {
+				command: Command::Build,
+				src: None,
+				dest: None,
+				drafts,
+				base_url: None,
+				address: String::new(),
+				check: false,
+				verbosity: Verbosity::Quiet,
+			};
+			Arc::new(load_site_from(&self.dir.join("config.toml"), &args, None)?).build()
+		}
+		fn read(&self, path: &str) -> String {
+			fs::read_to_string(self.dir.join("public").join(path)).unwrap()
+		}
+		fn exists(&self, path: &str) -> bool {
+			self.dir.join("public").join(path).exists()
+		}
+	}
+	impl Drop for TestSite {
+		fn drop(&mut self) {
+			let _ = fs::remove_dir_all(&self.dir);
+		}
+	}
+	// Whether each of the strings is in the html, in order.
+	fn in_order(html: &str, strings: &[&str]) -> bool {
+		let found: Vec<_> = strings.iter().map(|s| html.find(s)).collect();
+		found.iter().all(Option::is_some) && found.windows(2).all(|w| w[0] < w[1])
+	}
+
+	#[test]
+	fn test_section_sort() {
+		let site = TestSite::new(
+			"sort",
+			&[
+				("blog/a.md", "= Older post\n:date: 2021-01-01\n\nText.\n"),
+				("blog/b.md", "= Undated post\n\nText.\n"),
+				("blog/c.md", "= Newer post\n:date: 2022-01-01\n\nText.\n"),
+				("docs/_index.md", "= Docs\n:sort_by: weight\n\nThe docs.\n"),
+				("docs/a.md", "= Unweighted page\n\nText.\n"),
+				("docs/b.md", "= Heavy page\n:weight: 2\n\nText.\n"),
+				("docs/c.md", "= Light page\n:weight: 1\n\nText.\n"),
+				(
+					"notes/_index.md",
+					"= Notes\n:sort_by: title\n\nSome notes.\n",
+				),
+				("notes/a.md", "= Zebras\n:date: 2022-01-01\n\nText.\n"),
+				("notes/b.md", "= Aardvarks\n\nText.\n"),
+			],
+		);
+		site.build(false).unwrap();
+		let blog = site.read("blog/index.html");
+		assert!(in_order(
+			&blog,
+			&["Newer post", "Older post", "Undated post"]
+		));
+		let docs = site.read("docs/index.html");
+		assert!(in_order(
+			&docs,
+			&[
+				"Docs",
+				"The docs.",
+				"Light page",
+				"Heavy page",
+				"Unweighted page"
+			]
+		));
+		let notes = site.read("notes/index.html");
+		assert!(in_order(&notes, &["Aardvarks", "Zebras"]));
+	}
+
+	#[test]
+	fn test_drafts() {
+		let site = TestSite::new(
+			"drafts",
+			&[
+				(
+					"blog/done.md",
+					"= Finished post\n:date: 2021-01-01\n\nText.\n",
+				),
+				(
+					"blog/wip.md",
+					"= Unfinished post\n:date: 2022-01-01\n:draft:\n\nText.\n",
+				),
+				(
+					"blog/wip-dir/index.md",
+					"= Unfinished directory\n:draft:\n\n![A picture](a.png)\n",
+				),
+				("blog/wip-dir/a.png", ""),
+			],
+		);
+		site.build(false).unwrap();
+		assert!(site.exists("blog/done/index.html"));
+		assert!(!site.exists("blog/wip/index.html"));
+		assert!(!site.exists("blog/wip-dir"));
+		let blog = site.read("blog/index.html");
+		assert!(blog.contains("Finished post"));
+		assert!(!blog.contains("Unfinished"));
+		assert!(!site.read("atom.xml").contains("Unfinished"));
+
+		site.build(true).unwrap();
+		let done = site.read("blog/done/index.html");
+		assert!(!done.contains("noindex") && !done.contains(">DRAFT<"));
+		let wip = site.read("blog/wip/index.html");
+		assert!(wip.contains(r#"<meta name="robots" content="noindex">"#));
+		assert!(wip.contains(">DRAFT</p>"));
+		assert!(site.exists("blog/wip-dir/index.html") && site.exists("blog/wip-dir/a.png"));
+		let blog = site.read("blog/index.html");
+		assert!(in_order(
+			&blog,
+			&["Unfinished post", "<mark>Draft</mark>", "Finished post"]
+		));
+	}
+
+	#[test]
+	fn test_tags() {
+		let site = TestSite::new(
+			"tags",
+			&[
+				(
+					"a.md",
+					"= First post\n:date: 2021-01-01\n:tags: Web Dev, Rust\n\nText.\n",
+				),
+				(
+					"b.md",
+					"= Second post\n:date: 2022-01-01\n:tags: web-dev\n\nText.\n",
+				),
+				("c.md", "= Untagged post\n\nText.\n"),
+			],
+		);
+		site.build(false).unwrap();
+		let mut tags: Vec<_> = fs::read_dir(site.dir.join("public/tags"))
+			.unwrap()
+			.map(|e| e.unwrap().file_name().into_string().unwrap())
+			.collect();
+		tags.sort();
+		assert_eq!(tags, ["index.html", "rust", "web-dev"]);
+		// Both spellings are one tag, shown with one of them.
+		let list = site.read("tags/index.html");
+		assert!(in_order(&list, &["/tags/rust/", "/tags/web-dev/"]));
+		assert!(list.contains(">Web Dev</a>") != list.contains(">web-dev</a>"));
+		let web_dev = site.read("tags/web-dev/index.html");
+		assert!(in_order(&web_dev, &["Second post", "First post"]));
+		assert!(!web_dev.contains("Untagged post"));
+		let rust = site.read("tags/rust/index.html");
+		assert!(rust.contains("First post") && !rust.contains("Second post"));
+		assert!(site.read("tags/web-dev/atom.xml").contains("Second post"));
+	}
+	#[test]
+	fn test_reproducible() {
+		// Enough pages for the thread pool to finish them in different orders.
+		let mut files = Vec::new();
+		for i in 0..40 {
+			let path = format!("{}/post-{}.md", ["blog", "notes", "projects"][i % 3], i);
+			let contents = format!(
+				"= Post {}\n:date: 2021-01-{:02}\n:tags: Tag {}, Shared\n\nSome text with ![a picture](/blog/a.png).\n",
+				i,
+				i % 28 + 1,
+				i % 5
+			);
+			files.push((path, contents));
+		}
+		files.push(("blog/a.png".to_string(), String::new()));
+		let files: Vec<_> = files
+			.iter()
+			.map(|(p, c)| (p.as_str(), c.as_str()))
+			.collect();
+		let site = TestSite::new("reproducible", &files);
+		// Every output file and its contents.
+		fn snapshot(dir: &Path, outputs: &mut Vec<(PathBuf, Vec<u8>)>) {
+			for path in read_dir_sorted(dir).unwrap() {
+				if path.is_dir() {
+					snapshot(&path, outputs);
+				} else {
+					outputs.push((path.clone(), fs::read(&path).unwrap()));
+				}
+			}
+		}
+		let public = site.dir.join("public");
+		let mut builds = Vec::new();
+		for clean in [true, true, false] {
+			if clean {
+				let _ = fs::remove_dir_all(&public);
+				let _ = fs::remove_file(site.dir.join("cache"));
+			}
+			site.build(false).unwrap();
+			let mut outputs = Vec::new();
+			snapshot(&public, &mut outputs);
+			builds.push(outputs);
+		}
+		// Pages, section indexes, a page and a feed for each of the 6 tags, the tag list, 404.html, the feed and the picture.
+		assert_eq!(builds[0].len(), 40 + 4 + 6 * 2 + 1 + 1 + 1 + 1);
+		assert!(builds[0] == builds[1], "two clean builds differ");
+		assert!(
+			builds[0] == builds[2],
+			"a cached build differs from a clean one"
+		);
+	}
 }
@@ -1,6 +1,7 @@
 #![allow(unused)]
 use std::any::{type_name, Any, TypeId};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
 use std::panic::Location;
 use std::str::pattern::Pattern;
 use std::iter::Fuse;
@@ -12,6 +13,67 @@ pub fn take_pattern<'i, P: Pattern<'i>>(input: &mut &'i str, p: P) -> Option<&'i
 	Some(prefix)
 }
 
+// How a pattern is described when it fails to match.
+pub trait Describe {
+	fn describe(&self) -> String;
+}
+impl Describe for &str {
+	fn describe(&self) -> String {
+		format!("{:?}", self)
+	}
+}
+impl Describe for &String {
+	fn describe(&self) -> String {
+		format!("{:?}", self)
+	}
+}
+impl Describe for char {
+	fn describe(&self) -> String {
+		format!("{:?}", self)
+	}
+}
+impl<const N: usize> Describe for [char; N] {
+	fn describe(&self) -> String {
+		self.as_slice().describe()
+	}
+}
+impl Describe for &[char] {
+	fn describe(&self) -> String {
+		let chars: Vec<_> = self.iter().map(|c| format!("{:?}", c)).collect();
+		format!("one of {}", chars.join(", "))
+	}
+}
+impl<F: FnMut(char) -> bool> Describe for F {
+	fn describe(&self) -> String {
+		// A closure's type name is only where it was written, so wrap it in described() for a better message.
+		"a matching character".into()
+	}
+}
+
+// A pattern with a description of what it matches, for patterns like char predicates that can't describe themselves.
+#[derive(Debug, Clone, Copy)]
+pub struct Described<P> {
+	pattern: P,
+	description: &'static str,
+}
+pub fn described<P>(pattern: P, description: &'static str) -> Described<P> {
+	Described {
+		pattern,
+		description,
+	}
+}
+impl<P> Describe for Described<P> {
+	fn describe(&self) -> String {
+		self.description.into()
+	}
+}
+impl<'i, P: Pattern<'i>> Pattern<'i> for Described<P> {
+	type Searcher = P::Searcher;
+	fn into_searcher(self, haystack: &'i str) -> Self::Searcher {
+		self.pattern.into_searcher(haystack)
+	}
+}
+
 #[cfg(test)]
 mod tests;
 
@@ -66,27 +128,160 @@ pub enum ParseError<'i, L: Lexer<'i>> {
 		caller: &'static Location<'static>,
 		expected: String, // TODO: How to represent the pattern?
 	},
+	MemoTypeMismatch {
+		caller: &'static Location<'static>,
+		parser: &'static str,
+		expected: &'static str,
+		found: &'static str,
+	},
+}
+impl<'i, L: Lexer<'i>> ParseError<'i, L> {
+	fn caller(&self) -> &'static Location<'static> {
+		match self {
+			Self::LexError { caller, .. }
+			| Self::ExpectedToken { caller, .. }
+			| Self::ExpectedPattern { caller, .. }
+			| Self::MemoTypeMismatch { caller, .. } => caller,
+		}
+	}
+}
+
+// The furthest point that parsing reached before failing, and everything that could have continued from there.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+	pub line: usize,
+	pub col: usize,
+	pub expected: Vec<String>,
+	pub notes: Vec<String>,
+	pub callers: Vec<&'static Location<'static>>,
+	pub source_line: String,
+}
+impl Diagnostic {
+	pub fn message(&self) -> String {
+		match self.expected.split_last() {
+			None => "parsing failed".into(),
+			Some((last, [])) => format!("expected {}", last),
+			Some((last, rest)) => format!("expected {} or {}", rest.join(", "), last),
+		}
+	}
+}
+impl Display for Diagnostic {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		writeln!(f, "{}:{}: {}", self.line, self.col, self.message())?;
+		let gutter = self.line.to_string().len();
+		writeln!(f, "{} | {}", self.line, self.source_line)?;
+		// Keep tabs so that the caret lines up with the source line.
+		let indent: String = self
+			.source_line
+			.chars()
+			.take(self.col - 1)
+			.map(|c| if c == '\t' { '\t' } else { ' ' })
+			.collect();
+		writeln!(f, "{:gutter$} | {}^", "", indent, gutter = gutter)?;
+		for note in &self.notes {
+			writeln!(f, "{:gutter$} = note: {}", "", note, gutter = gutter)?;
+		}
+		let callers: Vec<_> = self.callers.iter().map(|c| c.to_string()).collect();
+		write!(
+			f,
+			"{:gutter$} = tried by: {}",
+			"",
+			callers.join(", "),
+			gutter = gutter
+		)
+	}
+}
+
+// Which parser a memo entry belongs to. A fn item or a closure that doesn't capture anything is the only value of its type, so the type is enough to tell it apart. Other parsers, like fn pointers, have to be named.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Rule {
+	Type(TypeId),
+	Named(&'static str),
+}
+
+// Fails to compile for types that can have more than one value, when epar uses it.
+struct OnlyValue<P>(P);
+impl<P> OnlyValue<P> {
+	const CHECK: () = assert!(
+		std::mem::size_of::<P>() == 0,
+		"epar can only memoize fn items and closures that don't capture anything, use erule for other parsers"
+	);
+}
+
+// A memoized parser output along with the name of its type for reporting mismatches.
+#[derive(Debug)]
+struct Output {
+	value: Box<dyn Any>,
+	type_name: &'static str,
+}
+
+// Where a parser stopped and what it output, or None if it failed.
+type Answer = Option<(usize, usize, Output)>;
+
+#[derive(Debug)]
+enum MemoEntry {
+	// The parser hasn't finished at this position yet, so a lookup means it's left recursive.
+	LeftRecursion(usize),
+	Answer(Answer),
+}
+
+// Left recursion is handled by growing a seed as described in "Packrat Parsers Can Support Left Recursion" (Warth, Douglass, and Millstein).
+#[derive(Debug)]
+struct LeftRecursion {
+	seed: Answer,
+	rule: Rule,
+	head: Option<usize>,
+}
+#[derive(Debug)]
+struct Head {
+	rule: Rule,
+	// Rules that are part of the left recursion, and which of them still need to be re-evaluated during this growth iteration.
+	involved: HashSet<Rule>,
+	eval: HashSet<Rule>,
+}
+enum Recall {
+	Miss,
+	Fail,
+	Hit,
+	LeftRecursion(usize),
 }
 
 #[derive(Debug)]
 pub struct PackRat<'i, L: Lexer<'i> = NoLex<'i>> {
+	source: &'i str,
 	lexer: Fuse<L>,
 	tokens: Vec<LexRes<'i, L::Token, L::LexError>>,
+	// Where each token starts in the source.
+	offsets: Vec<usize>,
+	lexed: usize,
 	tokens_index: usize,
 	str_consumed: usize,
-	errors: Vec<(usize, usize, ParseError<'i, L>)>,
-	// TODO: add a map that stores rescursive depth.
-	memo: HashMap<(usize, usize, usize), Option<(usize, usize, Box<()>)>>,
+	// Only the failures at the furthest offset are kept.
+	errors: Vec<(usize, ParseError<'i, L>)>,
+	memo: HashMap<(usize, usize, Rule), MemoEntry>,
+	left_recursions: Vec<LeftRecursion>,
+	// Stack of indexes into left_recursions for the parsers that are currently being evaluated.
+	lr_stack: Vec<usize>,
+	heads: Vec<Head>,
+	// Positions where a left recursive parser is being grown.
+	growing: HashMap<(usize, usize), usize>,
 }
 impl<'i, L: Lexer<'i>> PackRat<'i, L> {
 	pub fn new(s: &'i str) -> Self {
 		Self {
+			source: s,
 			lexer: L::new(s).fuse(),
 			tokens: Vec::new(),
+			offsets: Vec::new(),
+			lexed: 0,
 			tokens_index: 0,
 			str_consumed: 0,
 			errors: Vec::new(),
 			memo: HashMap::new(),
+			left_recursions: Vec::new(),
+			lr_stack: Vec::new(),
+			heads: Vec::new(),
+			growing: HashMap::new(),
 		}
 	}
 	fn get_lex_res(&mut self) -> Option<LexRes<'i, L::Token, L::LexError>> {
@@ -98,6 +293,20 @@ impl<'i, L: Lexer<'i>> PackRat<'i, L> {
 		}
 		if self.tokens_index == self.tokens.len() {
 			let tok = self.lexer.next()?;
+			// Tokens don't say how much source they cover, so they're placed at the end of the last text.
+			if let LexRes::Text(t) = &tok {
+				let start = (t.as_ptr() as usize).wrapping_sub(self.source.as_ptr() as usize);
+				if start
+					.checked_add(t.len())
+					.map_or(false, |end| end <= self.source.len())
+				{
+					self.lexed = start;
+				}
+				self.offsets.push(self.lexed);
+				self.lexed += t.len();
+			} else {
+				self.offsets.push(self.lexed);
+			}
 			self.tokens.push(tok);
 			self.str_consumed = 0; // I'm not certain about this... but it's working...
 		}
@@ -119,82 +328,317 @@ impl<'i, L: Lexer<'i>> PackRat<'i, L> {
 			None
 		}
 	}
+	fn offset(&self) -> usize {
+		match self.offsets.get(self.tokens_index) {
+			Some(offset) => offset + self.str_consumed,
+			None => self.source.len(),
+		}
+	}
+	fn fail(&mut self, e: ParseError<'i, L>) {
+		let mut offset = self.offset();
+		// Lexer errors are more useful than whatever we expected to find instead.
+		let e = match self.tokens.get(self.tokens_index) {
+			Some(LexRes::LexError(le)) => ParseError::LexError {
+				caller: e.caller(),
+				e: le.clone(),
+			},
+			_ => e,
+		};
+		// A mismatched memo is a bug in the grammar rather than the input, so it's kept however far parsing gets.
+		let is_mismatch = |e: &ParseError<'i, L>| matches!(e, ParseError::MemoTypeMismatch { .. });
+		match self.errors.last() {
+			Some((furthest, _)) if *furthest > offset && is_mismatch(&e) => offset = *furthest,
+			Some((furthest, _)) if *furthest > offset => return,
+			Some((furthest, _)) if *furthest < offset => {
+				self.errors.retain(|(_, e)| is_mismatch(e))
+			}
+			_ => {}
+		}
+		self.errors.push((offset, e));
+	}
 	#[track_caller]
 	pub fn etok<T: PartialEq<L::Token> + std::fmt::Debug>(&mut self, tok: T) -> Option<L::Token> {
-		let t = self.get_token()?;
-		if tok == t {
-			self.tokens_index += 1;
-			Some(t)
-		} else {
-			self.errors.push((
-				self.tokens_index,
-				self.str_consumed,
-				ParseError::ExpectedToken {
+		match self.get_token() {
+			Some(t) if tok == t => {
+				self.tokens_index += 1;
+				Some(t)
+			}
+			_ => {
+				self.fail(ParseError::ExpectedToken {
 					caller: Location::caller(),
-					expected: format!("{:?}", tok)
-				}
-			));
-			None
+					expected: format!("{:?}", tok),
+				});
+				None
+			}
 		}
 	}
 	#[track_caller]
-	pub fn epat<P: Pattern<'i>>(&mut self, pat: P) -> Option<&'i str> {
-		let i = self.get_input()?;
-		if let Some(postfix) = i.strip_prefix(pat) {
+	pub fn epat<P: Pattern<'i> + Describe>(&mut self, pat: P) -> Option<&'i str> {
+		let expected = pat.describe();
+		let input = self.get_input();
+		if let Some((i, postfix)) = input.and_then(|i| Some((i, i.strip_prefix(pat)?))) {
 			let prefix_len = i.len() - postfix.len();
 			let prefix = &i[..prefix_len];
 			self.str_consumed += prefix_len;
 			Some(prefix)
 		} else {
-			self.errors.push((
-				self.tokens_index,
-				self.str_consumed,
-				ParseError::ExpectedPattern {
-					caller: Location::caller(),
-					expected: type_name::<P>().into(),
+			self.fail(ParseError::ExpectedPattern {
+				caller: Location::caller(),
+				expected,
+			});
+			None
+		}
+	}
+	// Report the furthest position that parsing failed at, or None if nothing failed.
+	pub fn diagnostics(&self) -> Option<Diagnostic> {
+		let (offset, _) = self.errors.last()?;
+		let line_start = self.source[..*offset].rfind('\n').map_or(0, |i| i + 1);
+		let line_end = self.source[line_start..]
+			.find('\n')
+			.map_or(self.source.len(), |i| line_start + i);
+		let mut diagnostic = Diagnostic {
+			line: self.source[..line_start].matches('\n').count() + 1,
+			col: self.source[line_start..*offset].chars().count() + 1,
+			expected: Vec::new(),
+			notes: Vec::new(),
+			callers: Vec::new(),
+			source_line: self.source[line_start..line_end]
+				.trim_end_matches('\r')
+				.into(),
+		};
+		for (_, e) in &self.errors {
+			let (expected, note) = match e {
+				ParseError::ExpectedToken { expected, .. }
+				| ParseError::ExpectedPattern { expected, .. } => (Some(expected.clone()), None),
+				ParseError::LexError { e, .. } => (None, Some(format!("lexer error: {:?}", e))),
+				ParseError::MemoTypeMismatch {
+					parser,
+					expected,
+					found,
+					..
+				} => (
+					None,
+					Some(format!(
+						"{} was memoized as {} but loaded as {}",
+						parser, found, expected
+					)),
+				),
+			};
+			if let Some(expected) = expected.filter(|x| !diagnostic.expected.contains(x)) {
+				diagnostic.expected.push(expected);
+			}
+			if let Some(note) = note.filter(|n| !diagnostic.notes.contains(n)) {
+				diagnostic.notes.push(note);
+			}
+			if !diagnostic.callers.contains(&e.caller()) {
+				diagnostic.callers.push(e.caller());
+			}
+		}
+		Some(diagnostic)
+	}
+	fn store<O: Clone + 'static>(&self, o: Option<O>) -> Answer {
+		o.map(|o| {
+			let output = Output {
+				value: Box::new(o),
+				type_name: type_name::<O>(),
+			};
+			(self.tokens_index, self.str_consumed, output)
+		})
+	}
+	#[track_caller]
+	fn load<O: Clone + 'static, P>(&mut self, rule: Rule, answer: &Answer) -> Option<O> {
+		let (new_i, new_c, output) = answer.as_ref()?;
+		if let Some(o) = output.value.downcast_ref::<O>() {
+			self.tokens_index = *new_i;
+			self.str_consumed = *new_c;
+			Some(o.clone())
+		} else {
+			self.fail(ParseError::MemoTypeMismatch {
+				caller: Location::caller(),
+				parser: match rule {
+					Rule::Named(name) => name,
+					Rule::Type(_) => type_name::<P>(),
 				},
-			));
+				expected: type_name::<O>(),
+				found: output.type_name,
+			});
 			None
 		}
 	}
 	#[track_caller]
-	pub fn epar<O: Clone>(&mut self, par: Parser<'i, L, O>) -> Option<O> {
-		if let Some(ret) = self
-			.memo
-			.get(&(self.tokens_index, self.str_consumed, par as usize))
-		{
-			if let Some((new_i, new_c, o)) = ret {
-				self.tokens_index = *new_i;
-				self.str_consumed = *new_c;
-				let o = unsafe { std::mem::transmute::<&Box<()>, &Box<O>>(o) };
-				Some(*o.clone())
-			} else {
-				None
+	fn recall<O, P>(&mut self, par: P, key: (usize, usize, Rule)) -> Recall
+	where
+		O: Clone + 'static,
+		P: Fn(&mut Self) -> Option<O>,
+	{
+		let (i, c, rule) = key;
+		if let Some(&h) = self.growing.get(&(i, c)) {
+			// While growing, only the rules involved in the left recursion may be evaluated.
+			let head = &mut self.heads[h];
+			if !self.memo.contains_key(&key) && rule != head.rule && !head.involved.contains(&rule)
+			{
+				return Recall::Fail;
 			}
-		} else {
-			let old_str_consumed = self.str_consumed;
-			let old_tokens_index = self.tokens_index;
+			if head.eval.remove(&rule) {
+				let ret = par(self);
+				let answer = self.store(ret);
+				self.memo.insert(key, MemoEntry::Answer(answer));
+			}
+		}
+		match self.memo.get(&key) {
+			None => Recall::Miss,
+			Some(MemoEntry::LeftRecursion(lr)) => Recall::LeftRecursion(*lr),
+			Some(MemoEntry::Answer(_)) => Recall::Hit,
+		}
+	}
+	fn setup_lr(&mut self, rule: Rule, lr: usize) {
+		let head = match self.left_recursions[lr].head {
+			Some(head) => head,
+			None => {
+				self.heads.push(Head {
+					rule,
+					involved: HashSet::new(),
+					eval: HashSet::new(),
+				});
+				self.left_recursions[lr].head = Some(self.heads.len() - 1);
+				self.heads.len() - 1
+			}
+		};
+		// Everything called between the first application of the rule and this one is involved in the recursion.
+		for &s in self.lr_stack.iter().rev() {
+			if self.left_recursions[s].head == Some(head) {
+				break;
+			}
+			self.left_recursions[s].head = Some(head);
+			let involved = self.left_recursions[s].rule;
+			self.heads[head].involved.insert(involved);
+		}
+	}
+	#[track_caller]
+	fn grow_lr<O, P>(&mut self, par: P, key: (usize, usize, Rule), head: usize) -> Option<O>
+	where
+		O: Clone + 'static,
+		P: Fn(&mut Self) -> Option<O>,
+	{
+		let (i, c, _) = key;
+		self.growing.insert((i, c), head);
+		loop {
+			self.tokens_index = i;
+			self.str_consumed = c;
+			self.heads[head].eval = self.heads[head].involved.clone();
 			let ret = par(self);
-			self.memo.insert(
-				(old_tokens_index, old_str_consumed, par as usize),
-				ret.clone().map(|o| {
-					(self.tokens_index, self.str_consumed, unsafe {
-						std::mem::transmute::<Box<O>, Box<()>>(Box::new(o.clone()))
-					})
-				}),
-			);
-			if ret.is_none() {
-				// TODO: We actually should only reset before trying another option - that way we'll have more information to use when creating diagnostics.
-				// Restore packrat state if the parser failed to parse.
-				self.str_consumed = old_str_consumed;
-				self.tokens_index = old_tokens_index;
-			}
-			ret
+			let end = match self.memo.get(&key) {
+				Some(MemoEntry::Answer(Some((new_i, new_c, _)))) => (*new_i, *new_c),
+				_ => (i, c),
+			};
+			if ret.is_none() || (self.tokens_index, self.str_consumed) <= end {
+				break;
+			}
+			let answer = self.store(ret);
+			self.memo.insert(key, MemoEntry::Answer(answer));
+		}
+		self.growing.remove(&(i, c));
+		match self.memo.remove(&key) {
+			Some(MemoEntry::Answer(answer)) => {
+				let ret = self.load::<O, P>(key.2, &answer);
+				self.memo.insert(key, MemoEntry::Answer(answer));
+				ret
+			}
+			_ => None,
+		}
+	}
+	// Run a parser, memoized by its type. Parsers that share a type with others, like fn pointers or closures that capture something, can't be told apart that way, so they don't compile here: use erule to memoize them by name.
+	#[track_caller]
+	pub fn epar<O, P>(&mut self, par: P) -> Option<O>
+	where
+		O: Clone + 'static,
+		P: Fn(&mut Self) -> Option<O> + Copy + 'static,
+	{
+		let () = OnlyValue::<P>::CHECK;
+		self.memoized(Rule::Type(TypeId::of::<P>()), par)
+	}
+	// Run a parser, memoized by name. Every parser given the same name has to be the same rule, with the same output type.
+	#[track_caller]
+	pub fn erule<O, P>(&mut self, name: &'static str, par: P) -> Option<O>
+	where
+		O: Clone + 'static,
+		P: Fn(&mut Self) -> Option<O> + Copy,
+	{
+		self.memoized(Rule::Named(name), par)
+	}
+	#[track_caller]
+	fn memoized<O, P>(&mut self, rule: Rule, par: P) -> Option<O>
+	where
+		O: Clone + 'static,
+		P: Fn(&mut Self) -> Option<O> + Copy,
+	{
+		let old_str_consumed = self.str_consumed;
+		let old_tokens_index = self.tokens_index;
+		let key = (old_tokens_index, old_str_consumed, rule);
+		let ret = match self.recall(par, key) {
+			Recall::Fail => None,
+			Recall::Hit => match self.memo.remove(&key) {
+				Some(MemoEntry::Answer(answer)) => {
+					let ret = self.load::<O, P>(rule, &answer);
+					self.memo.insert(key, MemoEntry::Answer(answer));
+					ret
+				}
+				_ => unreachable!(),
+			},
+			Recall::LeftRecursion(lr) => {
+				// The parser called itself without consuming anything: answer with the seed so far.
+				self.setup_lr(key.2, lr);
+				let seed = self.left_recursions[lr].seed.take();
+				let ret = self.load::<O, P>(rule, &seed);
+				self.left_recursions[lr].seed = seed;
+				ret
+			}
+			Recall::Miss => {
+				self.left_recursions.push(LeftRecursion {
+					seed: None,
+					rule: key.2,
+					head: None,
+				});
+				let lr = self.left_recursions.len() - 1;
+				self.lr_stack.push(lr);
+				self.memo.insert(key, MemoEntry::LeftRecursion(lr));
+				let ret = par(self);
+				self.lr_stack.pop();
+				let answer = self.store(ret.clone());
+				match self.left_recursions[lr].head {
+					Some(head) if self.heads[head].rule == key.2 => {
+						// This is the head of the left recursion: grow the seed until it stops getting longer.
+						let found = answer.is_some();
+						self.memo.insert(key, MemoEntry::Answer(answer));
+						if found {
+							self.grow_lr(par, key, head)
+						} else {
+							None
+						}
+					}
+					Some(_) => {
+						// Involved in someone else's left recursion: the head will re-evaluate us while it grows.
+						self.left_recursions[lr].seed = answer;
+						ret
+					}
+					None => {
+						self.memo.insert(key, MemoEntry::Answer(answer));
+						ret
+					}
+				}
+			}
+		};
+		if ret.is_none() {
+			// TODO: We actually should only reset before trying another option - that way we'll have more information to use when creating diagnostics.
+			// Restore packrat state if the parser failed to parse.
+			self.str_consumed = old_str_consumed;
+			self.tokens_index = old_tokens_index;
 		}
+		ret
 	}
 	// #[track_caller]
 	pub fn is_eoi(&mut self) -> bool {
-		self.tokens_index == self.tokens.len() && self.get_lex_res().is_none()
+		// Memoized answers can leave us at the end of a text token instead of the start of the next one, which get_lex_res sorts out.
+		self.get_lex_res().is_none()
 	}
-	// TODO: Support Left Recursion.
 }
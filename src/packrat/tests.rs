@@ -116,3 +116,283 @@ fn t3() {
 	assert_eq!(pr.etok(ABCToken::B), Some(ABCToken::B));
 	assert_eq!(pr.epat("weird lexer."), Some("weird lexer."));
 }
+
+// epat only strips a single match of a char pattern.
+fn take_while(pr: &mut PackRat, f: fn(char) -> bool, description: &'static str) -> Option<String> {
+	let f = described(f, description);
+	let mut s = String::from(pr.epat(f)?);
+	while let Some(c) = pr.epat(f) {
+		s.push_str(c);
+	}
+	Some(s)
+}
+fn number(pr: &mut PackRat) -> Option<i64> {
+	take_while(pr, |c| c.is_ascii_digit(), "a digit")?
+		.parse()
+		.ok()
+}
+
+#[test]
+fn direct_left_recursion() {
+	// expr = expr '-' term | term
+	// term = term '*' number | number
+	fn expr(pr: &mut PackRat) -> Option<i64> {
+		if let Some(l) = pr.epar(expr) {
+			if let Some(_) = pr.epat("-") {
+				if let Some(r) = pr.epar(term) {
+					return Some(l - r);
+				}
+			}
+		}
+		pr.epar(term)
+	}
+	fn term(pr: &mut PackRat) -> Option<i64> {
+		if let Some(l) = pr.epar(term) {
+			if let Some(_) = pr.epat("*") {
+				if let Some(r) = number(pr) {
+					return Some(l * r);
+				}
+			}
+		}
+		number(pr)
+	}
+
+	let mut pr = PackRat::new("10-2*3-4");
+	// Left associative: (10 - (2 * 3)) - 4
+	assert_eq!(pr.epar(expr), Some(0));
+	assert!(pr.is_eoi());
+
+	let mut pr = PackRat::new("7");
+	assert_eq!(pr.epar(expr), Some(7));
+	assert!(pr.is_eoi());
+
+	// The longest parse wins, and what's left over is still there.
+	let mut pr = PackRat::new("8-2-x");
+	assert_eq!(pr.epar(expr), Some(6));
+	assert_eq!(pr.epat("-x"), Some("-x"));
+
+	let mut pr = PackRat::new("-1");
+	assert_eq!(pr.epar(expr), None);
+	assert_eq!(pr.epat("-1"), Some("-1"));
+}
+
+#[test]
+fn indirect_left_recursion() {
+	// chain = call | member | ident
+	// call = chain '()'
+	// member = chain '.' ident
+	#[derive(Debug, PartialEq, Clone)]
+	enum Chain {
+		Ident(String),
+		Call(Box<Chain>),
+		Member(Box<Chain>, String),
+	}
+	fn ident(pr: &mut PackRat) -> Option<String> {
+		take_while(pr, char::is_alphabetic, "a letter")
+	}
+	fn chain(pr: &mut PackRat) -> Option<Chain> {
+		pr.epar(call)
+			.or_else(|| pr.epar(member))
+			.or_else(|| ident(pr).map(Chain::Ident))
+	}
+	fn call(pr: &mut PackRat) -> Option<Chain> {
+		let c = pr.epar(chain)?;
+		pr.epat("()")?;
+		Some(Chain::Call(Box::new(c)))
+	}
+	fn member(pr: &mut PackRat) -> Option<Chain> {
+		let c = pr.epar(chain)?;
+		pr.epat(".")?;
+		Some(Chain::Member(Box::new(c), ident(pr)?))
+	}
+
+	let mut pr = PackRat::new("document.body.append()");
+	use Chain::*;
+	assert_eq!(
+		pr.epar(chain),
+		Some(Call(Box::new(Member(
+			Box::new(Member(Box::new(Ident("document".into())), "body".into())),
+			"append".into()
+		))))
+	);
+	assert!(pr.is_eoi());
+
+	let mut pr = PackRat::new("f().x");
+	assert_eq!(
+		pr.epar(chain),
+		Some(Member(
+			Box::new(Call(Box::new(Ident("f".into())))),
+			"x".into()
+		))
+	);
+	assert!(pr.is_eoi());
+}
+
+#[test]
+fn memo_types() {
+	// Different parsers at the same position mustn't share memo entries, even if one is a closure.
+	fn word(pr: &mut PackRat) -> Option<String> {
+		take_while(pr, char::is_alphabetic, "a letter")
+	}
+	fn word_len(pr: &mut PackRat) -> Option<usize> {
+		pr.epar(word).map(|w| w.len())
+	}
+	let mut pr = PackRat::new("hello");
+	assert_eq!(pr.epar(word_len), Some(5));
+	pr.tokens_index = 0;
+	pr.str_consumed = 0;
+	assert_eq!(pr.epar(word), Some("hello".to_string()));
+	pr.tokens_index = 0;
+	pr.str_consumed = 0;
+	assert_eq!(
+		pr.epar(|pr: &mut PackRat| pr.epar(word_len).map(|l| l * 2)),
+		Some(10)
+	);
+	assert_eq!(pr.epar(word_len), None);
+	assert!(pr.is_eoi());
+	assert!(pr
+		.errors
+		.iter()
+		.all(|(_, e)| !matches!(e, ParseError::MemoTypeMismatch { .. })));
+}
+
+#[test]
+fn memo_fn_pointers() {
+	// fn pointers all share a type, so they're memoized by name instead.
+	let x: Parser<'_, NoLex, i64> = |pr| pr.epat("x").map(|_| 0);
+	let n: Parser<'_, NoLex, i64> = number;
+	let mut pr = PackRat::new("12x");
+	assert_eq!(pr.erule("x", x), None);
+	assert_eq!(pr.erule("number", n), Some(12));
+	assert_eq!(pr.erule("x", x), Some(0));
+	assert!(pr.is_eoi());
+
+	// Named rules are memoized, so they can be left recursive.
+	// expr = expr '-' number | number
+	fn expr(pr: &mut PackRat) -> Option<i64> {
+		let rule: Parser<'_, NoLex, i64> = expr;
+		if let Some(l) = pr.erule("expr", rule) {
+			if let Some(_) = pr.epat("-") {
+				if let Some(r) = number(pr) {
+					return Some(l - r);
+				}
+			}
+		}
+		number(pr)
+	}
+	let mut pr = PackRat::new("10-2-3");
+	assert_eq!(pr.erule("expr", expr), Some(5));
+	assert!(pr.is_eoi());
+}
+
+#[test]
+fn parser_alias_left_recursion() {
+	// sum = sum '+' product | product
+	// product = product '*' number | number
+	// The rules only ever reach each other through Parser values.
+	type Rule<'i> = (&'static str, Parser<'i, NoLex<'i>, i64>);
+	fn binary<'i>(pr: &mut PackRat<'i>, left: Rule<'i>, op: &str, right: Rule<'i>) -> Option<i64> {
+		if let Some(l) = pr.erule(left.0, left.1) {
+			if pr.epat(op).is_some() {
+				if let Some(r) = pr.erule(right.0, right.1) {
+					return Some(if op == "+" { l + r } else { l * r });
+				}
+			}
+		}
+		pr.erule(right.0, right.1)
+	}
+	fn sum(pr: &mut PackRat) -> Option<i64> {
+		binary(pr, ("sum", sum), "+", ("product", product))
+	}
+	fn product(pr: &mut PackRat) -> Option<i64> {
+		binary(pr, ("product", product), "*", ("number", number))
+	}
+	for (input, expected) in [("1+2*3+4", 11), ("2*3*4+1", 25), ("5", 5)] {
+		let mut pr = PackRat::new(input);
+		assert_eq!(
+			pr.erule("sum", sum as Parser<'_, NoLex, i64>),
+			Some(expected)
+		);
+		assert!(pr.is_eoi());
+	}
+}
+
+#[test]
+fn memo_type_mismatch() {
+	fn word(pr: &mut PackRat) -> Option<String> {
+		take_while(pr, char::is_alphabetic, "a letter")
+	}
+	fn word_len(pr: &mut PackRat) -> Option<usize> {
+		take_while(pr, char::is_alphabetic, "a letter").map(|w| w.len())
+	}
+	// Two different rules with the same name.
+	let mut pr = PackRat::new("hello world");
+	assert_eq!(pr.erule("word", word), Some("hello".to_string()));
+	pr.epat(" ");
+	assert_eq!(pr.epat("!"), None);
+	pr.tokens_index = 0;
+	pr.str_consumed = 0;
+	assert_eq!(pr.erule("word", word_len), None);
+	let d = pr.diagnostics().unwrap();
+	// The mismatch isn't lost to failures further into the input.
+	assert_eq!((d.line, d.col), (1, 7));
+	assert_eq!(d.expected, ["\"!\""]);
+	assert_eq!(
+		d.notes,
+		[format!(
+			"word was memoized as {} but loaded as usize",
+			type_name::<String>()
+		)]
+	);
+}
+
+#[test]
+fn diagnostics() {
+	fn list(pr: &mut PackRat) -> Option<Vec<i64>> {
+		while pr.epat(char::is_whitespace).is_some() {}
+		pr.epat("[")?;
+		let mut items = vec![pr.epar(number)?];
+		while pr.epat(",").is_some() {
+			items.push(pr.epar(number)?);
+		}
+		pr.epat("]")?;
+		Some(items)
+	}
+	let mut pr = PackRat::new("[1,2]");
+	assert_eq!(pr.epar(list), Some(vec![1, 2]));
+
+	let mut pr = PackRat::new("\n\t[1,2 3]");
+	assert_eq!(pr.epar(list), None);
+	let d = pr.diagnostics().unwrap();
+	assert_eq!((d.line, d.col), (2, 6));
+	assert_eq!(d.expected, ["a digit", "\",\"", "\"]\""]);
+	assert!(d.message().ends_with(r#", "," or "]""#));
+	assert_eq!(d.callers.len(), 3);
+	assert!(d.callers.iter().all(|c| c.file().ends_with("tests.rs")));
+	let rendered = d.to_string();
+	let lines: Vec<_> = rendered.lines().collect();
+	assert_eq!(lines[0], format!("2:6: {}", d.message()));
+	assert_eq!(lines[1], "2 | \t[1,2 3]");
+	assert_eq!(lines[2], "  | \t    ^");
+
+	// Running out of input points just past the end.
+	let mut pr = PackRat::new("[1,");
+	assert_eq!(pr.epar(list), None);
+	let d = pr.diagnostics().unwrap();
+	assert_eq!((d.line, d.col), (1, 4));
+
+	// Predicates that weren't given a description get a generic one.
+	let mut pr: PackRat<NoLex> = PackRat::new("[x]");
+	assert_eq!(pr.epat("["), Some("["));
+	assert_eq!(pr.epat(char::is_numeric), None);
+	assert_eq!(pr.diagnostics().unwrap().expected, ["a matching character"]);
+
+	// Failures before a token are placed with the lexer's help.
+	let mut pr: PackRat<ABCLex> = PackRat::new("this b");
+	assert_eq!(pr.epat("this "), Some("this "));
+	assert_eq!(pr.etok(ABCToken::A), None);
+	assert_eq!(pr.epat("x"), None);
+	let d = pr.diagnostics().unwrap();
+	assert_eq!((d.line, d.col), (1, 6));
+	assert_eq!(d.expected, vec!["A", "\"x\""]);
+}
@@ -1,5 +1,7 @@
 #![allow(unused)]
+use super::date::Date;
 use super::parser2::{Input, ParseResult};
+use super::toml::{Table, Value};
 use std::collections::HashMap;
 
 #[derive(Debug)]
@@ -14,22 +16,100 @@ pub struct DocumentHeader<'i> {
 	pub keywords: Vec<&'i str>,
 	pub authors: Vec<Author<'i>>,
 	pub draft: bool,
+	pub date: Option<Date>,
+	pub updated: Option<Date>,
+	pub canonical: Option<&'i str>,
+	pub slug: Option<&'i str>,
+	pub summary: Option<&'i str>,
+	pub tags: Vec<&'i str>,
+	pub weight: Option<i64>,
+	// How a section's index is sorted
+	pub sort_by: Option<SortBy>,
+	// Properties that don't have a field of their own
 	pub meta: HashMap<&'i str, &'i str>,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortBy {
+	Date,
+	Title,
+	Weight,
+}
+
 fn parse_header_title<'i>(input: &mut Input<'i>) -> ParseResult<&'i str> {
 	input.expect_pattern("= ")?;
 	input.expect_line()
 }
-fn parse_header_attribute<'i>(input: &mut Input<'i>) -> ParseResult<(&'i str, &'i str)> {
+// A :key: value line, along with where the value starts for reporting errors.
+fn parse_header_attribute<'i>(input: &mut Input<'i>) -> ParseResult<(&'i str, usize, &'i str)> {
 	input.expect_pattern(':')?;
 	let k = input.expect_pattern(|c| c != ':' && c != '\r' && c != '\n')?;
 	input.expect_pattern(':')?;
+	let _ = input.expect_pattern(|c| c == ' ' || c == '\t');
+	let position = input.position();
 	let v = input.expect_line().or_else(|_| {
 		input.expect_lineend()?;
 		Ok("")
 	})?;
-	Ok((k, v.trim()))
+	Ok((k, position, v.trim()))
+}
+fn parse_header_url(v: &str) -> Result<&str, &'static str> {
+	let rest = v
+		.strip_prefix("https://")
+		.or_else(|| v.strip_prefix("http://"))
+		.ok_or("An absolute http(s) url")?;
+	let host = rest
+		.split(|c| c == '/' || c == '?' || c == '#')
+		.next()
+		.unwrap_or("");
+	let valid_host = host.split('.').all(|label| {
+		!label.is_empty()
+			&& label
+				.chars()
+				.all(|c| c.is_alphanumeric() || c == '-' || c == ':')
+	});
+	if !valid_host {
+		Err("A url with a valid host")
+	} else if v
+		.chars()
+		.any(|c| c.is_whitespace() || c.is_control() || c == '"' || c == '<' || c == '>')
+	{
+		Err("A url without spaces or quotes")
+	} else {
+		Ok(v)
+	}
+}
+fn parse_header_slug(v: &str) -> Result<&str, &'static str> {
+	let valid = |c: char| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_';
+	if !v.is_empty() && v.chars().all(valid) {
+		Ok(v)
+	} else {
+		Err("A slug made of lowercase letters, digits, '-' and '_'")
+	}
+}
+fn parse_header_tags(v: &str) -> Result<Vec<&str>, &'static str> {
+	let tags: Vec<_> = v.split(',').map(str::trim).collect();
+	if tags.iter().any(|t| t.is_empty()) {
+		Err("Comma separated tags")
+	} else {
+		Ok(tags)
+	}
+}
+fn parse_header_sort_by(v: &str) -> Result<SortBy, &'static str> {
+	match v {
+		"date" => Ok(SortBy::Date),
+		"title" => Ok(SortBy::Title),
+		"weight" => Ok(SortBy::Weight),
+		_ => Err("One of date, title or weight"),
+	}
+}
+fn parse_header_draft(v: &str) -> Result<bool, &'static str> {
+	// A bare :draft: marks a draft.
+	match v {
+		"" | "true" => Ok(true),
+		"false" => Ok(false),
+		_ => Err("Nothing, true or false"),
+	}
 }
 fn parse_header_keywords<'i>(input: &mut Input<'i>) -> ParseResult<Vec<&'i str>> {
 	let keyword = |c: char| c.is_alphabetic() || (c.is_whitespace() && c != '\r' && c != '\n');
@@ -62,42 +142,82 @@ fn parse_header_authors<'i>(input: &mut Input<'i>) -> ParseResult<Vec<Author<'i>
 }
 pub fn parse_header<'i>(input: &mut Input<'i>) -> ParseResult<DocumentHeader<'i>> {
 	let title = input.expect(&mut parse_header_title)?;
-	let mut description = "";
-	let mut keywords = vec![];
-	let mut authors = vec![];
-	let mut draft = false;
-	let mut meta = HashMap::new();
+	let mut header = DocumentHeader {
+		title,
+		description: "",
+		keywords: vec![],
+		authors: vec![],
+		draft: false,
+		date: None,
+		updated: None,
+		canonical: None,
+		slug: None,
+		summary: None,
+		tags: vec![],
+		weight: None,
+		sort_by: None,
+		meta: HashMap::new(),
+	};
+
+	let mut updated_at = 0;
 
 	// Consume until the first blank line.
 	while let Err(_) = input.expect_lineend() {
-		if let Ok((k, v)) = parse_header_attribute(input) {
-			match k {
-				"description" => description = v,
-				"keywords" => keywords = parse_header_keywords(&mut Input::from(v)).unwrap(),
-				"authors" => authors = parse_header_authors(&mut Input::from(v)).unwrap(),
-				"draft" => draft = v.parse::<bool>().unwrap_or(true),
+		if let Ok((k, position, v)) = parse_header_attribute(input) {
+			// Values are parsed after the whole line is consumed, so errors have to point back at them.
+			let result = match k {
+				"description" => {
+					header.description = v;
+					Ok(())
+				}
+				"keywords" => parse_header_keywords(&mut Input::from(v))
+					.map(|k| header.keywords = k)
+					.map_err(|_| "Comma separated keywords"),
+				"authors" => parse_header_authors(&mut Input::from(v))
+					.map(|a| header.authors = a)
+					.map_err(|_| "Authors like: Name <email>; Other Name"),
+				"draft" => parse_header_draft(v).map(|d| header.draft = d),
+				"date" => Date::parse(v).map(|d| header.date = Some(d)),
+				"updated" => Date::parse(v).map(|d| {
+					header.updated = Some(d);
+					updated_at = position;
+				}),
+				"canonical" => parse_header_url(v).map(|u| header.canonical = Some(u)),
+				"slug" => parse_header_slug(v).map(|s| header.slug = Some(s)),
+				"summary" => {
+					header.summary = Some(v);
+					Ok(())
+				}
+				"tags" => parse_header_tags(v).map(|t| header.tags = t),
+				"weight" => v
+					.parse()
+					.map(|w| header.weight = Some(w))
+					.map_err(|_| "An integer weight"),
+				"sort_by" => parse_header_sort_by(v).map(|s| header.sort_by = Some(s)),
 				_ => {
-					meta.insert(k, v);
+					header.meta.insert(k, v);
+					Ok(())
 				}
-			}
+			};
+			result.map_err(|expected| input.error_at(position, expected))?;
 		} else {
 			match [
-				title.is_empty(),
-				description.is_empty(),
-				keywords.is_empty(),
-				authors.is_empty(),
+				header.title.is_empty(),
+				header.description.is_empty(),
+				header.keywords.is_empty(),
+				header.authors.is_empty(),
 			] {
-				[false, true, _, _] => description = input.expect_line()?,
+				[false, true, _, _] => header.description = input.expect_line()?,
 				[false, false, true, _] => {
 					if let Ok(k) = parse_header_keywords(input) {
-						keywords = k
+						header.keywords = k
 					} else {
 						return Err(input.error("Header keywords line."));
 					}
 				}
 				[false, false, false, true] => {
 					if let Ok(a) = parse_header_authors(input) {
-						authors = a
+						header.authors = a
 					} else {
 						return Err(input.error("Header authors line."));
 					}
@@ -106,21 +226,21 @@ pub fn parse_header<'i>(input: &mut Input<'i>) -> ParseResult<DocumentHeader<'i>
 			}
 		}
 	}
+	if let (Some(date), Some(updated)) = (header.date, header.updated) {
+		if updated < date {
+			return Err(input.error_at(updated_at, "An updated date that isn't before the date"));
+		}
+	}
 
-	Ok(DocumentHeader {
-		title,
-		description,
-		keywords,
-		authors,
-		draft,
-		meta,
-	})
+	Ok(header)
 }
 
 #[derive(Debug)]
 pub struct Document<'i> {
 	pub header: DocumentHeader<'i>,
 	pub blocks: Vec<Block<'i>>,
+	// Reference definitions: [label]: url "title", by their labels as reference_label makes them.
+	pub references: HashMap<String, (&'i str, Option<&'i str>)>,
 }
 
 #[derive(Debug)]
@@ -133,13 +253,31 @@ pub enum Block<'i> {
 	UList(Attributes<'i>, Vec<Block<'i>>),
 	OList(Attributes<'i>, Vec<Block<'i>>),
 	Raw(&'i str),
+	Shortcode(Shortcode<'i>),
 }
 
-#[derive(Debug)]
+/// A call like {{ name(key=value) }} inline, or {% name(key=value) %} on its own line with a body that ends at {% end %}.
+#[derive(Debug, PartialEq)]
+pub struct Shortcode<'i> {
+	pub name: &'i str,
+	pub args: Table,
+	pub body: Option<&'i str>,
+	// Where the shortcode was called, for error messages.
+	pub line: usize,
+	pub col: usize,
+	pub source_line: &'i str,
+}
+
+#[derive(Debug, PartialEq, Default, Clone)]
 pub struct Attributes<'i> {
-	classes: Vec<&'i str>,
-	id: Option<&'i str>,
-	attributes: HashMap<&'i str, &'i str>,
+	pub classes: Vec<&'i str>,
+	pub id: Option<&'i str>,
+	pub attributes: HashMap<&'i str, &'i str>,
+}
+impl<'i> Attributes<'i> {
+	pub fn is_empty(&self) -> bool {
+		self.classes.is_empty() && self.id.is_none() && self.attributes.is_empty()
+	}
 }
 
 fn ctori(c: char) -> bool {
@@ -158,20 +296,30 @@ fn parse_attributes<'i>(input: &mut Input<'i>) -> ParseResult<Attributes<'i>> {
 		input.expect_pattern('"')?;
 		Ok((k, v))
 	};
+	let mut kvs = |input: &mut Input<'i>| -> ParseResult<Vec<(&'i str, &'i str)>> {
+		input.expect_literal("{")?;
+		let mut kvs = vec![input.expect(&mut kv)?];
+		while let Ok(_) = input.expect_pattern(',') {
+			let _ = input.expect_pattern(char::is_whitespace);
+			kvs.push(input.expect(&mut kv)?);
+		}
+		input.expect_literal("}")?;
+		Ok(kvs)
+	};
+	// Each attribute is all or nothing so that trailing punctuation (like the period at the end of a sentence) isn't an error.
 	loop {
-		if let Ok(_) = input.expect_pattern('.') {
-			classes.push(input.expect_pattern(ctori)?);
-		} else if let Ok(_) = input.expect_pattern('#') {
-			id = Some(input.expect_pattern(ctori)?);
-		} else if let Ok(_) = input.expect_pattern('{') {
-			let (k, v) = input.expect(&mut kv)?;
-			attributes.insert(k, v);
-			while let Ok(_) = input.expect_pattern(',') {
-				let _ = input.expect_pattern(char::is_whitespace);
-				let (k, v) = input.expect(&mut kv)?;
-				attributes.insert(k, v);
-			}
-			input.expect_pattern('}');
+		if let Ok(class) = input.expect(&mut |input: &mut Input<'i>| {
+			input.expect_literal(".")?;
+			input.expect_pattern(ctori)
+		}) {
+			classes.push(class);
+		} else if let Ok(i) = input.expect(&mut |input: &mut Input<'i>| {
+			input.expect_literal("#")?;
+			input.expect_pattern(ctori)
+		}) {
+			id = Some(i);
+		} else if let Ok(kvs) = input.expect(&mut kvs) {
+			attributes.extend(kvs);
 		} else {
 			break Ok(Attributes {
 				classes,
@@ -182,25 +330,50 @@ fn parse_attributes<'i>(input: &mut Input<'i>) -> ParseResult<Attributes<'i>> {
 	}
 }
 
+fn parse_blank_line<'i>(input: &mut Input<'i>) -> ParseResult<()> {
+	let _ = input.expect_pattern(|c| c == ' ' || c == '\t');
+	input
+		.expect_literal("\n")
+		.or_else(|_| input.expect_literal("\r\n"))?;
+	Ok(())
+}
+
 fn parse_block<'i>(current_indent: usize, input: &mut Input<'i>) -> ParseResult<Block<'i>> {
+	input.expect_star(&mut parse_blank_line);
+
 	// Try to parse the current indent
-	input.expect_pattern(&"\t".repeat(current_indent))?;
+	for _ in 0..current_indent {
+		input.expect_literal("\t")?;
+	}
+
+	// Reference definitions aren't blocks, parse_document collects them.
+	if input.lookahead(&mut parse_reference) {
+		return Err(input.error("<Block>"));
+	}
 
 	// TODO: Lists and Paragraph
 
+	if let Ok(shortcode) = input.expect(&mut parse_block_shortcode) {
+		return Ok(Block::Shortcode(shortcode));
+	}
+
 	// Try to parse a block
 	if let Ok(_) = input.expect_pattern("---") {
 		let attributes = parse_attributes(input)?;
 		input.expect_lineend()?;
 		Ok(Block::HorizontalRule(attributes))
-	} else if let Ok(tag_name) = input.expect_pattern(&mut ctori) {
+	} else if let Ok((tag_name, attributes)) = input.expect(&mut |input: &mut Input<'i>| {
+		// A tag is only a tag if it's alone on its line, otherwise it's the start of a paragraph.
+		let tag_name = input.expect_pattern(ctori)?;
 		let attributes = parse_attributes(input)?;
 		input.expect_lineend()?;
+		Ok((tag_name, attributes))
+	}) {
 		let blocks =
 			input.expect_star(&mut |input: &mut Input<'i>| parse_block(current_indent + 1, input));
 		Ok(Block::HtmlTag(tag_name, attributes, blocks))
 	} else if let Ok(_) = input.expect_pattern("```") {
-		let language = input.expect_pattern(&mut ctori)?;
+		let language = input.expect_pattern(&mut ctori).unwrap_or("");
 		let attributes = parse_attributes(input)?;
 		input.expect_lineend()?;
 		let code = input.expect_antipattern("```")?;
@@ -214,6 +387,7 @@ fn parse_block<'i>(current_indent: usize, input: &mut Input<'i>) -> ParseResult<
 			return Err(input.error("<Maximum heading is 6>"));
 		}
 		let title = input.expect_star(&mut parse_inline);
+		input.expect_lineend()?;
 		Ok(Block::Heading(hc, attributes, title))
 	} else if let Ok(_) = input.expect_pattern('#') {
 		let attributes = parse_attributes(input)?;
@@ -229,19 +403,22 @@ fn parse_block<'i>(current_indent: usize, input: &mut Input<'i>) -> ParseResult<
 	} else {
 		// Paragraph
 		let attributes = parse_attributes(input)?;
-		let _ = input.expect_pattern(char::is_whitespace);
-		Ok(Block::Paragraph(
-			attributes,
-			input.expect_star(&mut parse_inline),
-		))
+		let _ = input.expect_pattern(|c| c == ' ' || c == '\t');
+		let inlines = input.expect_star(&mut parse_inline);
+		if inlines.is_empty() {
+			return Err(input.error("<Paragraph text>"));
+		}
+		input.expect_lineend()?;
+		Ok(Block::Paragraph(attributes, inlines))
 	}
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub enum Inline<'i> {
 	Text(&'i str),
 	Span(Vec<Inline<'i>>, Attributes<'i>),
 	Image(Vec<Inline<'i>>, Attributes<'i>, &'i str),
+	Link(Vec<Inline<'i>>, Attributes<'i>, LinkTarget<'i>),
 	Strong(Vec<Inline<'i>>),
 	Emphasis(Vec<Inline<'i>>),
 	InlineCode(Vec<Inline<'i>>),
@@ -254,15 +431,920 @@ pub enum Inline<'i> {
 	Subscript(Vec<Inline<'i>>),
 	RawHTML(&'i str),
 	Symbol(char),
+	Shortcode(Shortcode<'i>),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum LinkTarget<'i> {
+	// A url and an optional title.
+	Url(&'i str, Option<&'i str>),
+	// The label of one of the document's reference definitions, and the whole link as it was written for when there isn't one.
+	Reference(&'i str, &'i str),
+}
+
+// Characters that might start an inline element.  Text stops at these so that the other inline parsers get a chance to match.
+const INLINE_CONTROLS: &str = "\\*_`+~=^@![]<{";
+
+fn is_bare_url(s: &str) -> bool {
+	s.starts_with("https://") || s.starts_with("http://")
+}
+/// Take the trailing punctuation and unbalanced closing parentheses off of a bare url, since they belong to the sentence around it.
+pub fn trim_bare_url(mut url: &str) -> &str {
+	loop {
+		let trimmed = url.trim_end_matches(|c| ".,:;!?\"'".contains(c));
+		url = if trimmed.ends_with(')')
+			&& trimmed.matches(')').count() > trimmed.matches('(').count()
+		{
+			&trimmed[..trimmed.len() - 1]
+		} else {
+			trimmed
+		};
+		if url == trimmed {
+			return url;
+		}
+	}
+}
+fn is_hard_break(s: &str) -> bool {
+	let rest = s.trim_start_matches(' ');
+	s.len() - rest.len() >= 2 && (rest.starts_with('\n') || rest.starts_with("\r\n"))
+}
+fn parse_text<'i>(input: &mut Input<'i>) -> ParseResult<Inline<'i>> {
+	// The first character is always text: if it was a control character then nothing else matched it.
+	let rest = input.peek();
+	let mut len = 0;
+	for (i, c) in rest.char_indices() {
+		if c == '\r' || c == '\n' {
+			break;
+		}
+		let url_start = || {
+			rest[..i].ends_with(|c: char| c.is_whitespace() || c == '(') && is_bare_url(&rest[i..])
+		};
+		if i > 0
+			&& (INLINE_CONTROLS.contains(c)
+				|| (c == ' ' && is_hard_break(&rest[i..]))
+				|| url_start())
+		{
+			break;
+		}
+		len = i + c.len_utf8();
+	}
+	Ok(Inline::Text(input.expect_len(len)?))
+}
+fn parse_line_break<'i>(input: &mut Input<'i>) -> ParseResult<Inline<'i>> {
+	// Either a backslash or two spaces at the end of a line that continues the paragraph.
+	if let Err(_) = input.expect_literal("\\") {
+		let spaces = input.expect_pattern(' ')?;
+		if spaces.len() < 2 {
+			return Err(input.error("<Two spaces before a line break>"));
+		}
+	}
+	if input.peek().is_empty() {
+		return Err(input.error("<Newline>"));
+	}
+	input.expect_lineend()?;
+	if input
+		.peek()
+		.lines()
+		.next()
+		.map_or(true, |l| l.trim().is_empty())
+	{
+		return Err(input.error("<Paragraph continuation>"));
+	}
+	Ok(Inline::LineBreak)
+}
+fn parse_symbol<'i>(input: &mut Input<'i>) -> ParseResult<Inline<'i>> {
+	input.expect_literal("\\")?;
+	let c = input.expect_pattern(|c: char| c.is_ascii_punctuation())?;
+	// expect_pattern is greedy, so only keep the first escaped character.
+	let mut chars = c.chars();
+	let symbol = chars.next().unwrap();
+	if chars.next().is_some() {
+		return Err(input.error("<Single escaped character>"));
+	}
+	Ok(Inline::Symbol(symbol))
+}
+fn parse_inline_code<'i>(input: &mut Input<'i>) -> ParseResult<Inline<'i>> {
+	// Code is closed by a run of backticks that is the same length as the one that opened it.
+	let ticks = input.expect_pattern('`')?.len();
+	let line = input.peek().lines().next().unwrap_or("");
+	let mut end = None;
+	let mut i = 0;
+	while let Some(start) = line[i..].find('`').map(|s| s + i) {
+		let run = line[start..].len() - line[start..].trim_start_matches('`').len();
+		if run == ticks {
+			end = Some(start);
+			break;
+		}
+		i = start + run;
+	}
+	let end = end.ok_or_else(|| input.error("<Closing backticks>"))?;
+	let mut code = input.expect_len(end)?;
+	input.expect_len(ticks)?;
+	if code.len() > 2 && code.starts_with(' ') && code.ends_with(' ') && !code.trim().is_empty() {
+		code = &code[1..code.len() - 1];
+	}
+	Ok(Inline::InlineCode(vec![Inline::Text(code)]))
+}
+fn parse_raw_html<'i>(input: &mut Input<'i>) -> ParseResult<Inline<'i>> {
+	let rest = input.peek();
+	let len = if rest.starts_with("<!--") {
+		rest.find("-->").map(|i| i + 3)
+	} else {
+		let tag = rest.strip_prefix('<').unwrap_or("");
+		let tag = tag.strip_prefix('/').unwrap_or(tag);
+		if tag.starts_with(|c: char| c.is_ascii_alphabetic()) {
+			tag.find(|c| c == '>' || c == '<' || c == '\n')
+				.filter(|&i| tag[i..].starts_with('>'))
+				.map(|i| rest.len() - tag.len() + i + 1)
+		} else {
+			None
+		}
+	};
+	let len = len.ok_or_else(|| input.error("<HTML tag>"))?;
+	Ok(Inline::RawHTML(input.expect_len(len)?))
+}
+fn parse_inlines_until<'i>(
+	input: &mut Input<'i>,
+	close: &'static str,
+) -> ParseResult<Vec<Inline<'i>>> {
+	let mut inlines = vec![];
+	while let Err(_) = input.expect_literal(close) {
+		inlines.push(input.expect(&mut parse_inline)?);
+	}
+	Ok(inlines)
+}
+fn parse_image<'i>(input: &mut Input<'i>) -> ParseResult<Inline<'i>> {
+	input.expect_literal("![")?;
+	let alt = parse_inlines_until(input, "]")?;
+	input.expect_literal("(")?;
+	let src = input.expect_pattern(|c: char| c != ')' && !c.is_whitespace())?;
+	input.expect_literal(")")?;
+	let attributes = parse_attributes(input)?;
+	Ok(Inline::Image(alt, attributes, src))
+}
+fn parse_link_title<'i>(input: &mut Input<'i>) -> ParseResult<&'i str> {
+	input.expect_pattern(' ')?;
+	let quote = input
+		.expect_literal("\"")
+		.or_else(|_| input.expect_literal("'"))?;
+	let title = input.expect_pattern(|c: char| !quote.starts_with(c) && c != '\n')?;
+	input.expect_pattern(quote)?;
+	Ok(title)
+}
+// Labels match whatever their case and spacing: [The  Spec] is defined by [the spec]: url
+pub fn reference_label(label: &str) -> String {
+	label
+		.split_whitespace()
+		.collect::<Vec<_>>()
+		.join(" ")
+		.to_lowercase()
+}
+fn parse_reference<'i>(input: &mut Input<'i>) -> ParseResult<(String, (&'i str, Option<&'i str>))> {
+	input.expect_literal("[")?;
+	let label = input.expect_pattern(|c| c != ']' && c != '\r' && c != '\n')?;
+	input.expect_literal("]:")?;
+	let _ = input.expect_pattern(' ');
+	let url = input.expect_pattern(|c: char| !c.is_whitespace())?;
+	let title = input.expect(&mut parse_link_title).ok();
+	let _ = input.expect_pattern(' ');
+	input.expect_lineend()?;
+	Ok((reference_label(label), (url, title)))
+}
+fn parse_link<'i>(input: &mut Input<'i>) -> ParseResult<Inline<'i>> {
+	// [text](url "title") or [text][label] or [label][]
+	let start = input.peek();
+	input.expect_literal("[")?;
+	let text = parse_inlines_until(input, "]")?;
+	let label = &start[1..start.len() - input.peek().len() - 1];
+	let mut target = if let Ok(_) = input.expect_literal("(") {
+		let _ = input.expect_pattern(' ');
+		let url = input
+			.expect_pattern(|c: char| c != ')' && !c.is_whitespace())
+			.unwrap_or("");
+		let title = input.expect(&mut parse_link_title).ok();
+		let _ = input.expect_pattern(' ');
+		input.expect_literal(")")?;
+		LinkTarget::Url(url, title)
+	} else {
+		input.expect_literal("[")?;
+		let reference = input
+			.expect_pattern(|c| c != ']' && c != '\r' && c != '\n')
+			.unwrap_or(label);
+		input.expect_literal("]")?;
+		LinkTarget::Reference(reference, "")
+	};
+	let attributes = parse_attributes(input)?;
+	if let LinkTarget::Reference(_, source) = &mut target {
+		*source = &start[..start.len() - input.peek().len()];
+	}
+	Ok(Inline::Link(text, attributes, target))
+}
+fn parse_autolink<'i>(input: &mut Input<'i>) -> ParseResult<Inline<'i>> {
+	// <scheme:...> or a bare http(s) url at the start of a word
+	let url = if let Ok(_) = input.expect_literal("<") {
+		let url = input.expect_pattern(|c: char| c != '<' && c != '>' && !c.is_whitespace())?;
+		if !url.contains(':') || !url.starts_with(|c: char| c.is_ascii_alphabetic()) {
+			return Err(input.error("<Url with a scheme>"));
+		}
+		input.expect_literal(">")?;
+		url
+	} else {
+		if !input
+			.last_char()
+			.map_or(true, |c| c.is_whitespace() || c == '(')
+			|| !is_bare_url(input.peek())
+		{
+			return Err(input.error("<Url>"));
+		}
+		let rest = input.peek();
+		let url = trim_bare_url(
+			&rest[..rest
+				.find(|c: char| c.is_whitespace() || c == '<')
+				.unwrap_or(rest.len())],
+		);
+		input.expect_len(url.len())?
+	};
+	Ok(Inline::Link(
+		vec![Inline::Text(url)],
+		Attributes::default(),
+		LinkTarget::Url(url, None),
+	))
+}
+fn parse_span<'i>(input: &mut Input<'i>) -> ParseResult<Inline<'i>> {
+	input.expect_literal("[")?;
+	let inlines = parse_inlines_until(input, "]")?;
+	let attributes = parse_attributes(input)?;
+	if inlines.is_empty() || attributes.is_empty() {
+		return Err(input.error("<Span attributes>"));
+	}
+	Ok(Inline::Span(inlines, attributes))
+}
+fn is_ident(c: char) -> bool {
+	c.is_ascii_alphanumeric() || c == '_'
+}
+fn skip_spaces(input: &mut Input) {
+	let _ = input.expect_pattern(|c| c == ' ' || c == '\t');
+}
+// Shortcode arguments are typed like they are in Zola: strings in any kind of quotes, numbers, booleans and arrays of those.
+fn parse_shortcode_value<'i>(input: &mut Input<'i>) -> ParseResult<Value> {
+	for quote in ["\"", "'", "`"] {
+		if let Ok(_) = input.expect_literal(quote) {
+			let s = input
+				.expect_pattern(|c: char| !quote.starts_with(c) && c != '\n')
+				.unwrap_or("");
+			input.expect_literal(quote)?;
+			return Ok(Value::String(s.to_string()));
+		}
+	}
+	if let Ok(_) = input.expect_literal("true") {
+		return Ok(Value::Boolean(true));
+	}
+	if let Ok(_) = input.expect_literal("false") {
+		return Ok(Value::Boolean(false));
+	}
+	if let Ok(_) = input.expect_literal("[") {
+		let mut values = vec![];
+		loop {
+			skip_spaces(input);
+			if let Ok(_) = input.expect_literal("]") {
+				break Ok(Value::Array(values));
+			}
+			if !values.is_empty() {
+				input.expect_literal(",")?;
+				skip_spaces(input);
+				// Trailing commas are fine.
+				if let Ok(_) = input.expect_literal("]") {
+					break Ok(Value::Array(values));
+				}
+			}
+			values.push(parse_shortcode_value(input)?);
+		}
+	} else {
+		let start = input.position();
+		let n = input.expect_pattern(|c: char| c.is_ascii_digit() || "+-.eE_".contains(c))?;
+		let n = n.replace('_', "");
+		if let Ok(i) = n.parse() {
+			Ok(Value::Integer(i))
+		} else if let Ok(f) = n.parse() {
+			Ok(Value::Float(f))
+		} else {
+			Err(input.error_at(start, "<Shortcode argument>"))
+		}
+	}
+}
+// name(key=value, ...)
+fn parse_shortcode_call<'i>(input: &mut Input<'i>) -> ParseResult<(&'i str, Table)> {
+	let name = input.expect_pattern(is_ident)?;
+	input.expect_literal("(")?;
+	// Past the '(' this can only be a shortcode, so a mistake in it is an error rather than text.
+	let args = parse_shortcode_args(input);
+	input.cut(args).map(|args| (name, args))
+}
+fn parse_shortcode_args<'i>(input: &mut Input<'i>) -> ParseResult<Table> {
+	let mut args = Table::new();
+	loop {
+		skip_spaces(input);
+		if let Ok(_) = input.expect_literal(")") {
+			break Ok(args);
+		}
+		if !args.is_empty() {
+			input.expect_literal(",")?;
+			skip_spaces(input);
+		}
+		let start = input.position();
+		let key = input.expect_pattern(is_ident)?;
+		skip_spaces(input);
+		input.expect_literal("=")?;
+		skip_spaces(input);
+		let value = parse_shortcode_value(input)?;
+		if args.insert(key.to_string(), value).is_some() {
+			return Err(input.error_at(start, "<Each shortcode argument once>"));
+		}
+	}
+}
+fn parse_inline_shortcode<'i>(input: &mut Input<'i>) -> ParseResult<Inline<'i>> {
+	let (line, col, source_line) = input.ln_cn_line(input.position());
+	input.expect_literal("{{")?;
+	skip_spaces(input);
+	let (name, args) = parse_shortcode_call(input)?;
+	skip_spaces(input);
+	let close = input.expect_literal("}}");
+	input.cut(close)?;
+	Ok(Inline::Shortcode(Shortcode {
+		name,
+		args,
+		body: None,
+		line,
+		col,
+		source_line,
+	}))
+}
+fn parse_block_shortcode<'i>(input: &mut Input<'i>) -> ParseResult<Shortcode<'i>> {
+	let (line, col, source_line) = input.ln_cn_line(input.position());
+	input.expect_literal("{%")?;
+	skip_spaces(input);
+	let (name, args) = parse_shortcode_call(input)?;
+	skip_spaces(input);
+	let close = input.expect_literal("%}");
+	input.cut(close)?;
+	skip_spaces(input);
+	input.expect_lineend()?;
+	// The body is every line up to the one that's just {% end %}
+	let rest = input.peek();
+	let mut len = 0;
+	let end = loop {
+		let line = rest[len..]
+			.split_inclusive('\n')
+			.next()
+			.ok_or_else(|| input.error("{% end %}"))?;
+		if line.trim() == "{% end %}" {
+			break line.len();
+		}
+		len += line.len();
+	};
+	let body = &rest[..len];
+	input.expect_len(len + end)?;
+	let body = body.strip_suffix('\n').unwrap_or(body);
+	Ok(Shortcode {
+		name,
+		args,
+		body: Some(body.strip_suffix('\r').unwrap_or(body)),
+		line,
+		col,
+		source_line,
+	})
+}
+
+fn parse_delimited<'i>(
+	input: &mut Input<'i>,
+	delimiter: &'static str,
+	intraword: bool,
+) -> ParseResult<Vec<Inline<'i>>> {
+	// Openers can't be followed by whitespace and closers can't be preceded by it.  Delimiters that aren't intraword (_) also can't touch a word on the outside.
+	let is_word = |c: char| c.is_alphanumeric();
+	if !intraword && input.last_char().map_or(false, is_word) {
+		return Err(input.error("<Start of a word>"));
+	}
+	input.expect_literal(delimiter)?;
+	if input.peek().starts_with(char::is_whitespace) {
+		return Err(input.error("<Non-whitespace after the opening delimiter>"));
+	}
+	let mut close = |input: &mut Input<'i>| -> ParseResult<()> {
+		if input.last_char().map_or(true, char::is_whitespace) {
+			return Err(input.error("<Non-whitespace before the closing delimiter>"));
+		}
+		input.expect_literal(delimiter)?;
+		if !intraword && input.peek().starts_with(is_word) {
+			return Err(input.error("<End of a word>"));
+		}
+		Ok(())
+	};
+	let mut inlines = vec![input.expect(&mut parse_inline)?];
+	while let Err(_) = input.expect(&mut close) {
+		inlines.push(input.expect(&mut parse_inline)?);
+	}
+	Ok(inlines)
 }
 
 fn parse_inline<'i>(input: &mut Input<'i>) -> ParseResult<Inline<'i>> {
-	unimplemented!()
+	// Longer delimiters need to come before their prefixes: ** before *, ~~ before ~.
+	let delimited: [(&'static str, bool, fn(Vec<Inline<'i>>) -> Inline<'i>); 9] = [
+		("**", true, Inline::Strong),
+		("*", true, Inline::Emphasis),
+		("_", false, Inline::Emphasis),
+		("++", true, Inline::Inserted),
+		("~~", true, Inline::Deleted),
+		("==", true, Inline::Marked),
+		("@@", true, Inline::Cite),
+		("^", true, Inline::Superscript),
+		("~", true, Inline::Subscript),
+	];
+	if let Ok(inline) = input
+		.expect(&mut parse_line_break)
+		.or_else(|_| input.expect(&mut parse_symbol))
+		.or_else(|_| input.expect(&mut parse_inline_code))
+		.or_else(|_| input.expect(&mut parse_inline_shortcode))
+		.or_else(|_| input.expect(&mut parse_autolink))
+		.or_else(|_| input.expect(&mut parse_raw_html))
+		.or_else(|_| input.expect(&mut parse_image))
+		.or_else(|_| input.expect(&mut parse_link))
+		.or_else(|_| input.expect(&mut parse_span))
+	{
+		return Ok(inline);
+	}
+	// Delimiters that aren't closed would be tried again by every enclosing delimiter that isn't closed either, so remember where they failed.
+	for (delimiter, intraword, inline) in delimited {
+		if let Ok(inlines) = input.expect_memo(delimiter, &mut |input: &mut Input<'i>| {
+			parse_delimited(input, delimiter, intraword)
+		}) {
+			return Ok(inline(inlines));
+		}
+	}
+	input.expect(&mut parse_text)
 }
 
 pub fn parse_document<'i>(input: &mut Input<'i>) -> ParseResult<Document<'i>> {
 	let header = parse_header(input)?;
-	let blocks = input.expect_star(&mut |input: &mut Input<'i>| parse_block(0, input));
+	let mut blocks = vec![];
+	let mut references = HashMap::new();
+	loop {
+		blocks.extend(input.expect_star(&mut |input: &mut Input<'i>| parse_block(0, input)));
+		input.expect_star(&mut parse_blank_line);
+		let definitions = input.expect_star(&mut parse_reference);
+		if definitions.is_empty() {
+			break;
+		}
+		references.extend(definitions);
+	}
+	if let Some(e) = input.take_cut() {
+		return Err(e);
+	}
+	let _ = input.expect_pattern(char::is_whitespace);
 	input.expect_eoi()?;
-	Ok(Document { header, blocks })
+	Ok(Document {
+		header,
+		blocks,
+		references,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const POSTS: [&str; 11] = [
+		include_str!("../content/blog/Built-In and Custom Traits in JavaScript/index.md"),
+		include_str!("../content/blog/calendar.md"),
+		include_str!("../content/blog/distributed-web-applications.md"),
+		include_str!("../content/blog/finite-state-machines-and-js/index.md"),
+		include_str!("../content/blog/introduction-to-programming-1-background.md"),
+		include_str!("../content/blog/introduction-to-programming-2-getting-started.md"),
+		include_str!(
+			"../content/blog/introduction-to-programming-3-boolean-s-logic-and-conditionals.md"
+		),
+		include_str!("../content/blog/reactivity.md"),
+		include_str!("../content/blog/template-elements/index.md"),
+		include_str!("../content/blog/why-i-hated-amp-and-why-my-mind-has-changed.md"),
+		include_str!("../content/about.md"),
+	];
+
+	fn inlines(s: &str) -> Vec<Inline<'_>> {
+		let mut input = Input::from(s);
+		let inlines = input.expect_star(&mut parse_inline);
+		input.expect_eoi().unwrap();
+		inlines
+	}
+
+	#[test]
+	fn test_emphasis() {
+		use Inline::*;
+		assert_eq!(
+			inlines("**EDIT:** I was wrong."),
+			vec![Strong(vec![Text("EDIT:")]), Text(" I was wrong.")]
+		);
+		assert_eq!(
+			inlines("**a _b_ *c***"),
+			vec![Strong(vec![
+				Text("a "),
+				Emphasis(vec![Text("b")]),
+				Text(" "),
+				Emphasis(vec![Text("c")])
+			])]
+		);
+		assert_eq!(
+			inlines("++a++ ~~b~~ ==c== @@d@@ x^2^ H~2~O"),
+			vec![
+				Inserted(vec![Text("a")]),
+				Text(" "),
+				Deleted(vec![Text("b")]),
+				Text(" "),
+				Marked(vec![Text("c")]),
+				Text(" "),
+				Cite(vec![Text("d")]),
+				Text(" x"),
+				Superscript(vec![Text("2")]),
+				Text(" H"),
+				Subscript(vec![Text("2")]),
+				Text("O"),
+			]
+		);
+		// Underscores inside words and unclosed delimiters are just text.
+		assert_eq!(
+			inlines("snake_case_name"),
+			vec![Text("snake"), Text("_case"), Text("_name")]
+		);
+		assert_eq!(
+			inlines("the equality test (==) will"),
+			vec![Text("the equality test ("), Text("="), Text("=) will")]
+		);
+		assert_eq!(inlines("a * b"), vec![Text("a "), Text("* b")]);
+	}
+	#[test]
+	fn test_unclosed_delimiters() {
+		// Each opener without a closer used to double the work for every one before it.
+		let source = ["*a ", "**a ", "_a ", "~a ", "~~a ", "==a "]
+			.repeat(9)
+			.concat();
+		let (send, receive) = std::sync::mpsc::channel();
+		std::thread::spawn(move || send.send(inlines(&source).len()).unwrap());
+		let parsed = receive.recv_timeout(std::time::Duration::from_secs(2));
+		assert!(parsed.is_ok(), "parsing unclosed delimiters took too long");
+	}
+
+	#[test]
+	fn test_code_and_escapes() {
+		use Inline::*;
+		assert_eq!(
+			inlines("use `then` or `` a`b ``"),
+			vec![
+				Text("use "),
+				InlineCode(vec![Text("then")]),
+				Text(" or "),
+				InlineCode(vec![Text("a`b")])
+			]
+		);
+		// Nothing is parsed inside of inline code.
+		assert_eq!(
+			inlines("`<figure>` and `**`"),
+			vec![
+				InlineCode(vec![Text("<figure>")]),
+				Text(" and "),
+				InlineCode(vec![Text("**")])
+			]
+		);
+		assert_eq!(
+			inlines(r"for \~12 hours \*not emphasis\*"),
+			vec![
+				Text("for "),
+				Symbol('~'),
+				Text("12 hours "),
+				Symbol('*'),
+				Text("not emphasis"),
+				Symbol('*')
+			]
+		);
+	}
+
+	#[test]
+	fn test_html_and_line_breaks() {
+		use Inline::*;
+		assert_eq!(
+			inlines("<del>old</del><ins>new</ins> <4094 bytes"),
+			vec![
+				RawHTML("<del>"),
+				Text("old"),
+				RawHTML("</del>"),
+				RawHTML("<ins>"),
+				Text("new"),
+				RawHTML("</ins>"),
+				Text(" "),
+				Text("<4094 bytes")
+			]
+		);
+		assert_eq!(
+			inlines("together:  \nNosebook.com"),
+			vec![Text("together:"), LineBreak, Text("Nosebook.com")]
+		);
+		assert_eq!(inlines("a\\\nb"), vec![Text("a"), LineBreak, Text("b")]);
+		// A trailing break at the end of a paragraph isn't a break.
+		let mut input = Input::from("a  \n\nb");
+		assert_eq!(
+			input.expect_star(&mut parse_inline),
+			vec![Text("a"), Text("  ")]
+		);
+	}
+
+	#[test]
+	fn test_images_and_spans() {
+		use Inline::*;
+		assert_eq!(
+			inlines("![Finite State Machine diagram of a Promise](promise-fsm-diagram.webp)"),
+			vec![Image(
+				vec![Text("Finite State Machine diagram of a Promise")],
+				Attributes::default(),
+				"promise-fsm-diagram.webp"
+			)]
+		);
+		assert_eq!(
+			inlines("![**Bold** alt](a.png).wide#fig{loading: \"lazy\"}."),
+			vec![
+				Image(
+					vec![Strong(vec![Text("Bold")]), Text(" alt")],
+					Attributes {
+						classes: vec!["wide"],
+						id: Some("fig"),
+						attributes: [("loading", "lazy")].into_iter().collect()
+					},
+					"a.png"
+				),
+				Text(".")
+			]
+		);
+		assert_eq!(
+			inlines("[really *important*].warning."),
+			vec![
+				Span(
+					vec![Text("really "), Emphasis(vec![Text("important")])],
+					Attributes {
+						classes: vec!["warning"],
+						..Attributes::default()
+					}
+				),
+				Text(".")
+			]
+		);
+		// Brackets without attributes aren't spans.
+		assert_eq!(inlines("[1]"), vec![Text("[1"), Text("]")]);
+	}
+
+	#[test]
+	fn test_links() {
+		use Inline::*;
+		assert_eq!(
+			inlines("[V8 tech blog](https://v8.dev/blog/trash-talk)."),
+			vec![
+				Link(
+					vec![Text("V8 tech blog")],
+					Attributes::default(),
+					LinkTarget::Url("https://v8.dev/blog/trash-talk", None)
+				),
+				Text(".")
+			]
+		);
+		assert_eq!(
+			inlines(r#"[a](b.md "The title")#top [c][d] [e][]"#),
+			vec![
+				Link(
+					vec![Text("a")],
+					Attributes {
+						id: Some("top"),
+						..Attributes::default()
+					},
+					LinkTarget::Url("b.md", Some("The title"))
+				),
+				Text(" "),
+				Link(
+					vec![Text("c")],
+					Attributes::default(),
+					LinkTarget::Reference("d", "[c][d]")
+				),
+				Text(" "),
+				Link(
+					vec![Text("e")],
+					Attributes::default(),
+					LinkTarget::Reference("e", "[e][]")
+				),
+			]
+		);
+		// Bare urls leave trailing punctuation and unbalanced parentheses in the sentence.
+		let url = |u| {
+			Link(
+				vec![Text(u)],
+				Attributes::default(),
+				LinkTarget::Url(u, None),
+			)
+		};
+		assert_eq!(
+			inlines("(see https://en.wikipedia.org/wiki/Fold_(higher-order_function)), or <mailto:me@x.com>"),
+			vec![
+				Text("(see "),
+				url("https://en.wikipedia.org/wiki/Fold_(higher-order_function)"),
+				Text("), or "),
+				url("mailto:me@x.com"),
+			]
+		);
+		assert_eq!(
+			inlines("https://en.wikipedia.org/wiki/Rust_(programming_language)."),
+			vec![
+				url("https://en.wikipedia.org/wiki/Rust_(programming_language)"),
+				Text("."),
+			]
+		);
+		assert_eq!(inlines("xhttps://a.b"), vec![Text("xhttps://a.b")]);
+
+		let document = parse_document(&mut Input::from(
+			"= Title\n\n[a][one]\n\n[One]: https://one.com \"One\"\n[ the\tsecond  one]: two.md\n",
+		))
+		.unwrap();
+		assert_eq!(document.blocks.len(), 1);
+		assert_eq!(document.references.len(), 2);
+		assert_eq!(document.references["one"], ("https://one.com", Some("One")));
+		assert_eq!(document.references["the second one"], ("two.md", None));
+	}
+
+	#[test]
+	fn test_post_lines() {
+		// Every line of prose in the real posts should parse as inlines all the way to the end of the line.
+		for post in POSTS {
+			let mut in_code = false;
+			for line in post.lines() {
+				if line.starts_with("```") {
+					in_code = !in_code;
+				}
+				if in_code || line.starts_with(':') {
+					continue;
+				}
+				let mut input = Input::from(line);
+				input.expect_star(&mut parse_inline);
+				if let Err(e) = input.expect_eoi() {
+					panic!("{}", e);
+				}
+			}
+		}
+		let post = POSTS[3];
+		let line = post
+			.lines()
+			.find(|l| l.starts_with("1. Every async"))
+			.unwrap();
+		assert!(inlines(line).contains(&Inline::RawHTML("<del>")));
+		let line = post.lines().find(|l| l.starts_with("![FSM")).unwrap();
+		assert!(matches!(
+			inlines(line)[..],
+			[Inline::Image(_, _, "simple-async-FSMd.webp")]
+		));
+	}
+
+	#[test]
+	fn test_header() {
+		let header = parse_header(&mut Input::from(
+			"= Title
+:date: 2020-03-27T20:18:42Z
+:updated: 2020-08-03
+:canonical: https://medium.com/@Evan_Brass/finite-state-machines-and-js-c9b55fc3f8f9
+:slug: fsm-and-js
+:summary: Short and sweet.
+:tags: JavaScript, State Machines
+:draft: false
+:weight: -2
+:sort_by: title
+:other: thing
+
+",
+		))
+		.unwrap();
+		assert_eq!(header.date, Date::parse("2020-03-27T20:18:42Z").ok());
+		assert_eq!(header.updated, Date::parse("2020-08-03").ok());
+		assert_eq!(
+			header.canonical,
+			Some("https://medium.com/@Evan_Brass/finite-state-machines-and-js-c9b55fc3f8f9")
+		);
+		assert_eq!(header.slug, Some("fsm-and-js"));
+		assert_eq!(header.summary, Some("Short and sweet."));
+		assert_eq!(header.tags, vec!["JavaScript", "State Machines"]);
+		assert_eq!(header.draft, false);
+		assert_eq!(header.weight, Some(-2));
+		assert_eq!(header.sort_by, Some(SortBy::Title));
+		assert_eq!(header.meta.get("other"), Some(&"thing"));
+
+		let header = parse_header(&mut Input::from("= Title\n:draft:\n")).unwrap();
+		assert!(header.draft);
+		assert_eq!(header.date, None);
+
+		for (header, line) in [
+			("= T\n:date: 2020-02-30\n", 2),
+			("= T\n:date: yesterday\n", 2),
+			(
+				"= T\n:keywords: JavaScript\n:updated: 2020-08-03T10:30\n",
+				3,
+			),
+			("= T\n:canonical: medium.com/post\n", 2),
+			("= T\n:canonical: https://exa mple.com/\n", 2),
+			("= T\n:slug: Not A Slug\n", 2),
+			("= T\n:tags: a,,b\n", 2),
+			("= T\n:keywords: C++\n", 2),
+			("= T\n:authors: <nobody>\n", 2),
+			("= T\n:draft: maybe\n", 2),
+			("= T\n:weight: heavy\n", 2),
+			("= T\n:sort_by: size\n", 2),
+			("= T\n:updated: 2019-01-01\n:date: 2020-01-01\n", 2),
+		] {
+			match parse_header(&mut Input::from(header)) {
+				Ok(_) => panic!("{:?} should fail", header),
+				Err(e) => {
+					let e = e.to_string();
+					let source = header.lines().nth(line - 1).unwrap();
+					assert!(
+						e.contains(&format!("{}: \"{}\"", line, source)),
+						"{:?}: {}",
+						header,
+						e
+					);
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn test_shortcodes() {
+		let args = |pairs: Vec<(&str, Value)>| -> Table {
+			pairs.into_iter().map(|(k, v)| (k.to_string(), v)).collect()
+		};
+		assert_eq!(
+			inlines("See {{ figure(src='a.png', width=640, scale=0.5, lazy=true, tags=[\"x\", `y`,]) }}."),
+			vec![
+				Inline::Text("See "),
+				Inline::Shortcode(Shortcode {
+					name: "figure",
+					args: args(vec![
+						("src", Value::String("a.png".into())),
+						("width", Value::Integer(640)),
+						("scale", Value::Float(0.5)),
+						("lazy", Value::Boolean(true)),
+						(
+							"tags",
+							Value::Array(vec![Value::String("x".into()), Value::String("y".into())])
+						),
+					]),
+					body: None,
+					line: 1,
+					col: 4,
+					source_line: "See {{ figure(src='a.png', width=640, scale=0.5, lazy=true, tags=[\"x\", `y`,]) }}.",
+				}),
+				Inline::Text("."),
+			]
+		);
+		// Something that isn't a call is just text.
+		assert_eq!(
+			inlines("{{ not a call }}"),
+			vec![Inline::Text("{"), Inline::Text("{ not a call }}")]
+		);
+		// But once it's a call, mistakes in it are errors.
+		let e = parse_document(&mut Input::from("= T\n\nSee {{ f(a=1, a=2) }}\n")).unwrap_err();
+		assert_eq!(e.at(), (3, 14));
+		assert_eq!(e.expected(), "<Each shortcode argument once>");
+		let e =
+			parse_document(&mut Input::from("= T\n\n{{ f(a=1 }} and\n\n{{ g() }\n")).unwrap_err();
+		assert_eq!((e.at(), e.expected()), ((3, 9), ","));
+
+		let mut input = Input::from(
+			"= T\n\nsection\n\t{% note(kind=\"warning\") %}\n\tIndented *body*\n\n\t{% end %}\n",
+		);
+		let document = parse_document(&mut input).unwrap();
+		match &document.blocks[..] {
+			[Block::HtmlTag("section", _, blocks)] => match &blocks[..] {
+				[Block::Shortcode(shortcode)] => {
+					assert_eq!(shortcode.name, "note");
+					assert_eq!(
+						shortcode.args,
+						args(vec![("kind", Value::String("warning".into()))])
+					);
+					assert_eq!(shortcode.body, Some("\tIndented *body*\n"));
+					assert_eq!((shortcode.line, shortcode.col), (4, 1));
+				}
+				blocks => panic!("{:?}", blocks),
+			},
+			blocks => panic!("{:?}", blocks),
+		}
+		assert!(parse_document(&mut Input::from("= T\n\n{% note() %}\nNo end\n")).is_ok());
+	}
+
+	#[test]
+	fn test_documents() {
+		for post in POSTS {
+			let mut input = Input::from(post);
+			if let Err(e) = parse_document(&mut input) {
+				panic!("{}", e);
+			}
+		}
+	}
 }
@@ -1,4 +1,5 @@
 use std::any::type_name;
+use std::collections::HashMap;
 use std::error::Error;
 use std::fmt::Display;
 use std::panic::Location;
@@ -6,7 +7,7 @@ use std::str::pattern::Pattern;
 use std::str::pattern::SearchStep;
 use std::str::pattern::Searcher;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ParseError {
 	caller: &'static Location<'static>,
 	expected: &'static str,
@@ -24,6 +25,19 @@ impl Display for ParseError {
 	}
 }
 impl Error for ParseError {}
+impl ParseError {
+	/// Line and column of the error.
+	pub fn at(&self) -> (usize, usize) {
+		self.at
+	}
+	pub fn expected(&self) -> &str {
+		self.expected
+	}
+	/// The line the error is on.
+	pub fn source_line(&self) -> &str {
+		&self.line
+	}
+}
 
 pub type ParseResult<O> = Result<O, ParseError>;
 
@@ -32,6 +46,10 @@ pub struct Input<'i> {
 	lines: Vec<usize>,
 	input: &'i str,
 	consumed: usize,
+	// The first error from a parser that had committed to its input, which backtracking mustn't hide.
+	cut: Option<ParseError>,
+	// Failures remembered by expect_memo, by position and the name it was given.
+	failures: HashMap<(usize, &'static str), ParseError>,
 }
 impl<'i> From<&'i str> for Input<'i> {
 	fn from(s: &'i str) -> Self {
@@ -39,6 +57,8 @@ impl<'i> From<&'i str> for Input<'i> {
 			lines: s.match_indices('\n').map(|(i, _)| i).collect(),
 			input: s,
 			consumed: 0,
+			cut: None,
+			failures: HashMap::new(),
 		}
 	}
 }
@@ -54,16 +74,24 @@ impl<'i> Input<'i> {
 			Err(n) => n,
 		}
 	}
-	fn ln_cn_line(&self, index: usize) -> (usize, usize, &str) {
+	pub fn ln_cn_line(&self, index: usize) -> (usize, usize, &'i str) {
 		let li = self.line_idx(index);
 		let line_start = if li == 0 { 0 } else { self.lines[li - 1] + 1 };
 		let cn = index - line_start;
 		let line_end = self.lines.get(li).cloned().unwrap_or(self.input.len());
 		(li + 1, cn, &self.input[line_start..line_end])
 	}
+	pub fn position(&self) -> usize {
+		self.consumed
+	}
 	#[track_caller]
 	pub fn error(&self, expected: &'static str) -> ParseError {
-		let (ln, cn, line) = self.ln_cn_line(self.consumed);
+		self.error_at(self.consumed, expected)
+	}
+	#[track_caller]
+	pub fn error_at(&self, position: usize, expected: &'static str) -> ParseError {
+		// For when the problem is with something that's already been consumed.
+		let (ln, cn, line) = self.ln_cn_line(position);
 		ParseError {
 			caller: Location::caller(),
 			expected,
@@ -71,6 +99,16 @@ impl<'i> Input<'i> {
 			line: line.into(),
 		}
 	}
+	/// Keep the error from a parser that can't be anything else past this point, even if the caller backtracks and parses the input another way.
+	pub fn cut<O>(&mut self, result: ParseResult<O>) -> ParseResult<O> {
+		if let Err(e) = &result {
+			self.cut.get_or_insert_with(|| e.clone());
+		}
+		result
+	}
+	pub fn take_cut(&mut self) -> Option<ParseError> {
+		self.cut.take()
+	}
 	#[track_caller]
 	pub fn expect<P: Parser<'i>>(&mut self, p: &mut P) -> ParseResult<P::Output> {
 		let last_consumed = self.consumed;
@@ -82,6 +120,30 @@ impl<'i> Input<'i> {
 			}
 		}
 	}
+	/// Like expect, but a failure is remembered so that trying the same parser at the same position again fails straight away instead of parsing everything again.  The parser's result must only depend on the position, and name must be unique to it.
+	#[track_caller]
+	pub fn expect_memo<P: Parser<'i>>(
+		&mut self,
+		name: &'static str,
+		p: &mut P,
+	) -> ParseResult<P::Output> {
+		let key = (self.consumed, name);
+		if let Some(e) = self.failures.get(&key) {
+			return Err(e.clone());
+		}
+		let result = self.expect(p);
+		if let Err(e) = &result {
+			self.failures.insert(key, e.clone());
+		}
+		result
+	}
+	pub fn lookahead<P: Parser<'i>>(&mut self, p: &mut P) -> bool {
+		// Check whether a parser would match without consuming anything.
+		let last_consumed = self.consumed;
+		let ret = p.parse(self).is_ok();
+		self.consumed = last_consumed;
+		ret
+	}
 	#[track_caller]
 	pub fn expect_star<P: Parser<'i>>(&mut self, p: &mut P) -> Vec<P::Output> {
 		let mut v = vec![];
@@ -127,6 +189,34 @@ impl<'i> Input<'i> {
 		})
 	}
 	#[track_caller]
+	pub fn expect_literal(&mut self, literal: &'static str) -> ParseResult<&'i str> {
+		// Unlike expect_pattern, this only takes a single occurrence of the literal.
+		if !literal.is_empty() && self.input().starts_with(literal) {
+			let ret = &self.input[self.consumed..self.consumed + literal.len()];
+			self.consumed += literal.len();
+			Ok(ret)
+		} else {
+			Err(self.error(literal))
+		}
+	}
+	#[track_caller]
+	pub fn expect_len(&mut self, len: usize) -> ParseResult<&'i str> {
+		// Take exactly len bytes (which must end on a char boundary)
+		if len > 0 && self.input().is_char_boundary(len) {
+			let ret = &self.input[self.consumed..self.consumed + len];
+			self.consumed += len;
+			Ok(ret)
+		} else {
+			Err(self.error("<Length>"))
+		}
+	}
+	pub fn peek(&self) -> &'i str {
+		self.input()
+	}
+	pub fn last_char(&self) -> Option<char> {
+		self.input[..self.consumed].chars().next_back()
+	}
+	#[track_caller]
 	pub fn expect_lineend(&mut self) -> ParseResult<()> {
 		// Expect a line termination or the end of input
 		if self.consumed == self.input.len() {
@@ -0,0 +1,58 @@
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Call f on every item, spread across a thread per core. The results are in the same order as the items, however the threads happen to be scheduled.
+pub fn map<T, R>(items: Vec<T>, f: impl Fn(T) -> R + Send + Sync + 'static) -> Vec<R>
+where
+	T: Send + 'static,
+	R: Send + 'static,
+{
+	let threads = thread::available_parallelism()
+		.map_or(1, |n| n.get())
+		.min(items.len());
+	let queue = Arc::new(Mutex::new(items.into_iter().enumerate()));
+	let f = Arc::new(f);
+	let workers: Vec<_> = (0..threads)
+		.map(|_| {
+			let (queue, f) = (Arc::clone(&queue), Arc::clone(&f));
+			thread::spawn(move || {
+				let mut done = Vec::new();
+				loop {
+					// The lock is released before f runs, so that the others can take the next item.
+					let next = queue.lock().unwrap().next();
+					match next {
+						Some((i, item)) => done.push((i, f(item))),
+						None => return done,
+					}
+				}
+			})
+		})
+		.collect();
+	let mut results = Vec::new();
+	for worker in workers {
+		match worker.join() {
+			Ok(done) => results.extend(done),
+			// Rendering panicked: fail the same way as if it had happened on this thread.
+			Err(panic) => std::panic::resume_unwind(panic),
+		}
+	}
+	results.sort_by_key(|(i, _)| *i);
+	results.into_iter().map(|(_, r)| r).collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::time::Duration;
+
+	#[test]
+	fn test_map() {
+		// The early items take longest, so they finish last.
+		let squares = map((0..20u64).collect(), |i| {
+			thread::sleep(Duration::from_millis(20 - i));
+			i * i
+		});
+		assert_eq!(squares, (0..20u64).map(|i| i * i).collect::<Vec<_>>());
+		assert_eq!(map(Vec::<u8>::new(), |i| i), Vec::new());
+	}
+}
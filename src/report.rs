@@ -0,0 +1,174 @@
+use super::parser2::ParseError;
+use super::shortcode::ShortcodeError;
+use super::template::TemplateError;
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Something wrong with one of the site's files.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Problem {
+	pub path: PathBuf,
+	// 1 based, or 0 if it isn't about a line.
+	pub line: usize,
+	// 0 based, in bytes
+	pub col: usize,
+	pub source_line: String,
+	pub message: String,
+}
+impl Problem {
+	pub fn new(path: &Path, message: impl Into<String>) -> Self {
+		Self {
+			path: path.to_path_buf(),
+			line: 0,
+			col: 0,
+			source_line: String::new(),
+			message: message.into(),
+		}
+	}
+	pub fn parse(path: &Path, e: &ParseError) -> Self {
+		let (line, col) = e.at();
+		Self {
+			line,
+			col,
+			source_line: e.source_line().to_string(),
+			..Self::new(path, format!("expected {}", e.expected()))
+		}
+	}
+	/// Errors from rendering path arrive as io::Errors, but the ones from its contents have the details inside them.
+	pub fn from_io(path: &Path, e: &io::Error) -> Self {
+		let inner = e.get_ref();
+		if let Some(e) = inner.and_then(|e| e.downcast_ref::<ParseError>()) {
+			Self::parse(path, e)
+		} else if let Some(e) = inner.and_then(|e| e.downcast_ref::<ShortcodeError>()) {
+			Self {
+				line: e.line,
+				col: e.col,
+				source_line: e.source_line.clone(),
+				..Self::new(
+					path,
+					format!("shortcode `{}` failed: {}", e.name, e.message),
+				)
+			}
+		} else if let Some(e) = inner.and_then(|e| e.downcast_ref::<TemplateError>()) {
+			// The line is the template's, not the document's, so it's in the message.
+			Self::new(path, e.to_string().trim_end())
+		} else {
+			Self::new(path, e.to_string())
+		}
+	}
+}
+impl Display for Problem {
+	// Like rustc: the message, where it is, and the line with a caret under the column.
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		writeln!(f, "error: {}", self.message)?;
+		if self.line == 0 {
+			return writeln!(f, "  --> {}", self.path.display());
+		}
+		writeln!(
+			f,
+			"  --> {}:{}:{}",
+			self.path.display(),
+			self.line,
+			self.col + 1
+		)?;
+		let number = self.line.to_string();
+		let gutter = " ".repeat(number.len());
+		// Keep tabs so that the caret lines up.
+		let indent: String = self
+			.source_line
+			.get(..self.col)
+			.unwrap_or("")
+			.chars()
+			.map(|c| if c == '\t' { '\t' } else { ' ' })
+			.collect();
+		writeln!(f, "{} |", gutter)?;
+		writeln!(f, "{} | {}", number, self.source_line)?;
+		writeln!(f, "{} | {}^", gutter, indent)
+	}
+}
+
+/// Every problem found while building, so that one bad file doesn't hide the rest.
+#[derive(Debug, Default)]
+pub struct Report {
+	problems: Vec<Problem>,
+}
+impl Report {
+	pub fn add(&mut self, problem: Problem) {
+		self.problems.push(problem);
+	}
+	pub fn is_empty(&self) -> bool {
+		self.problems.is_empty()
+	}
+}
+impl Display for Report {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let mut problems = self.problems.iter().collect::<Vec<_>>();
+		problems.sort();
+		for problem in &problems {
+			writeln!(f, "{}", problem)?;
+		}
+		let mut files = problems.iter().map(|p| &p.path).collect::<Vec<_>>();
+		files.dedup();
+		write!(
+			f,
+			"{} error{} in {} file{}",
+			problems.len(),
+			if problems.len() == 1 { "" } else { "s" },
+			files.len(),
+			if files.len() == 1 { "" } else { "s" },
+		)
+	}
+}
+impl Error for Report {}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::parser::parse_document;
+	use crate::parser2::Input;
+
+	#[test]
+	fn test_report() {
+		let mut report = Report::default();
+		let e = parse_document(&mut Input::from("= T\n:weight:\theavy\n")).unwrap_err();
+		let wrapped = io::Error::new(io::ErrorKind::InvalidData, e);
+		report.add(Problem::from_io(Path::new("content/b.md"), &wrapped));
+		report.add(Problem::new(
+			Path::new("content/link"),
+			"is a broken symlink",
+		));
+		let e = ShortcodeError {
+			name: "youtube".to_string(),
+			line: 5,
+			col: 4,
+			source_line: "Two {{ youtube(id=3) }}".to_string(),
+			message: "`id` should be a string, not an integer".to_string(),
+		};
+		let wrapped = io::Error::new(io::ErrorKind::InvalidData, e);
+		report.add(Problem::from_io(Path::new("content/a.md"), &wrapped));
+		report.add(Problem::new(Path::new("content/a.md"), "Something else"));
+		let report = report.to_string();
+		assert!(
+			report.starts_with(
+				"error: Something else
+  --> content/a.md
+
+error: shortcode `youtube` failed: `id` should be a string, not an integer
+  --> content/a.md:5:5
+  |
+5 | Two {{ youtube(id=3) }}
+  |     ^
+
+error: expected "
+			),
+			"{}",
+			report
+		);
+		assert!(report.contains("expected An integer weight\n  --> content/b.md:2:"));
+		assert!(report.contains("2 | :weight:\theavy\n  |"), "{}", report);
+		assert!(report
+			.ends_with("error: is a broken symlink\n  --> content/link\n\n4 errors in 3 files"));
+	}
+}
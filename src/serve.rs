@@ -0,0 +1,373 @@
+use super::html::Escape;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+// Browsers listen here for rebuilds.
+const EVENTS_PATH: &str = "/__livereload";
+
+// Added to every html page: reload when the site is rebuilt, and show build errors over the page.
+const LIVE_RELOAD: &str = r#"<script>
+(() => {
+	const events = new EventSource("/__livereload");
+	events.addEventListener("reload", () => location.reload());
+	events.addEventListener("build-error", e => {
+		let overlay = document.getElementById("gen-error-overlay");
+		if (!overlay) {
+			overlay = document.createElement("pre");
+			overlay.id = "gen-error-overlay";
+			overlay.title = "Click to dismiss";
+			overlay.style = "position: fixed; inset: 0; z-index: 2147483647; margin: 0; padding: 2em; overflow: auto; white-space: pre-wrap; font: 14px/1.5 monospace; color: #fdf6e3; background: rgba(0, 0, 0, 0.9); border-top: 4px solid #dc322f;";
+			overlay.onclick = () => overlay.remove();
+			document.body.append(overlay);
+		}
+		overlay.textContent = "The site didn't build:\n\n" + e.data;
+	});
+})();
+</script>
+"#;
+
+fn content_type(path: &Path) -> &'static str {
+	match path.extension().and_then(|e| e.to_str()) {
+		Some("html" | "htm") => "text/html; charset=utf-8",
+		Some("css") => "text/css; charset=utf-8",
+		Some("js" | "mjs") => "text/javascript; charset=utf-8",
+		Some("json") => "application/json",
+		Some("xml") => "application/xml",
+		Some("txt" | "md") => "text/plain; charset=utf-8",
+		Some("svg") => "image/svg+xml",
+		Some("png") => "image/png",
+		Some("jpg" | "jpeg") => "image/jpeg",
+		Some("gif") => "image/gif",
+		Some("webp") => "image/webp",
+		Some("ico") => "image/x-icon",
+		Some("wasm") => "application/wasm",
+		Some("woff2") => "font/woff2",
+		_ => "application/octet-stream",
+	}
+}
+
+fn percent_decode(s: &str) -> String {
+	let mut bytes = Vec::new();
+	let mut rest = s.as_bytes();
+	while let Some((&b, tail)) = rest.split_first() {
+		let hex = tail.get(..2).and_then(|h| std::str::from_utf8(h).ok());
+		match hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+			Some(decoded) if b == b'%' => {
+				bytes.push(decoded);
+				rest = &tail[2..];
+			}
+			_ => {
+				bytes.push(b);
+				rest = tail;
+			}
+		}
+	}
+	String::from_utf8_lossy(&bytes).into_owned()
+}
+
+// The file a url path refers to, if it's inside root.
+fn resolve(root: &Path, url: &str) -> Option<PathBuf> {
+	let path = url.split(|c| c == '?' || c == '#').next().unwrap_or("");
+	let mut file = root.to_path_buf();
+	for segment in percent_decode(path).split('/') {
+		match segment {
+			"" | "." => {}
+			".." => return None,
+			s if s.contains('\\') => return None,
+			s => file.push(s),
+		}
+	}
+	if file.is_dir() {
+		file.push("index.html");
+	}
+	Some(file)
+}
+
+// Put the live reload script at the end of the body, or the end of the page if it doesn't have one.
+fn inject(html: &[u8]) -> Vec<u8> {
+	let html = String::from_utf8_lossy(html);
+	match html.rfind("</body>") {
+		Some(i) => format!("{}{}{}", &html[..i], LIVE_RELOAD, &html[i..]),
+		None => format!("{}{}", html, LIVE_RELOAD),
+	}
+	.into_bytes()
+}
+
+// A server-sent event, with every line of data prefixed.
+fn event(name: &str, data: &str) -> String {
+	let mut event = format!("event: {}\n", name);
+	for line in data.lines() {
+		event.push_str("data: ");
+		event.push_str(line);
+		event.push('\n');
+	}
+	event + "\n"
+}
+
+fn respond(
+	stream: &mut TcpStream,
+	status: &str,
+	content_type: &str,
+	body: &[u8],
+	head: bool,
+) -> io::Result<()> {
+	write!(
+		stream,
+		"HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n",
+		status,
+		content_type,
+		body.len()
+	)?;
+	if !head {
+		stream.write_all(body)?;
+	}
+	stream.flush()
+}
+
+/// Serves root, and tells the browsers that are looking at it when it's rebuilt.
+pub struct Server {
+	root: PathBuf,
+	// Browsers waiting for events
+	clients: Mutex<Vec<TcpStream>>,
+	// Why the last build failed, for browsers that connect while it's broken.
+	error: Mutex<Option<String>>,
+}
+impl Server {
+	pub fn new(root: PathBuf) -> Self {
+		Self {
+			root,
+			clients: Mutex::new(Vec::new()),
+			error: Mutex::new(None),
+		}
+	}
+	/// Handle connections on a thread of their own, and each request on a thread of its own.
+	pub fn listen(self: &Arc<Self>, listener: TcpListener) {
+		let server = self.clone();
+		thread::spawn(move || {
+			for stream in listener.incoming().flatten() {
+				let server = server.clone();
+				thread::spawn(move || {
+					// The browser hanging up isn't the server's problem.
+					let _ = server.handle(stream);
+				});
+			}
+		});
+	}
+	fn handle(&self, mut stream: TcpStream) -> io::Result<()> {
+		let mut reader = BufReader::new(stream.try_clone()?);
+		let mut request = String::new();
+		reader.read_line(&mut request)?;
+		// Skip the headers
+		let mut header = String::new();
+		while reader.read_line(&mut header)? > 2 {
+			header.clear();
+		}
+		let mut parts = request.split_whitespace();
+		let (method, url) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+		if method != "GET" && method != "HEAD" {
+			let message = b"Only GET and HEAD are supported";
+			return respond(
+				&mut stream,
+				"405 Method Not Allowed",
+				"text/plain",
+				message,
+				false,
+			);
+		}
+		let head = method == "HEAD";
+		if url == EVENTS_PATH {
+			write!(
+				stream,
+				"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-store\r\n\r\n"
+			)?;
+			if let Some(error) = &*self.error.lock().unwrap() {
+				stream.write_all(event("build-error", error).as_bytes())?;
+			}
+			self.clients.lock().unwrap().push(stream);
+			return Ok(());
+		}
+		let file = resolve(&self.root, url);
+		match file.as_ref().map(fs::read) {
+			Some(Ok(body)) => {
+				let file = file.unwrap();
+				let content_type = content_type(&file);
+				let body = if content_type.starts_with("text/html") {
+					inject(&body)
+				} else {
+					body
+				};
+				respond(&mut stream, "200 OK", content_type, &body, head)
+			}
+			_ => {
+				let body = match fs::read(self.root.join("404.html")) {
+					Ok(page) => page,
+					Err(_) => format!("<h1>Not found</h1><p>{}</p>", Escape(url)).into_bytes(),
+				};
+				let body = inject(&body);
+				respond(
+					&mut stream,
+					"404 Not Found",
+					"text/html; charset=utf-8",
+					&body,
+					head,
+				)
+			}
+		}
+	}
+	/// Tell every browser that the site was rebuilt, or why it couldn't be.
+	pub fn notify(&self, error: Option<String>) {
+		let message = match &error {
+			Some(error) => event("build-error", error),
+			None => event("reload", "reload"),
+		};
+		*self.error.lock().unwrap() = error;
+		// Browsers that have gone away are dropped.
+		self.clients
+			.lock()
+			.unwrap()
+			.retain(|mut client| client.write_all(message.as_bytes()).is_ok());
+	}
+}
+
+// Every file under paths with when it was modified and how big it is, which changes when any of them are edited, added or removed.
+fn snapshot(paths: &[PathBuf]) -> Vec<(PathBuf, SystemTime, u64)> {
+	fn walk(path: &Path, files: &mut Vec<(PathBuf, SystemTime, u64)>) {
+		let metadata = match fs::metadata(path) {
+			Ok(m) => m,
+			Err(_) => return,
+		};
+		if metadata.is_dir() {
+			for entry in fs::read_dir(path).into_iter().flatten().flatten() {
+				walk(&entry.path(), files);
+			}
+		} else {
+			let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+			files.push((path.to_path_buf(), modified, metadata.len()));
+		}
+	}
+	let mut files = Vec::new();
+	for path in paths {
+		walk(path, &mut files);
+	}
+	files.sort();
+	files
+}
+
+/// Serve root at address, and call build (with the url the site is served at) at the start and whenever anything in watched changes. Errors from build are shown in the browser instead of stopping the server.
+pub fn serve(
+	address: &str,
+	root: &Path,
+	watched: &[PathBuf],
+	mut build: impl FnMut(&str) -> Result<(), String>,
+) -> io::Result<()> {
+	let listener = TcpListener::bind(address)?;
+	let url = format!("http://{}", listener.local_addr()?);
+	let server = Arc::new(Server::new(root.to_path_buf()));
+	server.listen(listener);
+
+	let mut last = None;
+	loop {
+		let mut files = snapshot(watched);
+		if last.as_ref() != Some(&files) {
+			if last.is_some() {
+				// Editors often save in more than one step, so wait until they're done.
+				thread::sleep(Duration::from_millis(100));
+				files = snapshot(watched);
+				println!("Change detected, rebuilding");
+			}
+			let result = build(&url);
+			match &result {
+				Ok(()) if last.is_none() => println!("Serving {} at {}", root.display(), url),
+				Ok(()) => println!("Rebuilt"),
+				Err(e) => eprintln!("{}", e),
+			}
+			server.notify(result.err());
+			last = Some(files);
+		}
+		thread::sleep(Duration::from_millis(250));
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::io::Read;
+
+	fn get(address: &str, path: &str) -> String {
+		let mut stream = TcpStream::connect(address).unwrap();
+		write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+		let mut response = String::new();
+		stream.read_to_string(&mut response).unwrap();
+		response
+	}
+
+	#[test]
+	fn test_resolve() {
+		let root = Path::new("content");
+		assert_eq!(
+			resolve(
+				root,
+				"/blog/Built-In%20and%20Custom%20Traits%20in%20JavaScript/?a#b"
+			),
+			Some(root.join("blog/Built-In and Custom Traits in JavaScript/index.html"))
+		);
+		assert_eq!(resolve(root, "/blog/../../secret"), None);
+		assert_eq!(resolve(root, "/%2e%2e/secret"), None);
+		assert_eq!(percent_decode("100%25 %zz%e2%9c%93"), "100% %zz✓");
+		assert_eq!(
+			event("build-error", "line 1\nline 2"),
+			"event: build-error\ndata: line 1\ndata: line 2\n\n"
+		);
+	}
+
+	#[test]
+	fn test_server() {
+		let root = std::env::temp_dir().join(format!("gen-serve-test-{}", std::process::id()));
+		fs::create_dir_all(root.join("blog")).unwrap();
+		fs::write(root.join("blog/index.html"), "<body>Blog</body>").unwrap();
+		fs::write(root.join("style.css"), "body {}").unwrap();
+
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let address = listener.local_addr().unwrap().to_string();
+		let server = Arc::new(Server::new(root.clone()));
+		server.listen(listener);
+
+		let page = get(&address, "/blog/");
+		assert!(page.starts_with("HTTP/1.1 200 OK\r\n"), "{}", page);
+		assert!(page.contains("Content-Type: text/html"));
+		assert!(page.contains("Blog<script>"));
+		assert!(page.ends_with("</script>\n</body>"));
+		let css = get(&address, "/style.css");
+		assert!(css.contains("text/css") && css.ends_with("\r\n\r\nbody {}"));
+		assert!(get(&address, "/nope").starts_with("HTTP/1.1 404 Not Found\r\n"));
+
+		// Browsers that connect get the error from the last build, and then whatever happens next.
+		server.notify(Some("Unexpected `}`".to_string()));
+		let mut events = TcpStream::connect(&address).unwrap();
+		write!(events, "GET {} HTTP/1.1\r\n\r\n", EVENTS_PATH).unwrap();
+		let mut reader = BufReader::new(events);
+		let mut lines = Vec::new();
+		while lines.last().map(String::as_str) != Some("data: Unexpected `}`") {
+			let mut line = String::new();
+			reader.read_line(&mut line).unwrap();
+			lines.push(line.trim_end().to_string());
+		}
+		assert!(lines.contains(&"event: build-error".to_string()));
+		// Wait for the server to add it as a client.
+		while server.clients.lock().unwrap().is_empty() {
+			thread::sleep(Duration::from_millis(10));
+		}
+		server.notify(None);
+		let mut line = String::new();
+		while line.trim_end() != "event: reload" {
+			line.clear();
+			reader.read_line(&mut line).unwrap();
+		}
+		fs::remove_dir_all(&root).unwrap();
+	}
+}
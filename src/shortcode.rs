@@ -0,0 +1,209 @@
+use super::asciimath;
+use super::parser::Shortcode;
+use super::template::{TemplateError, Templates};
+use super::toml::{Table, Value};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::Display;
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug)]
+pub struct ShortcodeError {
+	pub name: String,
+	pub line: usize,
+	pub col: usize,
+	pub source_line: String,
+	pub message: String,
+}
+impl Display for ShortcodeError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		writeln!(
+			f,
+			"Shortcode `{}` failed at col {}: {}",
+			self.name, self.col, self.message
+		)?;
+		writeln!(f, r#"{}: "{}""#, self.line, self.source_line)
+	}
+}
+impl Error for ShortcodeError {}
+
+/// Takes the shortcode's arguments (and its body as `body`), returns html.
+pub type Function = Box<dyn Fn(&Table) -> Result<String, String> + Send + Sync>;
+
+/// The shortcodes that documents can call: Rust functions and the templates in templates/shortcodes.
+#[derive(Default)]
+pub struct Shortcodes {
+	functions: HashMap<String, Function>,
+	templates: Templates,
+	// Shortcode name -> template name
+	template_names: HashMap<String, String>,
+}
+impl Shortcodes {
+	/// Load every .html and .md file in dir, named after the file: dir/youtube.html is the youtube shortcode. Their output is html either way, .md is allowed so that Zola's shortcodes keep working.
+	pub fn load(dir: &Path) -> Result<Self, TemplateError> {
+		let mut shortcodes = Self::default();
+		if !dir.is_dir() {
+			return Ok(shortcodes);
+		}
+		let io_error = |e| TemplateError::Io(dir.to_path_buf(), e);
+		for entry in fs::read_dir(dir).map_err(io_error)? {
+			let path = entry.map_err(io_error)?.path();
+			let is_template =
+				path.extension().contains(&"html") || path.extension().contains(&"md");
+			if !is_template {
+				continue;
+			}
+			let source =
+				fs::read_to_string(&path).map_err(|e| TemplateError::Io(path.clone(), e))?;
+			let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+			let template_name =
+				format!("shortcodes/{}", path.file_name().unwrap().to_string_lossy());
+			shortcodes.templates.add(&template_name, &source)?;
+			shortcodes.template_names.insert(name, template_name);
+		}
+		Ok(shortcodes)
+	}
+	/// Functions take precedence over templates with the same name.
+	pub fn register(
+		&mut self,
+		name: &str,
+		f: impl Fn(&Table) -> Result<String, String> + Send + Sync + 'static,
+	) {
+		self.functions.insert(name.to_string(), Box::new(f));
+	}
+	pub fn render(&self, shortcode: &Shortcode) -> Result<String, ShortcodeError> {
+		let mut args = shortcode.args.clone();
+		if let Some(body) = shortcode.body {
+			args.insert("body".to_string(), Value::String(body.to_string()));
+		}
+		let result = if let Some(f) = self.functions.get(shortcode.name) {
+			f(&args)
+		} else if let Some(template) = self.template_names.get(shortcode.name) {
+			self.templates
+				.render(template, &args)
+				.map_err(|e| e.to_string().trim_end().to_string())
+		} else {
+			Err("There isn't a shortcode with that name".to_string())
+		};
+		result.map_err(|message| ShortcodeError {
+			name: shortcode.name.to_string(),
+			line: shortcode.line,
+			col: shortcode.col,
+			source_line: shortcode.source_line.to_string(),
+			message,
+		})
+	}
+}
+
+/// The built in math shortcode: its body is AsciiMath, shown as MathML.
+pub fn math(args: &Table) -> Result<String, String> {
+	match args.get("body") {
+		Some(Value::String(body)) => Ok(asciimath::to_mathml(body)),
+		Some(v) => Err(format!("`body` should be a string, not {}", v.type_name())),
+		None => Err("`body` is missing".to_string()),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::html::{render_blocks, Context};
+	use crate::image::Images;
+	use crate::parser::parse_document;
+	use crate::parser2::Input;
+
+	fn render(shortcodes: &Shortcodes, s: &str) -> Result<String, String> {
+		let document = parse_document(&mut Input::from(s)).map_err(|e| e.to_string())?;
+		let cx = Context {
+			source_dir: "",
+			output_dir: "",
+			references: &document.references,
+			shortcodes,
+			images: &Images::default(),
+			used: Default::default(),
+		};
+		let mut output = Vec::new();
+		render_blocks(&mut output, &cx, &document.blocks).map_err(|e| e.to_string())?;
+		Ok(String::from_utf8(output).unwrap())
+	}
+
+	#[test]
+	fn test_shortcodes() {
+		let dir = std::env::temp_dir().join(format!("gen-shortcodes-test-{}", std::process::id()));
+		fs::create_dir_all(&dir).unwrap();
+		fs::write(dir.join("aside.md"), "<aside>{{ body }}</aside>").unwrap();
+		let shortcodes = Shortcodes::load(&dir);
+		fs::remove_dir_all(&dir).unwrap();
+		let mut shortcodes = shortcodes.unwrap();
+		shortcodes.register("math", math);
+		shortcodes.register("youtube", |args| match args.get("id") {
+			Some(Value::String(id)) => Ok(format!(
+				r#"<iframe src="https://www.youtube.com/embed/{}"></iframe>"#,
+				id
+			)),
+			Some(v) => Err(format!("`id` should be a string, not {}", v.type_name())),
+			None => Err("`id` is missing".to_string()),
+		});
+		shortcodes.register("quote", |args| {
+			Ok(format!(
+				"<blockquote>{:?} {:?}</blockquote>",
+				args.get("by"),
+				args.get("body")
+			))
+		});
+
+		assert_eq!(
+			render(&shortcodes, "= T\n\nWatch {{ youtube(id='abc') }} now\n"),
+			Ok(
+				"<p>Watch <iframe src=\"https://www.youtube.com/embed/abc\"></iframe> now</p>\n"
+					.to_string()
+			)
+		);
+		assert_eq!(
+			render(
+				&shortcodes,
+				"= T\n\nThe sequence {{ math(body=\"Z_0 = C\") }}\n"
+			),
+			Ok("<p>The sequence <math xmlns=\"http://www.w3.org/1998/Math/MathML\"><msub><mi>Z</mi><mn>0</mn></msub><mo>=</mo><mi>C</mi></math></p>\n".to_string())
+		);
+		assert_eq!(
+			render(&shortcodes, "= T\n\nA {{ aside(body=\"<note>\") }}\n"),
+			Ok("<p>A <aside>&lt;note&gt;</aside></p>\n".to_string())
+		);
+		assert_eq!(
+			render(
+				&shortcodes,
+				"= T\n\n{% quote(by=[\"Me\", 2]) %}\nSome *text*\n{% end %}\nAnd after.\n"
+			),
+			Ok("<blockquote>Some(Array([String(\"Me\"), Integer(2)])) Some(String(\"Some *text*\"))</blockquote>\n<p>And after.</p>\n".to_string())
+		);
+
+		// Errors point at the call.
+		let e = render(&shortcodes, "= T\n\nOne\n\nTwo {{ youtube(id=3) }}\n").unwrap_err();
+		assert_eq!(
+			e,
+			"Shortcode `youtube` failed at col 4: `id` should be a string, not an integer\n5: \"Two {{ youtube(id=3) }}\"\n"
+		);
+		let e = render(&shortcodes, "= T\n\n{{ nope() }}\n").unwrap_err();
+		assert!(
+			e.starts_with(
+				"Shortcode `nope` failed at col 0: There isn't a shortcode with that name"
+			),
+			"{}",
+			e
+		);
+		let e = render(&shortcodes, "= T\n\n{{ math() }}\n").unwrap_err();
+		assert!(
+			e.starts_with("Shortcode `math` failed at col 0: `body` is missing"),
+			"{}",
+			e
+		);
+		let e = render(&shortcodes, "= T\n\n{{ aside() }}\n").unwrap_err();
+		assert!(
+			e.starts_with("Shortcode `aside` failed at col 0: In template shortcodes/aside.md"),
+			"{}",
+			e
+		);
+	}
+}
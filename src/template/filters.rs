@@ -0,0 +1,251 @@
+use super::to_string;
+use crate::asciimath;
+use crate::date::Date;
+use crate::html::Escape;
+use crate::toml::{Table, Value};
+
+fn string_arg<'a>(args: &'a Table, name: &str) -> Result<Option<&'a str>, String> {
+	match args.get(name) {
+		None => Ok(None),
+		Some(Value::String(s)) => Ok(Some(s)),
+		Some(v) => Err(format!(
+			"`{}` should be a string, not {}",
+			name,
+			v.type_name()
+		)),
+	}
+}
+fn int_arg(args: &Table, name: &str) -> Result<Option<i64>, String> {
+	match args.get(name) {
+		None => Ok(None),
+		Some(Value::Integer(i)) => Ok(Some(*i)),
+		Some(v) => Err(format!(
+			"`{}` should be an integer, not {}",
+			name,
+			v.type_name()
+		)),
+	}
+}
+
+fn strip_tags(s: &str) -> String {
+	let mut out = String::new();
+	let mut rest = s;
+	while let Some(start) = rest.find('<') {
+		out.push_str(&rest[..start]);
+		match rest[start..].find('>') {
+			Some(end) => rest = &rest[start + end + 1..],
+			None => {
+				rest = "";
+			}
+		}
+	}
+	out.push_str(rest);
+	out
+}
+
+// Like Tera's truncate: keep the first length characters and mark that something was cut off.
+fn truncate(s: &str, length: usize, end: &str) -> String {
+	match s.char_indices().nth(length) {
+		Some((i, _)) => format!("{}{}", &s[..i], end),
+		None => s.to_string(),
+	}
+}
+
+fn title_case(s: &str) -> String {
+	let mut out = String::new();
+	let mut start = true;
+	for c in s.chars() {
+		if start {
+			out.extend(c.to_uppercase());
+		} else {
+			out.push(c);
+		}
+		start = c.is_whitespace();
+	}
+	out
+}
+
+// Clamp possibly negative indices the way slices in python do.
+fn slice_index(i: i64, len: usize) -> usize {
+	if i < 0 {
+		len.saturating_sub(i.unsigned_abs() as usize)
+	} else {
+		(i as usize).min(len)
+	}
+}
+
+/// Apply a filter to a value, which is None if it's undefined.
+pub fn apply(name: &str, value: Option<Value>, args: &Table) -> Result<Option<Value>, String> {
+	if name == "default" {
+		return Ok(value.or_else(|| args.get("value").cloned()));
+	}
+	let value = value.ok_or_else(|| format!("The value passed to `{}` is undefined", name))?;
+	Ok(Some(match (name, value) {
+		("safe", v) => v,
+		("escape", v) => Value::String(Escape(&to_string(&v)).to_string()),
+		("upper", Value::String(s)) => Value::String(s.to_uppercase()),
+		("lower", Value::String(s)) => Value::String(s.to_lowercase()),
+		("trim", Value::String(s)) => Value::String(s.trim().to_string()),
+		("title", Value::String(s)) => Value::String(title_case(&s)),
+		("striptags", Value::String(s)) => Value::String(strip_tags(&s)),
+		("truncate", Value::String(s)) => {
+			let length = int_arg(args, "length")?.unwrap_or(255).max(0) as usize;
+			let end = string_arg(args, "end")?.unwrap_or("…");
+			Value::String(truncate(&s, length, end))
+		}
+		("replace", Value::String(s)) => {
+			let from = string_arg(args, "from")?.ok_or("`from` is missing")?;
+			let to = string_arg(args, "to")?.ok_or("`to` is missing")?;
+			Value::String(s.replace(from, to))
+		}
+		("date", v) => {
+			let date = match v {
+				Value::Datetime(d) => d,
+				Value::String(s) => Date::parse(&s)?,
+				v => return Err(format!("`date` can't format {}", v.type_name())),
+			};
+			let format = string_arg(args, "format")?.unwrap_or("%Y-%m-%d");
+			Value::String(
+				date.format(format)
+					.map_err(|c| format!("`%{}` isn't a supported date format", c))?,
+			)
+		}
+		("asciimath", Value::String(s)) => Value::String(asciimath::to_mathml(&s)),
+		("length", Value::String(s)) => Value::Integer(s.chars().count() as i64),
+		("length", Value::Array(a)) => Value::Integer(a.len() as i64),
+		("length", Value::Table(t)) => Value::Integer(t.len() as i64),
+		("first", Value::Array(a)) => return Ok(a.into_iter().next()),
+		("last", Value::Array(a)) => return Ok(a.into_iter().last()),
+		("slice", Value::Array(a)) => {
+			let start = slice_index(int_arg(args, "start")?.unwrap_or(0), a.len());
+			let end = slice_index(int_arg(args, "end")?.unwrap_or(a.len() as i64), a.len());
+			Value::Array(a[start..end.max(start)].to_vec())
+		}
+		("join", Value::Array(a)) => Value::String(
+			a.iter()
+				.map(to_string)
+				.collect::<Vec<_>>()
+				.join(string_arg(args, "sep")?.unwrap_or("")),
+		),
+		(
+			"upper" | "lower" | "trim" | "title" | "striptags" | "truncate" | "replace"
+			| "asciimath" | "length" | "first" | "last" | "slice" | "join",
+			v,
+		) => return Err(format!("`{}` can't be applied to {}", name, v.type_name())),
+		_ => return Err(format!("`{}` isn't a filter", name)),
+	}))
+}
+
+fn range(args: &Table) -> Result<Value, String> {
+	let start = int_arg(args, "start")?.unwrap_or(0);
+	let end = int_arg(args, "end")?.ok_or("`end` is missing")?;
+	let step = int_arg(args, "step_by")?.unwrap_or(1);
+	if step <= 0 {
+		return Err("`step_by` should be positive".to_string());
+	}
+	Ok(Value::Array(
+		(start..end)
+			.step_by(step as usize)
+			.map(Value::Integer)
+			.collect(),
+	))
+}
+
+/// Functions that every template can call. The site adds its own with Templates::register_function.
+pub fn builtin(name: &str, args: &Table) -> Option<Result<Value, String>> {
+	match name {
+		"range" => Some(range(args)),
+		_ => None,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_filters() {
+		let s = |s: &str| Some(Value::String(s.to_string()));
+		let args = |pairs: &[(&str, Value)]| -> Table {
+			pairs
+				.iter()
+				.map(|(k, v)| (k.to_string(), v.clone()))
+				.collect()
+		};
+		let none = Table::new();
+		assert_eq!(
+			apply("striptags", s("<p>Hello <em>World</em></p>"), &none),
+			Ok(s("Hello World"))
+		);
+		assert_eq!(
+			apply(
+				"truncate",
+				s("Mediocre content"),
+				&args(&[("length", Value::Integer(8))])
+			),
+			Ok(s("Mediocre…"))
+		);
+		assert_eq!(
+			apply(
+				"truncate",
+				s("Short"),
+				&args(&[("length", Value::Integer(8))])
+			),
+			Ok(s("Short"))
+		);
+		assert_eq!(
+			apply(
+				"date",
+				s("2020-03-27T20:18:42Z"),
+				&args(&[("format", Value::String("%B %e, %Y".into()))])
+			),
+			Ok(s("March 27, 2020"))
+		);
+		assert_eq!(
+			apply("title", s("finite state machines"), &none),
+			Ok(s("Finite State Machines"))
+		);
+		assert_eq!(
+			apply("escape", s("<a & b>"), &none),
+			Ok(s("&lt;a &amp; b&gt;"))
+		);
+		assert_eq!(
+			apply("asciimath", s("x^2"), &none),
+			Ok(s(
+				r#"<math xmlns="http://www.w3.org/1998/Math/MathML"><msup><mi>x</mi><mn>2</mn></msup></math>"#
+			))
+		);
+		assert_eq!(
+			apply("default", None, &args(&[("value", Value::Integer(1))])),
+			Ok(Some(Value::Integer(1)))
+		);
+		let list = Some(Value::Array(vec![
+			Value::Integer(1),
+			Value::Integer(2),
+			Value::Integer(3),
+		]));
+		assert_eq!(
+			apply("last", list.clone(), &none),
+			Ok(Some(Value::Integer(3)))
+		);
+		assert_eq!(
+			apply("length", list.clone(), &none),
+			Ok(Some(Value::Integer(3)))
+		);
+		assert_eq!(
+			apply("slice", list.clone(), &args(&[("end", Value::Integer(-1))])),
+			Ok(Some(Value::Array(vec![
+				Value::Integer(1),
+				Value::Integer(2)
+			])))
+		);
+		assert_eq!(
+			apply("join", list, &args(&[("sep", Value::String(", ".into()))])),
+			Ok(s("1, 2, 3"))
+		);
+		assert!(apply("upper", None, &none).is_err());
+		assert!(apply("upper", Some(Value::Integer(1)), &none).is_err());
+		assert!(apply("nonexistent", s(""), &none).is_err());
+		assert!(apply("date", s("yesterday"), &none).is_err());
+	}
+}
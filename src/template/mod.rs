@@ -0,0 +1,886 @@
+// A subset of Tera (https://tera.netlify.app/docs/) for rendering the templates directory.
+use crate::html::Escape;
+use crate::parser2::{Input, ParseError};
+use crate::toml::{Table, Value};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::Display;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+mod filters;
+mod parser;
+use parser::{Expr, ExprKind, Node, Op, Template};
+
+#[derive(Debug)]
+pub enum TemplateError {
+	Io(PathBuf, io::Error),
+	Parse(String, ParseError),
+	Render {
+		template: String,
+		line: usize,
+		col: usize,
+		source_line: String,
+		message: String,
+	},
+}
+impl Display for TemplateError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			TemplateError::Io(path, e) => write!(f, "Couldn't read template {:?}: {}", path, e),
+			TemplateError::Parse(template, e) => write!(f, "In template {}, {}", template, e),
+			TemplateError::Render {
+				template,
+				line,
+				col,
+				source_line,
+				message,
+			} => {
+				writeln!(
+					f,
+					"In template {}, rendering failed at col {}: {}",
+					template, col, message
+				)?;
+				writeln!(f, r#"{}: "{}""#, line, source_line)
+			}
+		}
+	}
+}
+impl Error for TemplateError {}
+
+pub type Function = Box<dyn Fn(&Table) -> Result<Value, String> + Send + Sync>;
+
+/// Every template in a directory, by their path relative to it: "tags/list.html"
+#[derive(Default)]
+pub struct Templates {
+	templates: HashMap<String, Template>,
+	functions: HashMap<String, Function>,
+}
+
+pub fn truthy(value: &Option<Value>) -> bool {
+	match value {
+		None => false,
+		Some(Value::Boolean(b)) => *b,
+		Some(Value::String(s)) => !s.is_empty(),
+		Some(Value::Integer(i)) => *i != 0,
+		Some(Value::Float(f)) => *f != 0.0,
+		Some(Value::Array(a)) => !a.is_empty(),
+		Some(Value::Table(t)) => !t.is_empty(),
+		Some(Value::Datetime(_)) => true,
+	}
+}
+pub fn to_string(value: &Value) -> String {
+	match value {
+		Value::String(s) => s.clone(),
+		Value::Integer(i) => i.to_string(),
+		Value::Float(f) => f.to_string(),
+		Value::Boolean(b) => b.to_string(),
+		Value::Datetime(d) => d.to_string(),
+		Value::Array(a) => format!(
+			"[{}]",
+			a.iter().map(to_string).collect::<Vec<_>>().join(", ")
+		),
+		Value::Table(t) => format!(
+			"{{{}}}",
+			t.iter()
+				.map(|(k, v)| format!("{}: {}", k, to_string(v)))
+				.collect::<Vec<_>>()
+				.join(", ")
+		),
+	}
+}
+fn number(value: &Value) -> Option<f64> {
+	match value {
+		Value::Integer(i) => Some(*i as f64),
+		Value::Float(f) => Some(*f),
+		_ => None,
+	}
+}
+fn equal(a: &Option<Value>, b: &Option<Value>) -> bool {
+	match (a, b) {
+		(Some(a), Some(b)) => match (number(a), number(b)) {
+			(Some(x), Some(y)) => x == y,
+			_ => a == b,
+		},
+		_ => a == b,
+	}
+}
+fn compare(a: &Value, b: &Value) -> Option<std::cmp::Ordering> {
+	match (a, b) {
+		(Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+		(Value::Datetime(a), Value::Datetime(b)) => Some(a.cmp(b)),
+		_ => number(a)?.partial_cmp(&number(b)?),
+	}
+}
+fn arithmetic(op: Op, a: &Value, b: &Value) -> Result<Value, String> {
+	if let (Value::Integer(x), Value::Integer(y), false) = (a, b, op == Op::Div) {
+		let result = match op {
+			Op::Add => x.checked_add(*y),
+			Op::Sub => x.checked_sub(*y),
+			Op::Mul => x.checked_mul(*y),
+			_ => x.checked_rem(*y),
+		};
+		return result
+			.map(Value::Integer)
+			.ok_or_else(|| "Integer overflow or division by zero".to_string());
+	}
+	match (number(a), number(b)) {
+		(Some(x), Some(y)) => Ok(Value::Float(match op {
+			Op::Add => x + y,
+			Op::Sub => x - y,
+			Op::Mul => x * y,
+			Op::Div => x / y,
+			_ => x % y,
+		})),
+		_ => Err(format!(
+			"Can't do arithmetic with {} and {}",
+			a.type_name(),
+			b.type_name()
+		)),
+	}
+}
+// Whether an output is already html: {{ x | safe }}, {{ super() }} and macros.
+fn is_safe(expr: &Expr) -> bool {
+	match &expr.kind {
+		ExprKind::Filter(_, name, _) => name == "safe" || name == "escape",
+		ExprKind::Call(Some(_), ..) | ExprKind::Super => true,
+		_ => false,
+	}
+}
+
+struct Renderer<'t> {
+	templates: &'t Templates,
+	// The context the template was rendered with. Macros only see their arguments.
+	context: Option<&'t Table>,
+	// Variables from set and for loops, innermost last
+	scopes: Vec<Table>,
+	// The template and its parents, for finding blocks
+	chain: Vec<&'t str>,
+	// The block being rendered and where in the chain it's defined, for super()
+	block: Option<(&'t str, usize)>,
+	// The template that the nodes being rendered are from
+	current: &'t str,
+}
+impl<'t> Renderer<'t> {
+	fn template(&self, name: &str) -> &'t Template {
+		&self.templates.templates[name]
+	}
+	fn error(&self, position: usize, message: String) -> TemplateError {
+		let input = Input::from(self.template(self.current).source.as_str());
+		let (line, col, source_line) = input.ln_cn_line(position);
+		TemplateError::Render {
+			template: self.current.to_string(),
+			line,
+			col,
+			source_line: source_line.to_string(),
+			message,
+		}
+	}
+	fn lookup(&self, name: &str) -> Option<Value> {
+		self.scopes
+			.iter()
+			.rev()
+			.find_map(|s| s.get(name))
+			.or_else(|| self.context?.get(name))
+			.cloned()
+	}
+	fn args(&mut self, args: &'t [(String, Expr)]) -> Result<Table, TemplateError> {
+		let mut table = Table::new();
+		for (name, expr) in args {
+			let value = self
+				.eval(expr)?
+				.ok_or_else(|| self.error(expr.position, format!("`{}` is undefined", name)))?;
+			table.insert(name.clone(), value);
+		}
+		Ok(table)
+	}
+	fn call_macro(
+		&mut self,
+		namespace: &str,
+		name: &str,
+		args: &'t [(String, Expr)],
+		position: usize,
+	) -> Result<Value, TemplateError> {
+		let template_name = if namespace == "self" {
+			Some(self.current)
+		} else {
+			self.template(self.current)
+				.imports
+				.get(namespace)
+				.map(String::as_str)
+		}
+		.ok_or_else(|| self.error(position, format!("`{}` hasn't been imported", namespace)))?;
+		let template_name = self
+			.templates
+			.templates
+			.get_key_value(template_name)
+			.ok_or_else(|| {
+				self.error(
+					position,
+					format!("There's no template named {:?}", template_name),
+				)
+			})?
+			.0;
+		let m = self
+			.template(template_name)
+			.macros
+			.get(name)
+			.ok_or_else(|| {
+				self.error(
+					position,
+					format!("{} doesn't have a macro named `{}`", template_name, name),
+				)
+			})?;
+		let mut values = self.args(args)?;
+		for (arg, _) in args {
+			if !m.args.iter().any(|(a, _)| a == arg) {
+				return Err(self.error(position, format!("`{}` doesn't take `{}`", name, arg)));
+			}
+		}
+		for (arg, default) in &m.args {
+			if values.contains_key(arg) {
+				continue;
+			}
+			let previous = std::mem::replace(&mut self.current, template_name);
+			let value = match default {
+				Some(default) => self.eval(default),
+				None => Ok(None),
+			};
+			self.current = previous;
+			if let Some(value) = value? {
+				values.insert(arg.clone(), value);
+			}
+		}
+
+		// Macros are rendered in their own template with only their arguments.
+		let saved = (
+			self.context.take(),
+			std::mem::replace(&mut self.scopes, vec![values]),
+			std::mem::replace(&mut self.current, template_name),
+		);
+		let mut out = String::new();
+		let result = self.render_nodes(&m.body, &mut out);
+		self.context = saved.0;
+		self.scopes = saved.1;
+		self.current = saved.2;
+		result?;
+		Ok(Value::String(out))
+	}
+	// Render the definition of a block at a position in the chain.
+	fn render_block(
+		&mut self,
+		name: &'t str,
+		level: usize,
+		out: &mut String,
+	) -> Result<(), TemplateError> {
+		let template = self.chain[level];
+		let saved = (
+			self.block.replace((name, level)),
+			std::mem::replace(&mut self.current, template),
+		);
+		let result = self.render_nodes(&self.template(template).blocks[name], out);
+		self.block = saved.0;
+		self.current = saved.1;
+		result
+	}
+	fn eval(&mut self, expr: &'t Expr) -> Result<Option<Value>, TemplateError> {
+		let error = |r: &Self, message: String| r.error(expr.position, message);
+		Ok(match &expr.kind {
+			ExprKind::Literal(v) => Some(v.clone()),
+			ExprKind::Array(items) => {
+				let mut values = Vec::new();
+				for item in items {
+					values.push(
+						self.eval(item)?
+							.ok_or_else(|| error(self, "Undefined value in an array".into()))?,
+					);
+				}
+				Some(Value::Array(values))
+			}
+			ExprKind::Var(name) => self.lookup(name),
+			ExprKind::Attr(e, name) => match self.eval(e)? {
+				Some(Value::Table(mut t)) => t.remove(name),
+				_ => None,
+			},
+			ExprKind::Index(e, index) => match (self.eval(e)?, self.eval(index)?) {
+				(Some(Value::Table(mut t)), Some(Value::String(key))) => t.remove(&key),
+				(Some(Value::Array(a)), Some(Value::Integer(i))) if i >= 0 => {
+					a.into_iter().nth(i as usize)
+				}
+				_ => None,
+			},
+			ExprKind::Not(e) => Some(Value::Boolean(!truthy(&self.eval(e)?))),
+			ExprKind::Neg(e) => match self.eval(e)? {
+				Some(Value::Integer(i)) => Some(Value::Integer(-i)),
+				Some(Value::Float(f)) => Some(Value::Float(-f)),
+				_ => return Err(error(self, "Only numbers can be negated".into())),
+			},
+			ExprKind::Binary(op, a, b) => {
+				let a_value = self.eval(a)?;
+				match op {
+					Op::And if !truthy(&a_value) => return Ok(Some(Value::Boolean(false))),
+					Op::Or if truthy(&a_value) => return Ok(Some(Value::Boolean(true))),
+					_ => {}
+				}
+				let b_value = self.eval(b)?;
+				Some(match op {
+					Op::And | Op::Or => Value::Boolean(truthy(&b_value)),
+					Op::Eq => Value::Boolean(equal(&a_value, &b_value)),
+					Op::Ne => Value::Boolean(!equal(&a_value, &b_value)),
+					_ => {
+						let (a_value, b_value) = match (a_value, b_value) {
+							(Some(a), Some(b)) => (a, b),
+							(None, _) => {
+								return Err(self.error(a.position, "Undefined value".into()))
+							}
+							(_, None) => {
+								return Err(self.error(b.position, "Undefined value".into()))
+							}
+						};
+						match op {
+							Op::Lt | Op::Gt | Op::Le | Op::Ge => {
+								let ordering = compare(&a_value, &b_value).ok_or_else(|| {
+									error(
+										self,
+										format!(
+											"Can't compare {} with {}",
+											a_value.type_name(),
+											b_value.type_name()
+										),
+									)
+								})?;
+								Value::Boolean(match op {
+									Op::Lt => ordering.is_lt(),
+									Op::Gt => ordering.is_gt(),
+									Op::Le => ordering.is_le(),
+									_ => ordering.is_ge(),
+								})
+							}
+							Op::In => Value::Boolean(match (&a_value, &b_value) {
+								(_, Value::Array(items)) => items
+									.iter()
+									.any(|i| equal(&Some(i.clone()), &Some(a_value.clone()))),
+								(Value::String(s), Value::String(haystack)) => {
+									haystack.contains(s.as_str())
+								}
+								(Value::String(key), Value::Table(t)) => t.contains_key(key),
+								_ => {
+									return Err(error(
+										self,
+										format!(
+											"Can't look for {} in {}",
+											a_value.type_name(),
+											b_value.type_name()
+										),
+									))
+								}
+							}),
+							Op::Concat => Value::String(to_string(&a_value) + &to_string(&b_value)),
+							_ => arithmetic(*op, &a_value, &b_value).map_err(|m| error(self, m))?,
+						}
+					}
+				})
+			}
+			ExprKind::Filter(e, name, args) => {
+				let value = self.eval(e)?;
+				let args = self.args(args)?;
+				filters::apply(name, value, &args).map_err(|m| error(self, m))?
+			}
+			ExprKind::Call(Some(namespace), name, args) => {
+				Some(self.call_macro(namespace, name, args, expr.position)?)
+			}
+			ExprKind::Call(None, name, args) => {
+				let args = self.args(args)?;
+				let result = match self.templates.functions.get(name) {
+					Some(f) => f(&args),
+					None => filters::builtin(name, &args)
+						.unwrap_or_else(|| Err(format!("`{}` isn't a function", name))),
+				};
+				Some(result.map_err(|m| error(self, m))?)
+			}
+			ExprKind::Super => {
+				let (name, level) = self
+					.block
+					.ok_or_else(|| error(self, "super() can only be used in a block".into()))?;
+				let parent = (level + 1..self.chain.len())
+					.find(|l| self.template(self.chain[*l]).blocks.contains_key(name))
+					.ok_or_else(|| error(self, format!("`{}` isn't in a parent template", name)))?;
+				let mut out = String::new();
+				self.render_block(name, parent, &mut out)?;
+				Some(Value::String(out))
+			}
+		})
+	}
+	fn render_nodes(&mut self, nodes: &'t [Node], out: &mut String) -> Result<(), TemplateError> {
+		for node in nodes {
+			match node {
+				Node::Text(text) => out.push_str(text),
+				Node::Output(expr) => {
+					let value = self
+						.eval(expr)?
+						.ok_or_else(|| self.error(expr.position, "Undefined value".into()))?;
+					let s = to_string(&value);
+					if is_safe(expr) {
+						out.push_str(&s);
+					} else {
+						out.push_str(&Escape(&s).to_string());
+					}
+				}
+				Node::If(branches, otherwise) => {
+					let mut body = otherwise;
+					for (condition, b) in branches {
+						if truthy(&self.eval(condition)?) {
+							body = b;
+							break;
+						}
+					}
+					self.render_nodes(body, out)?;
+				}
+				Node::For {
+					key,
+					value,
+					iter,
+					body,
+				} => {
+					let items: Vec<(Option<Value>, Value)> = match self.eval(iter)? {
+						Some(Value::Array(a)) if key.is_none() => {
+							a.into_iter().map(|v| (None, v)).collect()
+						}
+						Some(Value::Table(t)) if key.is_some() => t
+							.into_iter()
+							.map(|(k, v)| (Some(Value::String(k)), v))
+							.collect(),
+						Some(v) => {
+							return Err(self.error(
+								iter.position,
+								format!(
+									"Can't loop over {} with that many variables",
+									v.type_name()
+								),
+							))
+						}
+						None => return Err(self.error(iter.position, "Undefined value".into())),
+					};
+					let length = items.len();
+					for (i, (k, v)) in items.into_iter().enumerate() {
+						let mut scope = Table::new();
+						if let (Some(key), Some(k)) = (key, k) {
+							scope.insert(key.clone(), k);
+						}
+						scope.insert(value.clone(), v);
+						let mut l = Table::new();
+						l.insert("index".into(), Value::Integer(i as i64 + 1));
+						l.insert("index0".into(), Value::Integer(i as i64));
+						l.insert("first".into(), Value::Boolean(i == 0));
+						l.insert("last".into(), Value::Boolean(i + 1 == length));
+						scope.insert("loop".into(), Value::Table(l));
+						self.scopes.push(scope);
+						let result = self.render_nodes(body, out);
+						self.scopes.pop();
+						result?;
+					}
+				}
+				Node::Set {
+					name,
+					value,
+					global,
+				} => {
+					let value = self
+						.eval(value)?
+						.ok_or_else(|| self.error(value.position, "Undefined value".into()))?;
+					let scope = if *global {
+						self.scopes.first_mut()
+					} else {
+						self.scopes.last_mut()
+					};
+					scope.unwrap().insert(name.clone(), value);
+				}
+				Node::Block(name) => {
+					// The most derived definition wins.
+					let level = (0..self.chain.len())
+						.find(|l| self.template(self.chain[*l]).blocks.contains_key(name))
+						.unwrap();
+					self.render_block(name, level, out)?;
+				}
+				Node::Include(name) => {
+					let (name, template) = self
+						.templates
+						.templates
+						.get_key_value(name)
+						.ok_or_else(|| {
+							self.error(
+								0,
+								format!("There's no template named {:?} to include", name),
+							)
+						})?;
+					let saved = (
+						std::mem::replace(&mut self.chain, vec![name]),
+						std::mem::replace(&mut self.current, name),
+						self.block.take(),
+					);
+					let result = self.render_nodes(&template.nodes, out);
+					self.chain = saved.0;
+					self.current = saved.1;
+					self.block = saved.2;
+					result?;
+				}
+			}
+		}
+		Ok(())
+	}
+}
+
+impl Templates {
+	pub fn add(&mut self, name: &str, source: &str) -> Result<(), TemplateError> {
+		let template =
+			parser::parse(source).map_err(|e| TemplateError::Parse(name.to_string(), e))?;
+		self.templates.insert(name.to_string(), template);
+		Ok(())
+	}
+	/// Load every .html file in a directory and its subdirectories.
+	pub fn load(dir: &Path) -> Result<Self, TemplateError> {
+		let mut templates = Self::default();
+		let mut dirs = vec![dir.to_path_buf()];
+		while let Some(d) = dirs.pop() {
+			let io_error = |e| TemplateError::Io(d.clone(), e);
+			for entry in fs::read_dir(&d).map_err(io_error)? {
+				let path = entry.map_err(io_error)?.path();
+				if path.is_dir() {
+					dirs.push(path);
+				} else if path.extension().contains(&"html") {
+					let source = fs::read_to_string(&path)
+						.map_err(|e| TemplateError::Io(path.clone(), e))?;
+					let name = path
+						.strip_prefix(dir)
+						.unwrap()
+						.components()
+						.map(|c| c.as_os_str().to_string_lossy())
+						.collect::<Vec<_>>()
+						.join("/");
+					templates.add(&name, &source)?;
+				}
+			}
+		}
+		Ok(templates)
+	}
+	pub fn register_function(
+		&mut self,
+		name: &str,
+		f: impl Fn(&Table) -> Result<Value, String> + Send + Sync + 'static,
+	) {
+		self.functions.insert(name.to_string(), Box::new(f));
+	}
+	pub fn render(&self, name: &str, context: &Table) -> Result<String, TemplateError> {
+		let missing = |name: &str, from: &str| TemplateError::Render {
+			template: from.to_string(),
+			line: 1,
+			col: 0,
+			source_line: String::new(),
+			message: format!("There's no template named {:?}", name),
+		};
+		// Follow extends up to the root template
+		let (mut current, mut template) = self
+			.templates
+			.get_key_value(name)
+			.ok_or_else(|| missing(name, name))?;
+		let mut chain = vec![current.as_str()];
+		while let Some(parent) = &template.parent {
+			let (n, t) = self
+				.templates
+				.get_key_value(parent)
+				.ok_or_else(|| missing(parent, current))?;
+			if chain.contains(&n.as_str()) {
+				return Err(TemplateError::Render {
+					template: name.to_string(),
+					line: 1,
+					col: 0,
+					source_line: String::new(),
+					message: format!("{} extends itself", n),
+				});
+			}
+			chain.push(n);
+			current = n;
+			template = t;
+		}
+
+		let mut renderer = Renderer {
+			templates: self,
+			context: Some(context),
+			scopes: vec![Table::new()],
+			current: chain.last().unwrap(),
+			chain,
+			block: None,
+		};
+		let mut out = String::new();
+		renderer.render_nodes(&template.nodes, &mut out)?;
+		Ok(out)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::date::Date;
+
+	fn templates(sources: &[(&str, &str)]) -> Templates {
+		let mut templates = Templates::default();
+		for (name, source) in sources {
+			templates.add(name, source).unwrap();
+		}
+		templates
+	}
+	fn table(pairs: Vec<(&str, Value)>) -> Table {
+		pairs.into_iter().map(|(k, v)| (k.to_string(), v)).collect()
+	}
+	fn string(s: &str) -> Value {
+		Value::String(s.to_string())
+	}
+
+	#[test]
+	fn test_syntax() {
+		let t = templates(&[(
+			"t",
+			r#"{# comment #}<h1>{{ title | upper }}</h1>
+{%- if items | length > 2 and not hidden %} many{% elif items %} some{% else %} none{% endif %}
+{% for i in items -%}
+	{{ loop.index }}:{{ i * 2 }}{% if not loop.last %}, {% endif %}
+{%- endfor %}
+{% set greeting = "Hi " ~ name -%}
+{{ greeting }} {{ html }} {{ html | safe }} {{ missing | default(value="d") }}
+{% for k, v in map %}{{ k }}={{ v }} {% endfor %}
+{{ 7 / 2 }} {{ 7 % 2 }} {{ -(1 + 2) }} {{ "b" in "abc" }} {{ 2 in items }} {{ range(end=3) | join(sep=",") }}"#,
+		)]);
+		let context = table(vec![
+			("title", string("Hello")),
+			(
+				"items",
+				Value::Array(vec![
+					Value::Integer(1),
+					Value::Integer(2),
+					Value::Integer(3),
+				]),
+			),
+			("name", string("Evan")),
+			("html", string("<b>&</b>")),
+			(
+				"map",
+				Value::Table(table(vec![
+					("a", Value::Integer(1)),
+					("b", Value::Boolean(true)),
+				])),
+			),
+		]);
+		assert_eq!(
+			t.render("t", &context).unwrap(),
+			"<h1>HELLO</h1> many
+1:2, 2:4, 3:6
+Hi Evan &lt;b&gt;&amp;&lt;/b&gt; <b>&</b> d
+a=1 b=true 
+3.5 1 -3 true true 0,1,2"
+		);
+	}
+
+	#[test]
+	fn test_inheritance() {
+		let t = templates(&[
+			(
+				"base.html",
+				"{% import \"macros.html\" as m %}<title>{% block title %}Site{% endblock title %}</title>\
+				{% block content %}{{ m::link(url=\"/\") }}{% endblock content %}",
+			),
+			(
+				"macros.html",
+				"{% macro link(url, text=\"Home\") %}<a href=\"{{ url }}\">{{ text }}</a>{% endmacro link %}",
+			),
+			(
+				"page.html",
+				"{% extends \"base.html\" %}{% import \"macros.html\" as macros %}\
+				{% block title %}{{ page.title }} | {{ super() }}{% endblock title %}\
+				{% block content %}{{ macros::link(url=page.url, text=page.title) }}{% endblock content %}",
+			),
+			("include.html", "[{% include \"macros.html\" %}{{ x }}]"),
+		]);
+		assert_eq!(
+			t.render("base.html", &Table::new()).unwrap(),
+			r#"<title>Site</title><a href="/">Home</a>"#
+		);
+		let page = table(vec![(
+			"page",
+			Value::Table(table(vec![
+				("title", string("A & B")),
+				("url", string("/a/")),
+			])),
+		)]);
+		assert_eq!(
+			t.render("page.html", &page).unwrap(),
+			r#"<title>A &amp; B | Site</title><a href="/a/">A &amp; B</a>"#
+		);
+		assert_eq!(
+			t.render("include.html", &table(vec![("x", Value::Integer(1))]))
+				.unwrap(),
+			"[1]"
+		);
+	}
+
+	#[test]
+	fn test_errors() {
+		for (source, line) in [
+			("{% if x %}", 1),
+			("{% for x %}{% endfor %}", 1),
+			("{{ x", 1),
+			("\n{% unknown %}", 2),
+			("{% block a %}{% endblock b %}", 1),
+			(
+				"{% block a %}{% endblock %}\n{% block a %}{% endblock %}",
+				2,
+			),
+		] {
+			let e = Templates::default().add("t", source).unwrap_err();
+			assert!(matches!(e, TemplateError::Parse(..)), "{}", source);
+			assert!(
+				e.to_string().contains(&format!("\n{}: ", line)),
+				"{}: {}",
+				source,
+				e
+			);
+		}
+		for (source, message) in [
+			("a\n{{ missing }}", "Undefined value"),
+			("{{ 1 | nope }}", "`nope` isn't a filter"),
+			("{{ nope() }}", "`nope` isn't a function"),
+			("{{ x::y() }}", "`x` hasn't been imported"),
+			("{% for x in 1 %}{% endfor %}", "Can't loop over an integer"),
+		] {
+			let t = templates(&[("t", source)]);
+			let e = t.render("t", &Table::new()).unwrap_err().to_string();
+			assert!(e.contains(message), "{}: {}", source, e);
+		}
+	}
+
+	// Whitespace runs become a single space, so that assertions don't depend on indentation.
+	fn collapse(html: String) -> String {
+		html.split_whitespace().collect::<Vec<_>>().join(" ")
+	}
+
+	// The templates directory should render with the contexts the site gives it.
+	#[test]
+	fn test_templates_directory() {
+		let mut t = Templates::load(Path::new("templates")).unwrap();
+		t.register_function("get_url", |args| match args.get("path") {
+			Some(Value::String(path)) => Ok(string(&format!(
+				"https://example.com/{}",
+				path.trim_start_matches('/')
+			))),
+			_ => Err("`path` is missing".into()),
+		});
+		t.register_function("get_taxonomy_url", |args| match args.get("name") {
+			Some(Value::String(name)) => Ok(string(&format!("https://example.com/tags/{}/", name))),
+			_ => Err("`name` is missing".into()),
+		});
+		let link = |name: &str, url: &str| {
+			Value::Table(table(vec![("name", string(name)), ("url", string(url))]))
+		};
+		let config = Value::Table(table(vec![
+			("base_url", string("https://example.com")),
+			("title", string("Example")),
+			("description", string("A description")),
+			("author", string("Someone")),
+			("rss", Value::Boolean(false)),
+			("nav", Value::Array(vec![link("Blog", "/blog/")])),
+			(
+				"social",
+				Value::Array(vec![
+					link("Twitter", "https://twitter.com/example"),
+					link("GitHub", "https://github.com/example"),
+				]),
+			),
+			("extra", Value::Table(Table::new())),
+		]));
+		let page = Value::Table(table(vec![
+			("title", string("A Post")),
+			("path", string("/blog/a-post/")),
+			("permalink", string("https://example.com/blog/a-post/")),
+			("date", Value::Datetime(Date::parse("2020-08-03").unwrap())),
+			("reading_time", Value::Integer(3)),
+			("summary", string("A <em>summary</em>")),
+			("content", string("<p>The content</p>")),
+			("draft", Value::Boolean(false)),
+			("toc", Value::Array(Vec::new())),
+			(
+				"taxonomies",
+				Value::Table(table(vec![(
+					"tags",
+					Value::Array(vec![string("JavaScript"), string("Rust")]),
+				)])),
+			),
+			("extra", Value::Table(Table::new())),
+		]));
+		let context = table(vec![
+			("config", config.clone()),
+			("lang", string("en")),
+			("page", page.clone()),
+		]);
+		let html = collapse(t.render("page.html", &context).unwrap());
+		assert!(
+			html.contains("<title> A Post | Example </title>"),
+			"{}",
+			html
+		);
+		assert!(html.contains(
+			r#"<time itemprop="datePublished" datetime="2020-08-03">August 3, 2020</time>"#
+		));
+		assert!(html.contains(r#"<a href="https://example.com/tags/JavaScript/">JavaScript</a>,"#));
+		assert!(html.contains(r#"<a href="https://example.com/tags/Rust/">Rust</a>"#));
+		assert!(html.contains("<p>The content</p>"));
+		assert!(html.contains(r#"<a href="/blog/">Blog</a>"#));
+		assert!(html.contains(r#"<a href="https://github.com/example">GitHub</a>"#));
+		assert!(!html.contains("noindex"));
+
+		let section = Value::Table(table(vec![
+			("title", string("Blog")),
+			("description", string("")),
+			("content", string("<p>Intro</p>")),
+			("pages", Value::Array(vec![page.clone()])),
+		]));
+		let context = table(vec![("config", config.clone()), ("section", section)]);
+		let html = collapse(t.render("section.html", &context).unwrap());
+		assert!(html.contains("<title> Blog | Example </title>"), "{}", html);
+		assert!(html.contains(r#"<meta name="description" content="A description">"#));
+		assert!(html.contains("<p>Intro</p>"));
+		assert!(html.contains(
+			r#"<a itemprop="mainEntityOfPage" href="https://example.com/blog/a-post/">A Post</a>"#
+		));
+		assert!(html.contains("A summary"));
+
+		let term = Value::Table(table(vec![
+			("name", string("Rust")),
+			("permalink", string("/tags/rust/")),
+			("pages", Value::Array(vec![page])),
+		]));
+		let context = table(vec![
+			("config", config.clone()),
+			(
+				"taxonomy",
+				Value::Table(table(vec![("name", string("tags"))])),
+			),
+			("terms", Value::Array(vec![term.clone()])),
+			("term", term),
+		]);
+		let html = collapse(t.render("tags/list.html", &context).unwrap());
+		assert!(
+			html.contains(r#"<a href="/tags/rust/">Rust</a>"#),
+			"{}",
+			html
+		);
+		let html = collapse(t.render("tags/single.html", &context).unwrap());
+		assert!(html.contains("<h1>Rust</h1>"), "{}", html);
+		assert!(html.contains("A Post"));
+
+		let html = collapse(
+			t.render("404.html", &table(vec![("config", config)]))
+				.unwrap(),
+		);
+		assert!(html.contains("404: Not Found"));
+	}
+}
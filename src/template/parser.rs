@@ -0,0 +1,596 @@
+use crate::parser2::{Input, ParseResult};
+use crate::toml::Value;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+	Or,
+	And,
+	Eq,
+	Ne,
+	Lt,
+	Gt,
+	Le,
+	Ge,
+	In,
+	Concat,
+	Add,
+	Sub,
+	Mul,
+	Div,
+	Rem,
+}
+
+pub type Args = Vec<(String, Expr)>;
+
+#[derive(Debug)]
+pub struct Expr {
+	pub kind: ExprKind,
+	// Byte offset into the template, for errors
+	pub position: usize,
+}
+#[derive(Debug)]
+pub enum ExprKind {
+	Literal(Value),
+	Array(Vec<Expr>),
+	Var(String),
+	Attr(Box<Expr>, String),
+	Index(Box<Expr>, Box<Expr>),
+	Not(Box<Expr>),
+	Neg(Box<Expr>),
+	Binary(Op, Box<Expr>, Box<Expr>),
+	Filter(Box<Expr>, String, Args),
+	// A function, or a macro if it has a namespace: snippets::article_preview(page=page)
+	Call(Option<String>, String, Args),
+	Super,
+}
+
+#[derive(Debug)]
+pub enum Node {
+	Text(String),
+	Output(Expr),
+	// Each condition with its body, and then the else body
+	If(Vec<(Expr, Vec<Node>)>, Vec<Node>),
+	For {
+		key: Option<String>,
+		value: String,
+		iter: Expr,
+		body: Vec<Node>,
+	},
+	Set {
+		name: String,
+		value: Expr,
+		global: bool,
+	},
+	// Renders the most derived definition of the block
+	Block(String),
+	Include(String),
+}
+
+#[derive(Debug)]
+pub struct Macro {
+	pub args: Vec<(String, Option<Expr>)>,
+	pub body: Vec<Node>,
+}
+
+#[derive(Debug, Default)]
+pub struct Template {
+	pub source: String,
+	pub parent: Option<String>,
+	// Namespace -> template name
+	pub imports: HashMap<String, String>,
+	pub macros: HashMap<String, Macro>,
+	pub blocks: HashMap<String, Vec<Node>>,
+	pub nodes: Vec<Node>,
+}
+
+fn is_ident(c: char) -> bool {
+	c.is_alphanumeric() || c == '_'
+}
+
+struct TemplateParser<'i> {
+	input: Input<'i>,
+	template: Template,
+	// Set by -%} and -}} to trim the whitespace at the start of the next text
+	trim_next: bool,
+}
+impl<'i> TemplateParser<'i> {
+	fn ws(&mut self) {
+		let _ = self.input.expect_pattern(char::is_whitespace);
+	}
+	fn ident(&mut self) -> ParseResult<String> {
+		if self.input.peek().starts_with(|c: char| c.is_ascii_digit()) {
+			return Err(self.input.error("An identifier"));
+		}
+		self.input
+			.expect_pattern(is_ident)
+			.map(str::to_string)
+			.map_err(|_| self.input.error("An identifier"))
+	}
+	// Like expect_literal, but the keyword can't be the start of a longer identifier.
+	fn keyword(&mut self, keyword: &'static str) -> bool {
+		let rest = self.input.peek();
+		if rest.starts_with(keyword) && !rest[keyword.len()..].starts_with(is_ident) {
+			let _ = self.input.expect_literal(keyword);
+			true
+		} else {
+			false
+		}
+	}
+	// Operators that aren't the start of a closing -%} or -}}
+	fn op(&mut self, op: &'static str) -> bool {
+		let rest = self.input.peek();
+		if rest.starts_with(op)
+			&& !rest[op.len()..].starts_with('}')
+			&& !(op == "-" && rest[1..].starts_with("%}"))
+		{
+			let _ = self.input.expect_literal(op);
+			self.ws();
+			true
+		} else {
+			false
+		}
+	}
+	fn string(&mut self) -> ParseResult<String> {
+		let quote = match self.input.peek().chars().next() {
+			Some(c @ ('"' | '\'' | '`')) => c,
+			_ => return Err(self.input.error("A string")),
+		};
+		let _ = self.input.expect_len(1);
+		let s = self.input.expect_antipattern(quote)?.to_string();
+		self.input
+			.expect_literal(match quote {
+				'"' => "\"",
+				'\'' => "'",
+				_ => "`",
+			})
+			.map_err(|_| self.input.error("A closing quote"))?;
+		Ok(s)
+	}
+
+	fn args(&mut self) -> ParseResult<Args> {
+		// Arguments are always named: (path="x", trailing_slash=false)
+		self.input.expect_literal("(")?;
+		let mut args = Vec::new();
+		loop {
+			self.ws();
+			if self.input.expect_literal(")").is_ok() {
+				break Ok(args);
+			}
+			let name = self.ident()?;
+			self.ws();
+			self.input.expect_literal("=")?;
+			self.ws();
+			args.push((name, self.expr()?));
+			self.ws();
+			if self.input.expect_literal(",").is_err() {
+				self.input
+					.expect_literal(")")
+					.map_err(|_| self.input.error("A ',' or ')'"))?;
+				break Ok(args);
+			}
+		}
+	}
+	fn primary(&mut self) -> ParseResult<Expr> {
+		let position = self.input.position();
+		let rest = self.input.peek();
+		let kind = if rest.starts_with(|c| c == '"' || c == '\'' || c == '`') {
+			ExprKind::Literal(Value::String(self.string()?))
+		} else if rest.starts_with(|c: char| c.is_ascii_digit()) {
+			let number = self
+				.input
+				.expect_pattern(|c: char| c.is_ascii_digit() || c == '.')?;
+			ExprKind::Literal(if number.contains('.') {
+				Value::Float(
+					number
+						.parse()
+						.map_err(|_| self.input.error_at(position, "A number"))?,
+				)
+			} else {
+				Value::Integer(
+					number
+						.parse()
+						.map_err(|_| self.input.error_at(position, "A number"))?,
+				)
+			})
+		} else if self.input.expect_literal("(").is_ok() {
+			self.ws();
+			let e = self.expr()?;
+			self.ws();
+			self.input.expect_literal(")")?;
+			return Ok(e);
+		} else if self.input.expect_literal("[").is_ok() {
+			let mut items = Vec::new();
+			loop {
+				self.ws();
+				if self.input.expect_literal("]").is_ok() {
+					break;
+				}
+				items.push(self.expr()?);
+				self.ws();
+				if self.input.expect_literal(",").is_err() {
+					self.input
+						.expect_literal("]")
+						.map_err(|_| self.input.error("A ',' or ']'"))?;
+					break;
+				}
+			}
+			ExprKind::Array(items)
+		} else {
+			let name = self.ident()?;
+			match name.as_str() {
+				"true" | "True" => ExprKind::Literal(Value::Boolean(true)),
+				"false" | "False" => ExprKind::Literal(Value::Boolean(false)),
+				"super" => {
+					self.input.expect_literal("()")?;
+					ExprKind::Super
+				}
+				_ if self.input.expect_literal("::").is_ok() => {
+					let function = self.ident()?;
+					ExprKind::Call(Some(name), function, self.args()?)
+				}
+				_ if self.input.peek().starts_with('(') => ExprKind::Call(None, name, self.args()?),
+				_ => ExprKind::Var(name),
+			}
+		};
+		Ok(Expr { kind, position })
+	}
+	fn postfix(&mut self) -> ParseResult<Expr> {
+		let mut e = self.primary()?;
+		loop {
+			let position = self.input.position();
+			let kind = if self.input.expect_literal(".").is_ok() {
+				ExprKind::Attr(Box::new(e), self.ident()?)
+			} else if self.input.expect_literal("[").is_ok() {
+				self.ws();
+				let index = self.expr()?;
+				self.ws();
+				self.input.expect_literal("]")?;
+				ExprKind::Index(Box::new(e), Box::new(index))
+			} else {
+				break Ok(e);
+			};
+			e = Expr { kind, position };
+		}
+	}
+	fn unary(&mut self) -> ParseResult<Expr> {
+		let position = self.input.position();
+		if self.op("-") {
+			let e = self.unary()?;
+			return Ok(Expr {
+				kind: ExprKind::Neg(Box::new(e)),
+				position,
+			});
+		}
+		self.postfix()
+	}
+	// Parse a left associative chain of binary operators
+	fn binary(
+		&mut self,
+		ops: &[(&'static str, Op)],
+		next: fn(&mut Self) -> ParseResult<Expr>,
+	) -> ParseResult<Expr> {
+		let mut left = next(self)?;
+		'chain: loop {
+			self.ws();
+			let position = self.input.position();
+			for (s, op) in ops {
+				let matched = if s.starts_with(is_ident) {
+					self.keyword(*s)
+				} else {
+					self.op(*s)
+				};
+				if matched {
+					self.ws();
+					let right = next(self)?;
+					left = Expr {
+						kind: ExprKind::Binary(*op, Box::new(left), Box::new(right)),
+						position,
+					};
+					continue 'chain;
+				}
+			}
+			break Ok(left);
+		}
+	}
+	fn multiplicative(&mut self) -> ParseResult<Expr> {
+		self.binary(
+			&[("*", Op::Mul), ("/", Op::Div), ("%", Op::Rem)],
+			Self::unary,
+		)
+	}
+	fn additive(&mut self) -> ParseResult<Expr> {
+		self.binary(&[("+", Op::Add), ("-", Op::Sub)], Self::multiplicative)
+	}
+	fn filtered(&mut self) -> ParseResult<Expr> {
+		let mut e = self.additive()?;
+		loop {
+			self.ws();
+			let position = self.input.position();
+			if !self.op("|") {
+				break Ok(e);
+			}
+			let name = self.ident()?;
+			let args = if self.input.peek().starts_with('(') {
+				self.args()?
+			} else {
+				Vec::new()
+			};
+			e = Expr {
+				kind: ExprKind::Filter(Box::new(e), name, args),
+				position,
+			};
+		}
+	}
+	fn concat(&mut self) -> ParseResult<Expr> {
+		self.binary(&[("~", Op::Concat)], Self::filtered)
+	}
+	fn comparison(&mut self) -> ParseResult<Expr> {
+		self.binary(
+			&[
+				("==", Op::Eq),
+				("!=", Op::Ne),
+				("<=", Op::Le),
+				(">=", Op::Ge),
+				("<", Op::Lt),
+				(">", Op::Gt),
+				("in", Op::In),
+			],
+			Self::concat,
+		)
+	}
+	fn not(&mut self) -> ParseResult<Expr> {
+		let position = self.input.position();
+		if self.keyword("not") {
+			self.ws();
+			let e = self.not()?;
+			return Ok(Expr {
+				kind: ExprKind::Not(Box::new(e)),
+				position,
+			});
+		}
+		self.comparison()
+	}
+	fn and(&mut self) -> ParseResult<Expr> {
+		self.binary(&[("and", Op::And)], Self::not)
+	}
+	fn expr(&mut self) -> ParseResult<Expr> {
+		self.binary(&[("or", Op::Or)], Self::and)
+	}
+
+	// The end of a {% tag %} or {{ output }}
+	fn close(&mut self, close: &'static str) -> ParseResult<()> {
+		self.ws();
+		let trimmed = if close == "%}" { "-%}" } else { "-}}" };
+		if self.input.expect_literal(trimmed).is_ok() {
+			self.trim_next = true;
+			Ok(())
+		} else {
+			self.input.expect_literal(close).map(|_| ())
+		}
+	}
+	// An optional name after endblock or endmacro, which has to match.
+	fn end_name(&mut self, name: &str) -> ParseResult<()> {
+		self.ws();
+		if self.input.peek().starts_with(is_ident) {
+			let position = self.input.position();
+			if self.ident()? != name {
+				return Err(self.input.error_at(position, "The name that was opened"));
+			}
+		}
+		self.close("%}")
+	}
+	// Parse nodes until one of the end tags, returning which one it was. The rest of the end tag is left for the caller.
+	fn nodes(
+		&mut self,
+		ends: &[&'static str],
+		expected: &'static str,
+	) -> ParseResult<(Vec<Node>, &'static str)> {
+		let mut nodes = Vec::new();
+		loop {
+			let rest = self.input.peek();
+			let end = ["{{", "{%", "{#"]
+				.iter()
+				.filter_map(|t| rest.find(t))
+				.min()
+				.unwrap_or(rest.len());
+			if end > 0 {
+				let mut text = self.input.expect_len(end)?;
+				if self.trim_next {
+					text = text.trim_start();
+				}
+				if !text.is_empty() {
+					nodes.push(Node::Text(text.to_string()));
+				}
+			}
+			self.trim_next = false;
+			if self.input.peek().is_empty() {
+				if ends.is_empty() {
+					return Ok((nodes, ""));
+				}
+				return Err(self.input.error(expected));
+			}
+			// {%- {{- and {#- trim the whitespace before them
+			if self.input.peek()[2..].starts_with('-') {
+				if let Some(Node::Text(text)) = nodes.last_mut() {
+					text.truncate(text.trim_end().len());
+					if text.is_empty() {
+						nodes.pop();
+					}
+				}
+			}
+			if self.input.expect_literal("{#").is_ok() {
+				let comment = self.input.expect_antipattern("#}")?;
+				self.trim_next = comment.ends_with('-');
+				self.input.expect_literal("#}")?;
+				continue;
+			}
+			if self.input.expect_literal("{{").is_ok() {
+				let _ = self.input.expect_literal("-");
+				self.ws();
+				let e = self.expr()?;
+				self.close("}}")?;
+				nodes.push(Node::Output(e));
+				continue;
+			}
+			self.input.expect_literal("{%")?;
+			let _ = self.input.expect_literal("-");
+			self.ws();
+			let position = self.input.position();
+			let tag = self.ident()?;
+			if let Some(end) = ends.iter().find(|e| **e == tag) {
+				return Ok((nodes, end));
+			}
+			self.ws();
+			match tag.as_str() {
+				"if" => {
+					let mut branches = Vec::new();
+					let mut condition = self.expr()?;
+					self.close("%}")?;
+					let otherwise = loop {
+						let (body, end) = self.nodes(&["elif", "else", "endif"], "{% endif %}")?;
+						branches.push((condition, body));
+						self.ws();
+						match end {
+							"elif" => {
+								condition = self.expr()?;
+								self.close("%}")?;
+							}
+							"else" => {
+								self.close("%}")?;
+								let (body, _) = self.nodes(&["endif"], "{% endif %}")?;
+								self.close("%}")?;
+								break body;
+							}
+							_ => {
+								self.close("%}")?;
+								break Vec::new();
+							}
+						}
+					};
+					nodes.push(Node::If(branches, otherwise));
+				}
+				"for" => {
+					let mut value = self.ident()?;
+					self.ws();
+					let mut key = None;
+					if self.input.expect_literal(",").is_ok() {
+						self.ws();
+						key = Some(value);
+						value = self.ident()?;
+						self.ws();
+					}
+					if !self.keyword("in") {
+						return Err(self.input.error("in"));
+					}
+					self.ws();
+					let iter = self.expr()?;
+					self.close("%}")?;
+					let (body, _) = self.nodes(&["endfor"], "{% endfor %}")?;
+					self.close("%}")?;
+					nodes.push(Node::For {
+						key,
+						value,
+						iter,
+						body,
+					});
+				}
+				"set" | "set_global" => {
+					let name = self.ident()?;
+					self.ws();
+					self.input.expect_literal("=")?;
+					self.ws();
+					let value = self.expr()?;
+					self.close("%}")?;
+					nodes.push(Node::Set {
+						name,
+						value,
+						global: tag == "set_global",
+					});
+				}
+				"block" => {
+					let name = self.ident()?;
+					self.close("%}")?;
+					let (body, _) = self.nodes(&["endblock"], "{% endblock %}")?;
+					self.end_name(&name)?;
+					if self.template.blocks.contains_key(&name) {
+						return Err(self
+							.input
+							.error_at(position, "A block name that isn't already used"));
+					}
+					self.template.blocks.insert(name.clone(), body);
+					nodes.push(Node::Block(name));
+				}
+				"extends" => {
+					self.template.parent = Some(self.string()?);
+					self.close("%}")?;
+				}
+				"import" => {
+					let template = self.string()?;
+					self.ws();
+					if !self.keyword("as") {
+						return Err(self.input.error("as"));
+					}
+					self.ws();
+					let namespace = self.ident()?;
+					self.close("%}")?;
+					self.template.imports.insert(namespace, template);
+				}
+				"include" => {
+					nodes.push(Node::Include(self.string()?));
+					self.close("%}")?;
+				}
+				"macro" => {
+					let name = self.ident()?;
+					self.input.expect_literal("(")?;
+					let mut args = Vec::new();
+					loop {
+						self.ws();
+						if self.input.expect_literal(")").is_ok() {
+							break;
+						}
+						let arg = self.ident()?;
+						self.ws();
+						let default = if self.input.expect_literal("=").is_ok() {
+							self.ws();
+							Some(self.expr()?)
+						} else {
+							None
+						};
+						args.push((arg, default));
+						self.ws();
+						if self.input.expect_literal(",").is_err() {
+							self.input
+								.expect_literal(")")
+								.map_err(|_| self.input.error("A ',' or ')'"))?;
+							break;
+						}
+					}
+					self.close("%}")?;
+					let (body, _) = self.nodes(&["endmacro"], "{% endmacro %}")?;
+					self.end_name(&name)?;
+					self.template.macros.insert(name, Macro { args, body });
+				}
+				_ => {
+					return Err(self.input.error_at(
+						position,
+						"A tag (if, for, set, block, extends, import, include or macro)",
+					))
+				}
+			}
+		}
+	}
+}
+
+pub fn parse(source: &str) -> ParseResult<Template> {
+	let mut parser = TemplateParser {
+		input: Input::from(source),
+		template: Template::default(),
+		trim_next: false,
+	};
+	let (nodes, _) = parser.nodes(&[], "")?;
+	let mut template = parser.template;
+	template.nodes = nodes;
+	template.source = source.to_string();
+	Ok(template)
+}
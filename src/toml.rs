@@ -0,0 +1,586 @@
+// Just enough TOML for config.toml: every value type, tables, arrays of tables and inline tables.
+use super::date::Date;
+use super::parser2::{Input, ParseResult};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+	String(String),
+	Integer(i64),
+	Float(f64),
+	Boolean(bool),
+	Datetime(Date),
+	Array(Vec<Value>),
+	Table(Table),
+}
+pub type Table = BTreeMap<String, Value>;
+
+impl Value {
+	pub fn type_name(&self) -> &'static str {
+		match self {
+			Value::String(_) => "a string",
+			Value::Integer(_) => "an integer",
+			Value::Float(_) => "a float",
+			Value::Boolean(_) => "a boolean",
+			Value::Datetime(_) => "a date",
+			Value::Array(_) => "an array",
+			Value::Table(_) => "a table",
+		}
+	}
+}
+
+// Spaces and tabs
+fn ws(input: &mut Input) {
+	let _ = input.expect_pattern(|c| c == ' ' || c == '\t');
+}
+fn comment(input: &mut Input) {
+	if input.peek().starts_with('#') {
+		let _ = input.expect_pattern(|c| c != '\r' && c != '\n');
+	}
+}
+// Whitespace, comments and newlines: between lines and inside of arrays.
+fn ws_newlines(input: &mut Input) {
+	loop {
+		ws(input);
+		comment(input);
+		if input.peek().is_empty() || input.expect_lineend().is_err() {
+			break;
+		}
+	}
+}
+
+fn is_bare_key(c: char) -> bool {
+	c.is_ascii_alphanumeric() || c == '_' || c == '-'
+}
+fn parse_key(input: &mut Input) -> ParseResult<Vec<String>> {
+	let mut keys = Vec::new();
+	loop {
+		ws(input);
+		let key = if input.peek().starts_with('"') {
+			parse_basic_string(input)?
+		} else if input.peek().starts_with('\'') {
+			parse_literal_string(input)?
+		} else {
+			input
+				.expect_pattern(is_bare_key)
+				.map_err(|_| input.error("A key"))?
+				.to_string()
+		};
+		keys.push(key);
+		ws(input);
+		if input.expect_literal(".").is_err() {
+			break Ok(keys);
+		}
+	}
+}
+
+fn parse_escape(input: &mut Input) -> ParseResult<char> {
+	let position = input.position();
+	input.expect_literal("\\")?;
+	const ESCAPE: &str = r#"An escape sequence (\b, \t, \n, \f, \r, \", \\, \uXXXX or \UXXXXXXXX)"#;
+	let c = input.peek().chars().next().ok_or(input.error(ESCAPE))?;
+	let len = match c {
+		'b' | 't' | 'n' | 'f' | 'r' | '"' | '\\' => {
+			input.expect_len(1)?;
+			return Ok(match c {
+				'b' => '\u{8}',
+				't' => '\t',
+				'n' => '\n',
+				'f' => '\u{c}',
+				'r' => '\r',
+				c => c,
+			});
+		}
+		'u' => 4,
+		'U' => 8,
+		_ => return Err(input.error(ESCAPE)),
+	};
+	input.expect_len(1)?;
+	let digits = input
+		.peek()
+		.get(..len)
+		.filter(|d| d.bytes().all(|b| b.is_ascii_hexdigit()))
+		.ok_or(input.error("Hex digits"))?;
+	let c = u32::from_str_radix(digits, 16)
+		.ok()
+		.and_then(char::from_u32)
+		.ok_or(input.error_at(position, "A unicode scalar value"))?;
+	input.expect_len(len)?;
+	Ok(c)
+}
+// Control characters other than tab aren't allowed in strings or comments.
+fn check_char(input: &Input, c: char) -> ParseResult<()> {
+	if c.is_control() && c != '\t' {
+		Err(input.error("A closing quote"))
+	} else {
+		Ok(())
+	}
+}
+// Multi-line strings can end with up to two extra quotes: """a""""" is a"".
+fn parse_closing(input: &mut Input, s: &mut String, quote: char) -> ParseResult<bool> {
+	let quotes = input.peek().chars().take_while(|c| *c == quote).count();
+	if quotes < 3 {
+		return Ok(false);
+	}
+	let extra = (quotes - 3).min(2);
+	s.extend(std::iter::repeat(quote).take(extra));
+	input.expect_len(3 + extra)?;
+	Ok(true)
+}
+fn skip_first_newline(input: &mut Input) {
+	if input.peek().starts_with('\n') || input.peek().starts_with("\r\n") {
+		let _ = input.expect_lineend();
+	}
+}
+fn parse_basic_string(input: &mut Input) -> ParseResult<String> {
+	let multiline = input.peek().starts_with(r#"""""#);
+	input.expect_literal(if multiline { r#"""""# } else { "\"" })?;
+	if multiline {
+		skip_first_newline(input);
+	}
+	let mut s = String::new();
+	loop {
+		let rest = input.peek();
+		let c = rest.chars().next().ok_or(input.error("A closing quote"))?;
+		match c {
+			'"' if multiline => {
+				if parse_closing(input, &mut s, '"')? {
+					break Ok(s);
+				}
+			}
+			'"' => {
+				input.expect_len(1)?;
+				break Ok(s);
+			}
+			// A backslash at the end of a line trims all the whitespace after it.
+			'\\' if multiline && {
+				let after = rest[1..].trim_start_matches(|c| c == ' ' || c == '\t');
+				after.starts_with('\n') || after.starts_with("\r\n")
+			} =>
+			{
+				input.expect_len(1)?;
+				input.expect_pattern(char::is_whitespace)?;
+				continue;
+			}
+			'\\' => {
+				s.push(parse_escape(input)?);
+				continue;
+			}
+			'\n' | '\r' if multiline => {}
+			c => check_char(input, c)?,
+		}
+		s.push(c);
+		input.expect_len(c.len_utf8())?;
+	}
+}
+fn parse_literal_string(input: &mut Input) -> ParseResult<String> {
+	let multiline = input.peek().starts_with("'''");
+	input.expect_literal(if multiline { "'''" } else { "'" })?;
+	if multiline {
+		skip_first_newline(input);
+	}
+	let mut s = String::new();
+	loop {
+		let c = input
+			.peek()
+			.chars()
+			.next()
+			.ok_or(input.error("A closing quote"))?;
+		match c {
+			'\'' if multiline => {
+				if parse_closing(input, &mut s, '\'')? {
+					break Ok(s);
+				}
+			}
+			'\'' => {
+				input.expect_len(1)?;
+				break Ok(s);
+			}
+			'\n' | '\r' if multiline => {}
+			c => check_char(input, c)?,
+		}
+		s.push(c);
+		input.expect_len(c.len_utf8())?;
+	}
+}
+
+fn parse_number(s: &str) -> Option<Value> {
+	// Underscores are only allowed between digits.
+	let bytes = s.as_bytes();
+	for (i, b) in bytes.iter().enumerate() {
+		if *b == b'_'
+			&& !(i > 0
+				&& bytes[i - 1].is_ascii_alphanumeric()
+				&& bytes.get(i + 1).map_or(false, u8::is_ascii_alphanumeric))
+		{
+			return None;
+		}
+	}
+	let digits = s.replace('_', "");
+	let (sign, unsigned) = match digits.strip_prefix('-') {
+		Some(rest) => (-1.0, rest),
+		None => (1.0, digits.strip_prefix('+').unwrap_or(&digits)),
+	};
+	match unsigned {
+		"inf" => return Some(Value::Float(sign * f64::INFINITY)),
+		"nan" => return Some(Value::Float(f64::NAN)),
+		_ => {}
+	}
+	for (prefix, radix) in [("0x", 16), ("0o", 8), ("0b", 2)] {
+		if let Some(n) = digits.strip_prefix(prefix) {
+			return i64::from_str_radix(n, radix).ok().map(Value::Integer);
+		}
+	}
+	// No leading zeros, and a '.' needs digits on both sides.
+	let whole = unsigned
+		.split(|c| c == '.' || c == 'e' || c == 'E')
+		.next()?;
+	if whole.is_empty()
+		|| !whole.bytes().all(|b| b.is_ascii_digit())
+		|| (whole.len() > 1 && whole.starts_with('0'))
+		|| unsigned.ends_with('.')
+		|| unsigned.contains(".e")
+		|| unsigned.contains(".E")
+	{
+		return None;
+	}
+	if unsigned.contains(|c| c == '.' || c == 'e' || c == 'E') {
+		digits.parse().ok().map(Value::Float)
+	} else {
+		digits.parse().ok().map(Value::Integer)
+	}
+}
+
+fn parse_array(input: &mut Input) -> ParseResult<Value> {
+	input.expect_literal("[")?;
+	let mut values = Vec::new();
+	loop {
+		ws_newlines(input);
+		if input.peek().starts_with(']') {
+			break;
+		}
+		values.push(parse_value(input)?);
+		ws_newlines(input);
+		if input.expect_literal(",").is_err() {
+			break;
+		}
+	}
+	input
+		.expect_literal("]")
+		.map_err(|_| input.error("A ',' or ']'"))?;
+	Ok(Value::Array(values))
+}
+fn parse_inline_table(input: &mut Input) -> ParseResult<Value> {
+	input.expect_literal("{")?;
+	let mut table = Table::new();
+	ws(input);
+	if input.expect_literal("}").is_ok() {
+		return Ok(Value::Table(table));
+	}
+	loop {
+		parse_key_value(input, &mut table)?;
+		ws(input);
+		if input.expect_literal(",").is_err() {
+			break;
+		}
+	}
+	input
+		.expect_literal("}")
+		.map_err(|_| input.error("A ',' or '}'"))?;
+	Ok(Value::Table(table))
+}
+
+fn parse_value(input: &mut Input) -> ParseResult<Value> {
+	let rest = input.peek();
+	if rest.starts_with('"') {
+		parse_basic_string(input).map(Value::String)
+	} else if rest.starts_with('\'') {
+		parse_literal_string(input).map(Value::String)
+	} else if rest.starts_with('[') {
+		parse_array(input)
+	} else if rest.starts_with('{') {
+		parse_inline_table(input)
+	} else {
+		let position = input.position();
+		const VALUE: &str = "A value (string, number, boolean, date, array or table)";
+		let token = input
+			.expect_pattern(|c: char| c.is_ascii_alphanumeric() || "+-_.:".contains(c))
+			.map_err(|_| input.error(VALUE))?;
+		match token {
+			"true" => return Ok(Value::Boolean(true)),
+			"false" => return Ok(Value::Boolean(false)),
+			_ => {}
+		}
+		if token.len() >= 10 && token.as_bytes()[4] == b'-' {
+			let mut date = token.to_string();
+			// The time can be separated from the date by a space.
+			let rest = input.peek();
+			if token.len() == 10
+				&& rest.starts_with(' ')
+				&& rest[1..].starts_with(|c: char| c.is_ascii_digit())
+			{
+				input.expect_len(1)?;
+				date.push('T');
+				date.push_str(
+					input.expect_pattern(|c: char| {
+						c.is_ascii_alphanumeric() || "+-.:".contains(c)
+					})?,
+				);
+			}
+			return Date::parse(&date)
+				.map(Value::Datetime)
+				.map_err(|e| input.error_at(position, e));
+		}
+		parse_number(token).ok_or(input.error_at(position, VALUE))
+	}
+}
+
+// Insert a value at a dotted key, creating the tables along the way.
+fn insert(table: &mut Table, keys: &[String], value: Value) -> Result<(), &'static str> {
+	let (last, keys) = keys.split_last().unwrap();
+	let table = table_at(table, keys)?;
+	if table.contains_key(last) {
+		return Err("A key that isn't already defined");
+	}
+	table.insert(last.clone(), value);
+	Ok(())
+}
+// The table at a path, where an array of tables means its last table.
+fn table_at<'t>(mut table: &'t mut Table, keys: &[String]) -> Result<&'t mut Table, &'static str> {
+	for key in keys {
+		let value = table
+			.entry(key.clone())
+			.or_insert_with(|| Value::Table(Table::new()));
+		table = match value {
+			Value::Table(t) => t,
+			Value::Array(a) if matches!(a.last(), Some(Value::Table(_))) => match a.last_mut() {
+				Some(Value::Table(t)) => t,
+				_ => unreachable!(),
+			},
+			_ => return Err("A key that isn't already a value"),
+		};
+	}
+	Ok(table)
+}
+
+fn parse_key_value(input: &mut Input, table: &mut Table) -> ParseResult<()> {
+	let position = input.position();
+	let keys = parse_key(input)?;
+	input.expect_literal("=")?;
+	ws(input);
+	let value = parse_value(input)?;
+	insert(table, &keys, value).map_err(|e| input.error_at(position, e))
+}
+
+pub fn parse(input: &mut Input) -> ParseResult<Table> {
+	let mut root = Table::new();
+	// The keys of the current [table] and of every table that's been defined by a header.
+	let mut current = Vec::new();
+	let mut defined = Vec::new();
+	loop {
+		ws_newlines(input);
+		if input.peek().is_empty() {
+			break Ok(root);
+		}
+		let position = input.position();
+		if input.expect_literal("[[").is_ok() {
+			let keys = parse_key(input)?;
+			input.expect_literal("]]")?;
+			let (last, parents) = keys.split_last().unwrap();
+			let parent = table_at(&mut root, parents).map_err(|e| input.error_at(position, e))?;
+			let array = parent
+				.entry(last.clone())
+				.or_insert_with(|| Value::Array(Vec::new()));
+			match array {
+				Value::Array(a) if a.iter().all(|v| matches!(v, Value::Table(_))) => {
+					a.push(Value::Table(Table::new()))
+				}
+				_ => return Err(input.error_at(position, "A key that isn't already a value")),
+			}
+			// Tables inside of each element can be defined again.
+			defined.retain(|d: &Vec<String>| !d.starts_with(&keys));
+			current = keys;
+		} else if input.expect_literal("[").is_ok() {
+			let keys = parse_key(input)?;
+			input.expect_literal("]")?;
+			if defined.contains(&keys) {
+				return Err(input.error_at(position, "A table that isn't already defined"));
+			}
+			table_at(&mut root, &keys).map_err(|e| input.error_at(position, e))?;
+			defined.push(keys.clone());
+			current = keys;
+		} else {
+			let table = table_at(&mut root, &current).unwrap();
+			parse_key_value(input, table)?;
+		}
+		ws(input);
+		comment(input);
+		input
+			.expect_lineend()
+			.map_err(|_| input.error("A newline"))?;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn parse_str(s: &str) -> ParseResult<Table> {
+		parse(&mut Input::from(s))
+	}
+	fn string(s: &str) -> Value {
+		Value::String(s.to_string())
+	}
+
+	#[test]
+	fn test_values() {
+		let table = parse_str(
+			r#"
+# A comment
+title = "Evan Brass" # Another comment
+"quoted key" = 'C:\path'
+escapes = "tab\t quote\" \u00e9 \U0001F600"
+multi = """
+first \
+	second""\""""
+literal = '''
+no \escapes'''
+numbers = [1_000, -17, +3, 0x1F, 0o17, 0b101, 3.5, -2e3, 6.02E23, inf, -inf]
+dates = [2020-08-03, 2020-03-27T20:18:42Z, 2018-07-17 19:31:29-07:00]
+booleans = [true, false]
+nested = [ [1, 2], ["a"], ]
+"#,
+		)
+		.unwrap();
+		assert_eq!(table["title"], string("Evan Brass"));
+		assert_eq!(table["quoted key"], string("C:\\path"));
+		assert_eq!(table["escapes"], string("tab\t quote\" \u{e9} \u{1F600}"));
+		assert_eq!(table["multi"], string("first second\"\"\""));
+		assert_eq!(table["literal"], string("no \\escapes"));
+		let numbers: Vec<_> = [1000, -17, 3, 31, 15, 5]
+			.into_iter()
+			.map(Value::Integer)
+			.chain(
+				[3.5, -2000.0, 6.02e23, f64::INFINITY, f64::NEG_INFINITY]
+					.into_iter()
+					.map(Value::Float),
+			)
+			.collect();
+		assert_eq!(table["numbers"], Value::Array(numbers));
+		let dates: Vec<_> = [
+			"2020-08-03",
+			"2020-03-27T20:18:42Z",
+			"2018-07-17T19:31:29-07:00",
+		]
+		.into_iter()
+		.map(|d| Value::Datetime(Date::parse(d).unwrap()))
+		.collect();
+		assert_eq!(table["dates"], Value::Array(dates));
+		assert_eq!(
+			table["booleans"],
+			Value::Array(vec![Value::Boolean(true), Value::Boolean(false)])
+		);
+		assert_eq!(
+			table["nested"],
+			Value::Array(vec![
+				Value::Array(vec![Value::Integer(1), Value::Integer(2)]),
+				Value::Array(vec![string("a")]),
+			])
+		);
+	}
+
+	#[test]
+	fn test_tables() {
+		let table = parse_str(
+			r#"
+social = [
+	{ name = "Twitter", url = "https://twitter.com/evan_brass" },
+	# { name = "LinkedIn" }
+	{ name = "GitHub", url = "https://github.com/evan-brass" },
+]
+site.author.name = "Evan"
+
+[extra]
+a = 1
+b.c = 2
+
+[extra.d]
+e = 3
+
+[[menu]]
+name = "About"
+[[menu]]
+name = "Blog"
+[menu.child]
+x = true
+"#,
+		)
+		.unwrap();
+		let social = match &table["social"] {
+			Value::Array(a) => a,
+			v => panic!("{:?}", v),
+		};
+		assert_eq!(social.len(), 2);
+		assert_eq!(
+			social[1],
+			Value::Table(Table::from([
+				("name".to_string(), string("GitHub")),
+				("url".to_string(), string("https://github.com/evan-brass")),
+			]))
+		);
+		let table_of = |pairs: Vec<(&str, Value)>| {
+			Value::Table(pairs.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+		};
+		assert_eq!(
+			table["site"],
+			table_of(vec![("author", table_of(vec![("name", string("Evan"))]))])
+		);
+		assert_eq!(
+			table["extra"],
+			table_of(vec![
+				("a", Value::Integer(1)),
+				("b", table_of(vec![("c", Value::Integer(2))])),
+				("d", table_of(vec![("e", Value::Integer(3))])),
+			])
+		);
+		assert_eq!(
+			table["menu"],
+			Value::Array(vec![
+				table_of(vec![("name", string("About"))]),
+				table_of(vec![
+					("name", string("Blog")),
+					("child", table_of(vec![("x", Value::Boolean(true))])),
+				]),
+			])
+		);
+	}
+
+	#[test]
+	fn test_errors() {
+		for (s, line) in [
+			("a = 1\na = 2", 2),
+			("a = 1\n[a]", 2),
+			("[a]\n[a]", 2),
+			("a = \"unterminated\nb = 1", 1),
+			("a = \"bad \\q escape\"", 1),
+			("a = 01", 1),
+			("a = 1.", 1),
+			("a = 1__0", 1),
+			("a = [1, 2", 1),
+			("a = { b = 1,\n c = 2 }", 1),
+			("a = 1 b = 2", 1),
+			("a =", 1),
+			("= 1", 1),
+			("a = 2020-02-30", 1),
+			("a = yes", 1),
+		] {
+			let e = parse_str(s).expect_err(s);
+			assert!(
+				e.to_string().contains(&format!("\n{}: ", line)),
+				"{}: {}",
+				s,
+				e
+			);
+		}
+	}
+}